@@ -0,0 +1,231 @@
+use serde::{Serialize, Deserialize};
+use chrono::{Utc, DateTime};
+use anyhow::{Result, anyhow};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use zeroize::Zeroize;
+
+use super::{
+    data::{Note, NoteList, LegacyNote, LegacyNoteList, OriginalNoteList, Argon2Params},
+    crypto::{self, EncryptedData, PARAMS_LEN},
+};
+
+// Passphrase-protected container, independent of the vault key and salt.
+// Layout: magic (8) | version (1) | memory_cost | time_cost | parallelism (u32 LE each)
+//         | salt | nonce | tag | ciphertext
+// Since version 2 everything up to and including the salt is authenticated as
+// associated data, so edited KDF parameters fail decryption like a wrong passphrase.
+// Version 1 archives are still read.
+pub const EXPORT_MAGIC: &[u8; 8] = b"NOTASEXP";
+pub const SHARE_MAGIC: &[u8; 8] = b"NOTASSHR";
+const MAGIC_LEN: usize = 8;
+const ARCHIVE_VERSION: u8 = 2;
+const UNAUTHENTICATED_VERSION: u8 = 1;
+
+// ASCII armor so sealed notes can be pasted into chat or email
+const ARMOR_BEGIN: &str = "-----BEGIN NOTAS SHARED NOTE-----";
+const ARMOR_END: &str = "-----END NOTAS SHARED NOTE-----";
+const ARMOR_LINE_LEN: usize = 64;
+
+// Version of the ExportPayload structure inside the ciphertext
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportMetadata {
+    pub exported_at: DateTime<Utc>,
+    pub note_count: usize,
+    pub format_version: u32,
+    pub app_version: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportPayload {
+    pub metadata: ExportMetadata,
+    pub note_list: NoteList,
+}
+
+// A single note handed to someone else, sealed with a one-off passphrase
+#[derive(Serialize, Deserialize)]
+pub struct SharedNote {
+    pub metadata: ExportMetadata,
+    pub note: Note,
+}
+
+impl SharedNote {
+    pub fn new(note: Note) -> Self {
+        Self {
+            metadata: ExportMetadata {
+                exported_at: Utc::now(),
+                note_count: 1,
+                format_version: EXPORT_FORMAT_VERSION,
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            note,
+        }
+    }
+
+    // Notes shared before notes could be locked use the older Note layout
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        #[derive(Deserialize)]
+        struct LegacySharedNote {
+            metadata: ExportMetadata,
+            note: LegacyNote,
+        }
+
+        bincode::deserialize(bytes).or_else(|err| {
+            bincode::deserialize::<LegacySharedNote>(bytes)
+                .map(|legacy| Self { metadata: legacy.metadata, note: legacy.note.into() })
+                .map_err(|_| err)
+        })
+    }
+}
+
+impl Drop for SharedNote {
+    fn drop(&mut self) {
+        self.note.zeroize();
+    }
+}
+
+// Which notes go into an export
+#[derive(Debug, Clone)]
+pub enum ExportScope {
+    All,
+    Folder(String),
+    /// Notes containing this #hashtag
+    Tag(String),
+    Notes(Vec<u64>),
+}
+
+impl ExportScope {
+    pub fn filter(&self, note_list: &NoteList) -> NoteList {
+        let mut scoped = NoteList::new();
+        for note in &note_list.notes {
+            let included = match self {
+                ExportScope::All => true,
+                ExportScope::Folder(folder) => note.folder.as_deref() == Some(folder.as_str()),
+                ExportScope::Tag(tag) => note.has_tag(tag),
+                ExportScope::Notes(ids) => ids.contains(&note.id),
+            };
+            if included {
+                scoped.notes.push(note.clone());
+            }
+        }
+        scoped.folders = match self {
+            ExportScope::All => note_list.folders.clone(),
+            _ => {
+                let mut folders: Vec<String> = scoped.notes.iter().filter_map(|n| n.folder.clone()).collect();
+                folders.sort();
+                folders.dedup();
+                folders
+            },
+        };
+        scoped
+    }
+}
+
+impl ExportPayload {
+    pub fn new(note_list: NoteList) -> Self {
+        Self {
+            metadata: ExportMetadata {
+                exported_at: Utc::now(),
+                note_count: note_list.notes.len(),
+                format_version: EXPORT_FORMAT_VERSION,
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            note_list,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        #[derive(Deserialize)]
+        struct LegacyExportPayload {
+            metadata: ExportMetadata,
+            note_list: LegacyNoteList,
+        }
+        #[derive(Deserialize)]
+        struct OriginalExportPayload {
+            metadata: ExportMetadata,
+            note_list: OriginalNoteList,
+        }
+
+        bincode::deserialize(bytes).or_else(|err| {
+            bincode::deserialize::<LegacyExportPayload>(bytes)
+                .map(|legacy| Self { metadata: legacy.metadata, note_list: legacy.note_list.into() })
+                .or_else(|_| {
+                    bincode::deserialize::<OriginalExportPayload>(bytes)
+                        .map(|original| Self { metadata: original.metadata, note_list: original.note_list.into() })
+                })
+                .map_err(|_| err)
+        })
+    }
+}
+
+pub fn has_magic(bytes: &[u8], magic: &[u8; MAGIC_LEN]) -> bool {
+    bytes.len() >= MAGIC_LEN && &bytes[..MAGIC_LEN] == magic
+}
+
+// Encrypt `plaintext` under a key derived from `passphrase` with a fresh salt
+pub fn seal(magic: &[u8; MAGIC_LEN], passphrase: &[u8], params: &Argon2Params, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let (key, salt) = crypto::generate_test_key_with_params(passphrase, params)?;
+
+    let mut bytes = Vec::with_capacity(MAGIC_LEN + 1 + PARAMS_LEN + plaintext.len() + 64);
+    bytes.extend_from_slice(magic);
+    bytes.push(ARCHIVE_VERSION);
+    crypto::write_params(params, &mut bytes);
+    bytes.extend_from_slice(&salt);
+
+    // EncryptedData starts with the salt again; it has already been written as part of
+    // the authenticated header
+    let encrypted = crypto::encrypt(&key, &salt, plaintext, &bytes)?;
+    bytes.extend_from_slice(&encrypted.to_bytes()[salt.len()..]);
+    Ok(bytes)
+}
+
+// Decrypt a container produced by `seal`, using the KDF params stored in it
+pub fn open(magic: &[u8; MAGIC_LEN], passphrase: &[u8], bytes: &[u8]) -> Result<Vec<u8>> {
+    if !has_magic(bytes, magic) {
+        return Err(anyhow!("Not a Notas archive"));
+    }
+    let rest = &bytes[MAGIC_LEN..];
+    let (&version, rest) = rest.split_first().ok_or_else(|| anyhow!("Archive is truncated"))?;
+    if version != ARCHIVE_VERSION && version != UNAUTHENTICATED_VERSION {
+        return Err(anyhow!("Unsupported archive version {}", version));
+    }
+    let (params_bytes, rest) = rest.split_first_chunk::<PARAMS_LEN>().ok_or_else(|| anyhow!("Archive is truncated"))?;
+    let params = crypto::read_params(params_bytes)
+        .ok_or_else(|| anyhow!("Archive uses unsupported key derivation parameters"))?;
+
+    let encrypted = EncryptedData::from_bytes(rest)?;
+    let header_len = bytes.len() - rest.len() + encrypted.header.salt.len();
+    let aad = if version == ARCHIVE_VERSION { &bytes[..header_len] } else { &[][..] };
+    let key = crypto::derive_key_with_params(passphrase, &encrypted.header.salt, &params)?;
+    crypto::decrypt(&key, &encrypted, aad).map_err(|_| anyhow!("Wrong passphrase or corrupted archive"))
+}
+
+pub fn armor(bytes: &[u8]) -> String {
+    let encoded = BASE64.encode(bytes);
+    let mut text = String::with_capacity(encoded.len() + encoded.len() / ARMOR_LINE_LEN + 80);
+    text.push_str(ARMOR_BEGIN);
+    text.push('\n');
+    for chunk in encoded.as_bytes().chunks(ARMOR_LINE_LEN) {
+        // Base64 output is ASCII, so every chunk is valid UTF-8
+        text.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        text.push('\n');
+    }
+    text.push_str(ARMOR_END);
+    text.push('\n');
+    text
+}
+
+pub fn is_armored(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes)
+        .map(|text| text.contains(ARMOR_BEGIN))
+        .unwrap_or(false)
+}
+
+// Accepts text with surrounding chatter (e.g. a pasted message) around the armor block
+pub fn dearmor(text: &str) -> Result<Vec<u8>> {
+    let start = text.find(ARMOR_BEGIN).ok_or_else(|| anyhow!("No shared note found in text"))? + ARMOR_BEGIN.len();
+    let end = text[start..].find(ARMOR_END).ok_or_else(|| anyhow!("Shared note text is incomplete"))? + start;
+    let encoded: String = text[start..end].chars().filter(|c| !c.is_whitespace()).collect();
+    BASE64.decode(encoded).map_err(|_| anyhow!("Shared note text is corrupted"))
+}
@@ -0,0 +1,252 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::Result;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use hmac::{Hmac, Mac, digest::KeyInit};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use super::crypto;
+
+// Failed unlock attempts are counted in a small file next to the vault, so restarting
+// the app doesn't reset them. The record must be readable while locked, so it can't be
+// encrypted with the vault. Instead it carries an HMAC under a per-install key, and a
+// record that was edited is flagged and reported at the next unlock rather than
+// trusted. The key file also lists the vaults whose record shows failures, so deleting
+// such a record is flagged too. A vault without a record that isn't listed (new,
+// copied in or restored from a backup) starts from zero. Someone who can write the key
+// file can still forge or clear the count; it slows down guessing in the app, nothing
+// more.
+
+// Typos are free; back-off starts after this many failures in a row
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_BACKOFF_SECS: u64 = 15 * 60;
+const KEY_LEN: usize = 32;
+// Vaults are listed in the key file by a SHA-256 digest of their path
+const VAULT_ID_LEN: usize = 32;
+const KEY_FILE: &str = "attempts.key";
+const RECORD_EXTENSION: &str = "attempts";
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AttemptRecord {
+    pub failures: u32,
+    /// Unix time of the last failure
+    pub last_failure: u64,
+    /// Set when a stored record didn't verify
+    pub tampered: bool,
+    #[serde(default)]
+    mac: String,
+}
+
+impl AttemptRecord {
+    /// Seconds until another unlock attempt is allowed (0 = now)
+    pub fn retry_in(&self, now: u64) -> u64 {
+        // A clock set back before the last failure doesn't shorten the wait
+        backoff(self.failures).as_secs().saturating_sub(now.saturating_sub(self.last_failure))
+    }
+
+    fn message(&self) -> Vec<u8> {
+        let mut message = Vec::with_capacity(13);
+        message.extend_from_slice(&self.failures.to_le_bytes());
+        message.extend_from_slice(&self.last_failure.to_le_bytes());
+        message.push(self.tampered as u8);
+        message
+    }
+}
+
+// Delay after `failures` failed attempts: none for the first few, then doubling from
+// one second up to a quarter of an hour
+pub fn backoff(failures: u32) -> Duration {
+    if failures < FREE_ATTEMPTS {
+        return Duration::ZERO;
+    }
+    let exponent = (failures - FREE_ATTEMPTS).min(16);
+    Duration::from_secs((1u64 << exponent).min(MAX_BACKOFF_SECS))
+}
+
+pub struct AttemptLog {
+    record_path: PathBuf,
+    vault_id: [u8; VAULT_ID_LEN],
+    key_path: PathBuf,
+}
+
+impl AttemptLog {
+    pub fn for_vault(vault_path: &Path) -> Self {
+        Self {
+            record_path: vault_path.with_extension(RECORD_EXTENSION),
+            vault_id: vault_id(vault_path),
+            key_path: dirs::config_dir()
+                .unwrap_or_else(|| PathBuf::from("."))
+                .join("notas")
+                .join(KEY_FILE),
+        }
+    }
+
+    // Read the record for a vault that exists on disk
+    pub fn load(&self) -> AttemptRecord {
+        let record = fs::read_to_string(&self.record_path).ok()
+            .map(|contents| serde_json::from_str::<AttemptRecord>(&contents).ok());
+        let key_file = self.read_key_file();
+
+        let mut record = match (record, key_file) {
+            // Removed after failures were recorded
+            (None, Some(mut key_file)) if listed(&key_file, &self.vault_id) => {
+                key_file.zeroize();
+                AttemptRecord { tampered: true, ..AttemptRecord::default() }
+            },
+            // Nothing recorded for this vault yet
+            (None, key_file) => {
+                if let Some(mut key_file) = key_file {
+                    key_file.zeroize();
+                }
+                return AttemptRecord::default();
+            },
+            (Some(Some(record)), Some(mut key_file)) => {
+                let verified = verify(&key_file[..KEY_LEN], &record);
+                key_file.zeroize();
+                if verified {
+                    return record;
+                }
+                AttemptRecord { tampered: true, ..record }
+            },
+            // Can't be verified without the key
+            (Some(Some(record)), None) => AttemptRecord { tampered: true, ..record },
+            // Unreadable
+            (Some(None), _) => AttemptRecord { tampered: true, ..AttemptRecord::default() },
+        };
+        // No free retries while the count can't be trusted
+        record.failures = record.failures.max(FREE_ATTEMPTS);
+        record
+    }
+
+    pub fn record_failure(&self, now: u64) -> Result<AttemptRecord> {
+        let mut record = self.load();
+        record.failures = record.failures.saturating_add(1);
+        record.last_failure = now;
+        self.store(&mut record)?;
+        Ok(record)
+    }
+
+    // Start counting afresh after a successful unlock, returning what was recorded
+    pub fn reset(&self) -> Result<AttemptRecord> {
+        let previous = self.load();
+        self.store(&mut AttemptRecord::default())?;
+        Ok(previous)
+    }
+
+    // Follow the vault to a new location
+    pub fn move_to(&self, vault_path: &Path) -> Result<()> {
+        if self.record_path.exists() {
+            let moved = Self::for_vault(vault_path);
+            fs::rename(&self.record_path, &moved.record_path)?;
+            let mut key_file = self.key_file()?;
+            if listed(&key_file, &self.vault_id) {
+                self.set_listed(&mut key_file, false)?;
+                moved.set_listed(&mut key_file, true)?;
+            }
+            key_file.zeroize();
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.record_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {},
+        }
+        let mut key_file = self.key_file()?;
+        let result = self.set_listed(&mut key_file, false);
+        key_file.zeroize();
+        result
+    }
+
+    fn store(&self, record: &mut AttemptRecord) -> Result<()> {
+        let mut key_file = self.key_file()?;
+        record.mac = BASE64.encode(mac(&key_file[..KEY_LEN], record).finalize().into_bytes());
+        // List the vault before writing a record with failures, so that record can't
+        // simply be deleted
+        let listed = self.set_listed(&mut key_file, record.failures > 0 || record.tampered);
+        key_file.zeroize();
+        listed?;
+        fs::write(&self.record_path, serde_json::to_string_pretty(record)?)?;
+        Ok(())
+    }
+
+    // Add this vault to, or drop it from, the vaults listed in `key_file`
+    fn set_listed(&self, key_file: &mut Vec<u8>, list: bool) -> Result<()> {
+        if listed(key_file, &self.vault_id) == list {
+            return Ok(());
+        }
+        let mut updated = key_file[..KEY_LEN].to_vec();
+        for id in key_file[KEY_LEN..].chunks(VAULT_ID_LEN).filter(|id| *id != self.vault_id) {
+            updated.extend_from_slice(id);
+        }
+        if list {
+            updated.extend_from_slice(&self.vault_id);
+        }
+        key_file.zeroize();
+        *key_file = updated;
+        self.write_key_file(key_file)
+    }
+
+    // The key file: the per-install key followed by the listed vaults
+    fn read_key_file(&self) -> Option<Vec<u8>> {
+        fs::read(&self.key_path).ok()
+            .filter(|bytes| bytes.len() >= KEY_LEN && (bytes.len() - KEY_LEN).is_multiple_of(VAULT_ID_LEN))
+    }
+
+    // The key file, created (readable by the owner only) on first use
+    fn key_file(&self) -> Result<Vec<u8>> {
+        if let Some(key_file) = self.read_key_file() {
+            return Ok(key_file);
+        }
+        if let Some(parent) = self.key_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut key = vec![0u8; KEY_LEN];
+        crypto::fill_random(&mut key);
+        self.write_key_file(&key)?;
+        Ok(key)
+    }
+
+    fn write_key_file(&self, bytes: &[u8]) -> Result<()> {
+        fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.key_path)?
+            .write_all(bytes)?;
+        Ok(())
+    }
+}
+
+fn vault_id(vault_path: &Path) -> [u8; VAULT_ID_LEN] {
+    let path = std::path::absolute(vault_path).unwrap_or_else(|_| vault_path.to_path_buf());
+    let mut digest = Sha256::new();
+    digest.update(b"notas-attempts-vault");
+    digest.update(path.as_os_str().as_encoded_bytes());
+    digest.finalize().into()
+}
+
+fn listed(key_file: &[u8], vault_id: &[u8; VAULT_ID_LEN]) -> bool {
+    key_file[KEY_LEN..].chunks(VAULT_ID_LEN).any(|id| id == vault_id)
+}
+
+fn mac(key: &[u8], record: &AttemptRecord) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(b"notas-attempts");
+    mac.update(&record.message());
+    mac
+}
+
+fn verify(key: &[u8], record: &AttemptRecord) -> bool {
+    match BASE64.decode(&record.mac) {
+        Ok(tag) => mac(key, record).verify_slice(&tag).is_ok(),
+        Err(_) => false,
+    }
+}
@@ -1,7 +1,8 @@
 use aes_gcm::{
-    aead::{AeadInPlace, KeyInit, OsRng},
+    aead::{self, AeadInPlace, KeyInit, OsRng},
     Aes256Gcm, Nonce, Key, Tag,
 };
+use chacha20poly1305::XChaCha20Poly1305;
 use argon2::{
     password_hash::rand_core::RngCore,
     Argon2, Params, Algorithm, Version,
@@ -9,13 +10,19 @@ use argon2::{
 use anyhow::{Result, anyhow};
 use zeroize::Zeroize;
 
-use super::data::Argon2Params;
+use super::data::{Argon2Params, Cipher};
 
 // Constants
 pub const SALT_LEN: usize = 16;
 const NONCE_LEN: usize = 12;
 const TAG_LEN: usize = 16;
 pub const KEY_LEN: usize = 32; // AES-256 key size
+/// Argon2 parameters as stored in vault and archive headers (three u32, little-endian)
+pub const PARAMS_LEN: usize = 12;
+// Bounds on stored parameters, so a crafted file can't exhaust memory or CPU
+const MAX_MEMORY_COST: u32 = 4 * 1024 * 1024; // 4 GiB
+const MAX_TIME_COST: u32 = 64;
+const MAX_PARALLELISM: u32 = 64;
 
 // Header structure for the encrypted file
 #[derive(Debug)]
@@ -66,6 +73,24 @@ impl EncryptedData {
     }
 }
 
+pub fn write_params(params: &Argon2Params, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&params.memory_cost.to_le_bytes());
+    bytes.extend_from_slice(&params.time_cost.to_le_bytes());
+    bytes.extend_from_slice(&params.parallelism.to_le_bytes());
+}
+
+/// Parameters written by `write_params`, or None if they're beyond the bounds above
+pub fn read_params(bytes: &[u8; PARAMS_LEN]) -> Option<Argon2Params> {
+    let read_u32 = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let params = Argon2Params {
+        memory_cost: read_u32(0),
+        time_cost: read_u32(4),
+        parallelism: read_u32(8),
+    };
+    (params.memory_cost <= MAX_MEMORY_COST && params.time_cost <= MAX_TIME_COST && params.parallelism <= MAX_PARALLELISM)
+        .then_some(params)
+}
+
 // Key Derivation Function (KDF) with default params
 pub fn derive_key(password: &[u8], salt: &[u8; SALT_LEN]) -> Result<Key<Aes256Gcm>> {
     derive_key_with_params(password, salt, &Argon2Params::default())
@@ -73,6 +98,12 @@ pub fn derive_key(password: &[u8], salt: &[u8; SALT_LEN]) -> Result<Key<Aes256Gc
 
 // Key Derivation Function (KDF) with custom params
 pub fn derive_key_with_params(password: &[u8], salt: &[u8; SALT_LEN], argon2_params: &Argon2Params) -> Result<Key<Aes256Gcm>> {
+    derive_key_with_secret(password, None, salt, argon2_params)
+}
+
+// KDF keyed with an additional secret (Argon2's optional secret input), used to
+// bind the key to a second factor such as a keyfile
+pub fn derive_key_with_secret(password: &[u8], secret: Option<&[u8]>, salt: &[u8; SALT_LEN], argon2_params: &Argon2Params) -> Result<Key<Aes256Gcm>> {
     let params = Params::new(
         argon2_params.memory_cost,
         argon2_params.time_cost,
@@ -80,7 +111,11 @@ pub fn derive_key_with_params(password: &[u8], salt: &[u8; SALT_LEN], argon2_par
         Some(KEY_LEN),
     ).map_err(|e| anyhow!("Invalid Argon2 parameters: {}", e))?;
     
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let argon2 = match secret {
+        Some(secret) => Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, params)
+            .map_err(|e| anyhow!("Invalid key derivation secret: {}", e))?,
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    };
     let mut key_bytes = [0u8; KEY_LEN];
     
     argon2.hash_password_into(
@@ -95,8 +130,9 @@ pub fn derive_key_with_params(password: &[u8], salt: &[u8; SALT_LEN], argon2_par
     Ok(key)
 }
 
-// Encryption function (salt is passed in, not regenerated)
-pub fn encrypt(key: &Key<Aes256Gcm>, salt: &[u8; SALT_LEN], plaintext: &[u8]) -> Result<EncryptedData> {
+// Encryption function (salt is passed in, not regenerated). `aad` is authenticated
+// but not encrypted, e.g. the file header in front of the ciphertext.
+pub fn encrypt(key: &Key<Aes256Gcm>, salt: &[u8; SALT_LEN], plaintext: &[u8], aad: &[u8]) -> Result<EncryptedData> {
     let cipher = Aes256Gcm::new(key);
 
     let mut nonce_bytes = [0u8; NONCE_LEN];
@@ -104,7 +140,7 @@ pub fn encrypt(key: &Key<Aes256Gcm>, salt: &[u8; SALT_LEN], plaintext: &[u8]) ->
     let nonce = Nonce::from_slice(&nonce_bytes);
 
     let mut buffer = plaintext.to_vec();
-    let tag = cipher.encrypt_in_place_detached(nonce, b"", &mut buffer)
+    let tag = cipher.encrypt_in_place_detached(nonce, aad, &mut buffer)
         .map_err(|e| anyhow!("Encryption failed: {}", e))?;
 
     let mut tag_bytes = [0u8; TAG_LEN];
@@ -116,20 +152,143 @@ pub fn encrypt(key: &Key<Aes256Gcm>, salt: &[u8; SALT_LEN], plaintext: &[u8]) ->
     })
 }
 
-// Decryption function
-pub fn decrypt(key: &Key<Aes256Gcm>, encrypted_data: &EncryptedData) -> Result<Vec<u8>> {
+// Decryption function; `aad` must match what was passed to `encrypt`
+pub fn decrypt(key: &Key<Aes256Gcm>, encrypted_data: &EncryptedData, aad: &[u8]) -> Result<Vec<u8>> {
     let cipher = Aes256Gcm::new(key);
     let nonce = Nonce::from_slice(&encrypted_data.header.nonce);
     let tag = Tag::from_slice(&encrypted_data.header.tag);
 
     let mut buffer = encrypted_data.ciphertext.clone();
     
-    cipher.decrypt_in_place_detached(nonce, b"", &mut buffer, tag)
+    cipher.decrypt_in_place_detached(nonce, aad, &mut buffer, tag)
+        .map_err(|e| anyhow!("Decryption failed: {}", e))?;
+
+    Ok(buffer)
+}
+
+// AES-256-GCM with the nonce and tag carried in front of the ciphertext:
+// nonce | tag | ciphertext. Used where the key is not derived per file (data keys).
+pub fn seal(key: &Key<Aes256Gcm>, plaintext: &[u8]) -> Result<Vec<u8>> {
+    seal_with(Cipher::Aes256Gcm, key, plaintext, b"")
+}
+
+pub fn open(key: &Key<Aes256Gcm>, sealed: &[u8]) -> Result<Vec<u8>> {
+    open_with(Cipher::Aes256Gcm, key, sealed, b"")
+}
+
+// `seal` with a selectable cipher and associated data. XChaCha20-Poly1305 takes a
+// 192-bit nonce, so random nonces stay safe however many times a vault is rewritten
+// under the same data key.
+pub fn seal_with(cipher: Cipher, key: &Key<Aes256Gcm>, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    match cipher {
+        Cipher::Aes256Gcm => seal_aead(&Aes256Gcm::new(key), plaintext, aad),
+        Cipher::XChaCha20Poly1305 => seal_aead(&XChaCha20Poly1305::new(key), plaintext, aad),
+    }
+}
+
+pub fn open_with(cipher: Cipher, key: &Key<Aes256Gcm>, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    match cipher {
+        Cipher::Aes256Gcm => open_aead(&Aes256Gcm::new(key), sealed, aad),
+        Cipher::XChaCha20Poly1305 => open_aead(&XChaCha20Poly1305::new(key), sealed, aad),
+    }
+}
+
+fn seal_aead<A: AeadInPlace>(cipher: &A, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = aead::Nonce::<A>::default();
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut buffer = plaintext.to_vec();
+    let tag = cipher.encrypt_in_place_detached(&nonce, aad, &mut buffer)
+        .map_err(|e| anyhow!("Encryption failed: {}", e))?;
+
+    let mut sealed = Vec::with_capacity(nonce.len() + tag.len() + buffer.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&tag);
+    sealed.extend_from_slice(&buffer);
+    buffer.zeroize();
+    Ok(sealed)
+}
+
+fn open_aead<A: AeadInPlace>(cipher: &A, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce_len = aead::Nonce::<A>::default().len();
+    if sealed.len() < nonce_len + TAG_LEN {
+        return Err(anyhow!("Encrypted data is too short"));
+    }
+    let (nonce_bytes, rest) = sealed.split_at(nonce_len);
+    let (tag_bytes, ciphertext) = rest.split_at(TAG_LEN);
+
+    let mut buffer = ciphertext.to_vec();
+    cipher.decrypt_in_place_detached(aead::Nonce::<A>::from_slice(nonce_bytes), aad, &mut buffer, aead::Tag::<A>::from_slice(tag_bytes))
         .map_err(|e| anyhow!("Decryption failed: {}", e))?;
 
     Ok(buffer)
 }
 
+// Known-answer tests for both ciphers, run before a vault is written with one so a
+// broken build can't produce a file nothing else can read. Vectors: AES-GCM test
+// case 14 from the GCM specification (McGrew & Viega) and the XChaCha20-Poly1305
+// example from draft-irtf-cfrg-xchacha-03, appendix A.3.1.
+pub fn self_test() -> bool {
+    aes_gcm_known_answer() && xchacha20_poly1305_known_answer()
+}
+
+fn known_answer<A: AeadInPlace>(cipher: &A, nonce: &[u8], aad: &[u8], plaintext: &[u8], expected_ct: &[u8], expected_tag: &[u8]) -> bool {
+    let nonce = aead::Nonce::<A>::from_slice(nonce);
+    let mut buffer = plaintext.to_vec();
+    let Ok(tag) = cipher.encrypt_in_place_detached(nonce, aad, &mut buffer) else { return false };
+    if buffer != expected_ct || tag.as_slice() != expected_tag {
+        return false;
+    }
+    cipher.decrypt_in_place_detached(nonce, aad, &mut buffer, &tag).is_ok() && buffer == plaintext
+}
+
+fn aes_gcm_known_answer() -> bool {
+    let key = [0u8; KEY_LEN];
+    known_answer(
+        &Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        &[0u8; NONCE_LEN],
+        b"",
+        &[0u8; 16],
+        &hex("cea7403d4d606b6e074ec5d3baf39d18"),
+        &hex("d0d1c8a799996bf0265b98b5d48ab919"),
+    )
+}
+
+fn xchacha20_poly1305_known_answer() -> bool {
+    let key: Vec<u8> = (0x80..=0x9f).collect();
+    let nonce: Vec<u8> = (0x40..=0x57).collect();
+    known_answer(
+        &XChaCha20Poly1305::new(Key::<Aes256Gcm>::from_slice(&key)),
+        &nonce,
+        &hex("50515253c0c1c2c3c4c5c6c7"),
+        b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.",
+        &hex(concat!(
+            "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb",
+            "731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452",
+            "2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9",
+            "21f9664c97637da9768812f615c68b13b52e",
+        )),
+        &hex("c0875924c1c7987947deafd8780acf49"),
+    )
+}
+
+fn hex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap_or(0))
+        .collect()
+}
+
+// Size of `seal` output for a plaintext of `len` bytes
+pub const fn sealed_len(len: usize) -> usize {
+    NONCE_LEN + TAG_LEN + len
+}
+
+// Fill `buf` from the OS random number generator
+pub fn fill_random(buf: &mut [u8]) {
+    OsRng.fill_bytes(buf);
+}
+
 // Helper to generate a new random salt
 pub fn generate_salt() -> [u8; SALT_LEN] {
     let mut salt = [0u8; SALT_LEN];
@@ -150,3 +309,33 @@ pub fn generate_test_key_with_params(password: &[u8], argon2_params: &Argon2Para
     Ok((key, salt))
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aes_gcm_matches_known_answer() {
+        assert!(aes_gcm_known_answer());
+    }
+
+    #[test]
+    fn xchacha20_poly1305_matches_known_answer() {
+        assert!(xchacha20_poly1305_known_answer());
+    }
+
+    #[test]
+    fn sealed_data_opens_only_with_its_cipher_key_and_aad() {
+        let key = Key::<Aes256Gcm>::from_slice(&[7u8; KEY_LEN]).to_owned();
+        let other = Key::<Aes256Gcm>::from_slice(&[8u8; KEY_LEN]).to_owned();
+        for cipher in Cipher::all_ciphers() {
+            let sealed = seal_with(cipher, &key, b"note", b"header").unwrap();
+            assert_eq!(open_with(cipher, &key, &sealed, b"header").unwrap(), b"note");
+            assert!(open_with(cipher, &key, &sealed, b"headex").is_err());
+            assert!(open_with(cipher, &other, &sealed, b"header").is_err());
+            let mut flipped = sealed.clone();
+            *flipped.last_mut().unwrap() ^= 1;
+            assert!(open_with(cipher, &key, &flipped, b"header").is_err());
+        }
+    }
+}
@@ -1,9 +1,15 @@
 use serde::{Serialize, Deserialize};
 use chrono::{Utc, DateTime};
+use sha2::{Digest, Sha256};
 use zeroize::{Zeroize, ZeroizeOnDrop};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+use super::guarded::SecretString;
+use super::note_lock::NoteLock;
+use super::secrets::mask_secrets;
+use super::strength;
+
 // Monotonic counter used as low bits to prevent ID collisions when notes are
 // created within the same millisecond (e.g. during import).
 pub static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -12,32 +18,106 @@ pub static ID_COUNTER: AtomicU64 = AtomicU64::new(0);
 pub struct Note {
     pub id: u64,
     pub title: String,
-    pub content: String,
+    // Kept in guarded memory (locked, left out of core dumps) while the vault is open
+    pub content: SecretString,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     #[serde(default)]
     pub pinned: bool,
     #[serde(default)]
     pub folder: Option<String>,
+    /// Set while the note is locked with its own passphrase; `content` is empty then
+    #[serde(default)]
+    pub lock: Option<NoteLock>,
+}
+
+// Layout of Note before `lock` was added
+#[derive(Deserialize)]
+pub(crate) struct LegacyNote {
+    id: u64,
+    title: String,
+    content: SecretString,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    pinned: bool,
+    folder: Option<String>,
+}
+
+impl From<LegacyNote> for Note {
+    fn from(legacy: LegacyNote) -> Self {
+        Self {
+            id: legacy.id,
+            title: legacy.title,
+            content: legacy.content,
+            created_at: legacy.created_at,
+            updated_at: legacy.updated_at,
+            pinned: legacy.pinned,
+            folder: legacy.folder,
+            lock: None,
+        }
+    }
 }
 
 impl Note {
     pub fn new(title: String, content: String) -> Self {
         let now = Utc::now();
-        // Combine millisecond timestamp (upper bits) with a monotonic counter
-        // (lower 20 bits) so rapid creation never produces duplicate IDs.
-        let millis = now.timestamp_millis() as u64;
-        let seq = ID_COUNTER.fetch_add(1, Ordering::Relaxed) & 0xF_FFFF;
-        let id = (millis << 20) | seq;
         Self {
-            id,
+            id: Self::next_id(),
             title,
-            content,
+            content: content.into(),
             created_at: now,
             updated_at: now,
             pinned: false,
             folder: None,
+            lock: None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_some()
+    }
+
+    pub fn next_id() -> u64 {
+        // Combine millisecond timestamp (upper bits) with a monotonic counter
+        // (lower 20 bits) so rapid creation never produces duplicate IDs.
+        let millis = Utc::now().timestamp_millis() as u64;
+        let seq = ID_COUNTER.fetch_add(1, Ordering::Relaxed) & 0xF_FFFF;
+        (millis << 20) | seq
+    }
+
+    // Tags are #hashtags written anywhere in the content (Markdown headings and secret
+    // fields excluded)
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for word in mask_secrets(&self.content).split_whitespace() {
+            let Some(tag) = word.strip_prefix('#') else { continue };
+            let tag = tag.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '-' && c != '_');
+            if tag.chars().next().map(char::is_alphanumeric).unwrap_or(false)
+                && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+            {
+                tags.push(tag.to_string());
+            }
+        }
+        tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim_start_matches('#');
+        self.tags().iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    // SHA-256 over title and content, used to recognise identical notes on import.
+    // Locked notes are compared by their sealed content.
+    pub fn content_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.title.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.content.as_bytes());
+        if let Some(ref lock) = self.lock {
+            hasher.update([0u8]);
+            hasher.update(&lock.sealed);
         }
+        hasher.finalize().into()
     }
 }
 
@@ -50,6 +130,9 @@ impl Zeroize for Note {
         if let Some(ref mut f) = self.folder {
             f.zeroize();
         }
+        if let Some(ref mut lock) = self.lock {
+            lock.sealed.zeroize();
+        }
     }
 }
 
@@ -58,6 +141,45 @@ pub struct NoteList {
     pub notes: Vec<Note>,
     #[serde(default)]
     pub folders: Vec<String>,
+    /// age X25519 secret key ("AGE-SECRET-KEY-1...") that notes shared to this vault
+    /// are encrypted to. Created on first use.
+    #[serde(default)]
+    pub identity: Option<String>,
+}
+
+// Layout of NoteList before notes could be locked
+#[derive(Deserialize)]
+pub(crate) struct LegacyNoteList {
+    notes: Vec<LegacyNote>,
+    folders: Vec<String>,
+    identity: Option<String>,
+}
+
+impl From<LegacyNoteList> for NoteList {
+    fn from(legacy: LegacyNoteList) -> Self {
+        Self {
+            notes: legacy.notes.into_iter().map(Note::from).collect(),
+            folders: legacy.folders,
+            identity: legacy.identity,
+        }
+    }
+}
+
+// Layout of NoteList before `identity` was added
+#[derive(Deserialize)]
+pub(crate) struct OriginalNoteList {
+    notes: Vec<LegacyNote>,
+    folders: Vec<String>,
+}
+
+impl From<OriginalNoteList> for NoteList {
+    fn from(original: OriginalNoteList) -> Self {
+        Self {
+            notes: original.notes.into_iter().map(Note::from).collect(),
+            folders: original.folders,
+            identity: None,
+        }
+    }
 }
 
 impl NoteList {
@@ -65,8 +187,20 @@ impl NoteList {
         Self { 
             notes: Vec::new(),
             folders: Vec::new(),
+            identity: None,
         }
     }
+
+    // bincode ignores serde defaults, so vaults written before a trailing field
+    // existed fail to decode as the current layout; fall back to the older ones.
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes).or_else(|err| {
+            bincode::deserialize::<LegacyNoteList>(bytes)
+                .map(Self::from)
+                .or_else(|_| bincode::deserialize::<OriginalNoteList>(bytes).map(Self::from))
+                .map_err(|_| err)
+        })
+    }
     
     fn sort_notes(&mut self) {
         // Sort: pinned first, then by updated_at descending
@@ -106,7 +240,7 @@ impl NoteList {
             note.title.zeroize();
             note.content.zeroize();
             note.title = title;
-            note.content = content;
+            note.content = content.into();
             note.folder = folder;
             note.updated_at = Utc::now();
             self.sort_notes();
@@ -116,6 +250,19 @@ impl NoteList {
         }
     }
     
+    // Replace the note with `id` wholesale (used when an import overwrites it)
+    pub fn replace_note(&mut self, id: u64, mut note: Note) -> bool {
+        if let Some(existing) = self.notes.iter_mut().find(|n| n.id == id) {
+            existing.zeroize();
+            note.id = id;
+            *existing = note;
+            self.sort_notes();
+            true
+        } else {
+            false
+        }
+    }
+    
     pub fn toggle_pin(&mut self, id: u64) -> bool {
         if let Some(note) = self.notes.iter_mut().find(|n| n.id == id) {
             note.pinned = !note.pinned;
@@ -160,6 +307,7 @@ impl Zeroize for NoteList {
         }
         self.notes.clear();
         self.folders.clear();
+        self.identity.zeroize();
     }
 }
 
@@ -310,6 +458,154 @@ impl EditorFont {
     }
 }
 
+// AEAD cipher the vault contents are encrypted with
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Cipher {
+    #[default]
+    Aes256Gcm,
+    XChaCha20Poly1305,
+}
+
+impl Cipher {
+    // Identifier stored in the vault header
+    pub fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::XChaCha20Poly1305 => 2,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Cipher::Aes256Gcm),
+            2 => Some(Cipher::XChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "AES-256-GCM",
+            Cipher::XChaCha20Poly1305 => "XChaCha20-Poly1305",
+        }
+    }
+
+    pub fn all_ciphers() -> Vec<Cipher> {
+        vec![Cipher::Aes256Gcm, Cipher::XChaCha20Poly1305]
+    }
+
+    pub fn from_index(idx: u32) -> Self {
+        Self::all_ciphers().get(idx as usize).cloned().unwrap_or_default()
+    }
+
+    pub fn to_index(&self) -> u32 {
+        Self::all_ciphers().iter().position(|c| c == self).unwrap_or(0) as u32
+    }
+}
+
+// Where the vault key is kept between Notas processes in a login session (see key_cache)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum KeyCacheBackend {
+    #[default]
+    Off,
+    KernelKeyring,
+    SecretService,
+}
+
+impl KeyCacheBackend {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            KeyCacheBackend::Off => "Off",
+            KeyCacheBackend::KernelKeyring => "Kernel keyring",
+            KeyCacheBackend::SecretService => "Secret Service",
+        }
+    }
+
+    pub fn all_backends() -> Vec<KeyCacheBackend> {
+        vec![KeyCacheBackend::Off, KeyCacheBackend::KernelKeyring, KeyCacheBackend::SecretService]
+    }
+
+    pub fn from_index(idx: u32) -> Self {
+        Self::all_backends().get(idx as usize).cloned().unwrap_or_default()
+    }
+
+    pub fn to_index(&self) -> u32 {
+        Self::all_backends().iter().position(|b| b == self).unwrap_or(0) as u32
+    }
+}
+
+// Minimum a new master password has to meet
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Lowest strength score accepted (0-4, see `strength::estimate`)
+    pub min_score: u8,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: 8, min_score: 2 }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn check(&self, password: &str) -> anyhow::Result<()> {
+        if password.chars().count() < self.min_length {
+            return Err(anyhow::anyhow!("Min {} characters", self.min_length));
+        }
+        let strength = strength::estimate(password);
+        if strength.score < self.min_score {
+            return Err(anyhow::anyhow!(
+                "Password is too weak ({}); {} or better is required",
+                strength.label().to_lowercase(),
+                strength::score_label(self.min_score).to_lowercase()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Desktop events that lock the vault, besides the inactivity timeout
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct LockTriggers {
+    /// The desktop session is locked (logind `Lock`)
+    pub session_lock: bool,
+    /// The machine is about to suspend or hibernate (logind `PrepareForSleep`)
+    pub suspend: bool,
+    /// The screensaver starts or the screen blanks (`ActiveChanged`)
+    pub screensaver: bool,
+    /// Seconds no Notas window has had focus before locking (0 = never)
+    pub unfocused_after: u64,
+    /// Seconds the main window has been minimized before locking (0 = never)
+    pub minimized_after: u64,
+    /// Blur the notes while no Notas window has focus
+    pub privacy_screen: bool,
+}
+
+impl Default for LockTriggers {
+    fn default() -> Self {
+        Self {
+            session_lock: true,
+            suspend: true,
+            screensaver: true,
+            unfocused_after: 0,
+            minimized_after: 0,
+            privacy_screen: false,
+        }
+    }
+}
+
+// A vault file the app knows about, listed in the vault picker
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct VaultEntry {
+    pub name: String,
+    pub path: PathBuf,
+    /// Last successful unlock
+    #[serde(default)]
+    pub last_opened: Option<DateTime<Utc>>,
+}
+
 // Application settings/preferences
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppSettings {
@@ -334,6 +630,29 @@ pub struct AppSettings {
     /// Show note title field
     #[serde(default = "default_true")]
     pub show_note_title: bool,
+    /// Cipher for new vaults; an existing vault records its own in the header
+    #[serde(default)]
+    pub cipher: Cipher,
+    /// Erase the vault after this many failed unlocks in a row (0 = never)
+    #[serde(default)]
+    pub wipe_after_failures: u32,
+    /// Required of new master passwords
+    #[serde(default)]
+    pub password_policy: PasswordPolicy,
+    /// Known vaults; `custom_db_path` (or the default path) is the one in use
+    #[serde(default)]
+    pub vaults: Vec<VaultEntry>,
+    #[serde(default)]
+    pub lock_triggers: LockTriggers,
+    /// Minutes after a password unlock during which a PIN can reopen the vault (0 = off)
+    #[serde(default)]
+    pub quick_unlock_minutes: u64,
+    /// Share an unlock with other Notas processes for the rest of the login session
+    #[serde(default)]
+    pub key_cache: KeyCacheBackend,
+    /// Minutes a shared unlock lasts
+    #[serde(default = "default_key_cache_minutes")]
+    pub key_cache_minutes: u64,
 }
 
 fn default_true() -> bool {
@@ -344,6 +663,10 @@ fn default_font_size() -> u32 {
     12
 }
 
+fn default_key_cache_minutes() -> u64 {
+    60
+}
+
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
@@ -355,6 +678,14 @@ impl Default for AppSettings {
             editor_font: EditorFont::default(),
             editor_font_size: 12,
             show_note_title: true,
+            cipher: Cipher::default(),
+            wipe_after_failures: 0,
+            password_policy: PasswordPolicy::default(),
+            vaults: Vec::new(),
+            lock_triggers: LockTriggers::default(),
+            quick_unlock_minutes: 0,
+            key_cache: KeyCacheBackend::Off,
+            key_cache_minutes: default_key_cache_minutes(),
         }
     }
 }
@@ -0,0 +1,438 @@
+use std::fmt;
+use std::io;
+use std::ops::{Deref, Range};
+use std::ptr::NonNull;
+use std::sync::{Mutex, PoisonError};
+use aes_gcm::{Aes256Gcm, Key};
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use zeroize::Zeroize;
+
+use super::crypto;
+
+// Memory for secrets: each allocation gets its own mapping with an inaccessible page
+// on either side (an overrun faults instead of reading a neighbour), is locked into RAM
+// where the memlock limit allows, is left out of core dumps and forked children, and is
+// zeroed before it is unmapped. The data sits at the end of its pages so that running
+// off the end hits the guard page straight away.
+//
+// Every mapping counts against the kernel's per-process limit (vm.max_map_count), so
+// this is for the handful of keys and buffers an open vault needs. Note contents, of
+// which there can be thousands, share larger guarded chunks instead (see SecretString).
+
+pub struct GuardedBytes {
+    region: Option<Region>,
+    len: usize,
+}
+
+struct Region {
+    base: NonNull<u8>,
+    // Whole mapping, guard pages included
+    size: usize,
+    page: usize,
+    locked: bool,
+}
+
+// The mapping is owned exclusively, like a Box
+unsafe impl Send for GuardedBytes {}
+unsafe impl Sync for GuardedBytes {}
+
+fn page_size() -> usize {
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page > 0 { page as usize } else { 4096 }
+}
+
+impl GuardedBytes {
+    /// Zeroed guarded memory of `len` bytes
+    pub fn new(len: usize) -> Result<Self> {
+        if len == 0 {
+            return Ok(Self { region: None, len });
+        }
+        let page = page_size();
+        let data_size = len.div_ceil(page) * page;
+        let size = data_size + 2 * page;
+        unsafe {
+            let base = libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            if base == libc::MAP_FAILED {
+                return Err(anyhow!("Cannot allocate {} bytes of guarded memory: {}", len, io::Error::last_os_error()));
+            }
+            let base = base as *mut u8;
+            let data = base.add(page) as *mut libc::c_void;
+            libc::mprotect(base as *mut libc::c_void, page, libc::PROT_NONE);
+            libc::mprotect(base.add(page + data_size) as *mut libc::c_void, page, libc::PROT_NONE);
+            libc::madvise(data, data_size, libc::MADV_DONTDUMP);
+            libc::madvise(data, data_size, libc::MADV_WIPEONFORK);
+            // Best effort: the default memlock limit is small, and an unlocked page
+            // is still kept out of core dumps
+            let locked = libc::mlock(data, data_size) == 0;
+            Ok(Self {
+                region: Some(Region { base: NonNull::new_unchecked(base), size, page, locked }),
+                len,
+            })
+        }
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let mut guarded = Self::new(bytes.len())?;
+        guarded.as_mut_slice().copy_from_slice(bytes);
+        Ok(guarded)
+    }
+
+    pub fn try_clone(&self) -> Result<Self> {
+        Self::from_slice(self.as_slice())
+    }
+
+    fn data(&self) -> *mut u8 {
+        match &self.region {
+            Some(region) => unsafe { region.base.as_ptr().add(region.size - region.page - self.len) },
+            None => NonNull::dangling().as_ptr(),
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.data(), self.len) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for GuardedBytes {
+    fn drop(&mut self) {
+        self.as_mut_slice().zeroize();
+        if let Some(region) = self.region.take() {
+            unsafe {
+                let data = region.base.as_ptr().add(region.page) as *mut libc::c_void;
+                if region.locked {
+                    libc::munlock(data, region.size - 2 * region.page);
+                }
+                libc::munmap(region.base.as_ptr() as *mut libc::c_void, region.size);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for GuardedBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GuardedBytes({} bytes)", self.len)
+    }
+}
+
+/// A 256-bit key in guarded memory
+pub struct GuardedKey(GuardedBytes);
+
+impl GuardedKey {
+    pub fn random() -> Result<Self> {
+        let mut bytes = GuardedBytes::new(32)?;
+        crypto::fill_random(bytes.as_mut_slice());
+        Ok(Self(bytes))
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 32 {
+            return Err(anyhow!("Keys are 32 bytes"));
+        }
+        Ok(Self(GuardedBytes::from_slice(bytes)?))
+    }
+
+    // Takes the key by value and wipes that copy once it's moved into guarded memory
+    pub fn from_key(mut key: Key<Aes256Gcm>) -> Result<Self> {
+        let guarded = GuardedBytes::from_slice(&key).map(Self);
+        key.as_mut_slice().zeroize();
+        guarded
+    }
+
+    pub fn try_clone(&self) -> Result<Self> {
+        self.0.try_clone().map(Self)
+    }
+
+    pub fn key(&self) -> &Key<Aes256Gcm> {
+        Key::<Aes256Gcm>::from_slice(self.0.as_slice())
+    }
+}
+
+// Note contents are packed into shared chunks of guarded memory, each with guard pages
+// around it, so a vault of thousands of notes needs a few mappings rather than one per
+// note. Allocations inside a chunk sit next to each other without guard pages between
+// them. A freed allocation is zeroed right away, and a chunk is unmapped once empty.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+static ARENA: Mutex<Vec<Chunk>> = Mutex::new(Vec::new());
+
+struct Chunk {
+    memory: GuardedBytes,
+    base: NonNull<u8>,
+    // Free byte ranges, sorted and coalesced
+    free: Vec<Range<usize>>,
+}
+
+// Only reached through the arena's mutex
+unsafe impl Send for Chunk {}
+
+impl Chunk {
+    fn new(size: usize) -> Result<Self> {
+        let mut memory = GuardedBytes::new(size)?;
+        let base = NonNull::new(memory.as_mut_slice().as_mut_ptr()).ok_or_else(|| anyhow!("Empty chunk"))?;
+        let free = std::iter::once(0..size).collect();
+        Ok(Self { memory, base, free })
+    }
+
+    fn contains(&self, ptr: *const u8) -> bool {
+        let start = self.base.as_ptr() as usize;
+        (start..start + self.memory.len()).contains(&(ptr as usize))
+    }
+
+    // First fit
+    fn take(&mut self, len: usize) -> Option<NonNull<u8>> {
+        let index = self.free.iter().position(|range| range.len() >= len)?;
+        let offset = self.free[index].start;
+        self.free[index].start += len;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+        Some(unsafe { NonNull::new_unchecked(self.base.as_ptr().add(offset)) })
+    }
+
+    fn give_back(&mut self, ptr: NonNull<u8>, len: usize) {
+        let start = ptr.as_ptr() as usize - self.base.as_ptr() as usize;
+        let index = self.free.partition_point(|range| range.start < start);
+        self.free.insert(index, start..start + len);
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            let next = self.free.remove(index + 1);
+            self.free[index].end = next.end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            let this = self.free.remove(index);
+            self.free[index - 1].end = this.end;
+        }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.free.len() == 1 && self.free[0].len() == self.memory.len()
+    }
+}
+
+// An allocation in the arena, owned like a Box
+struct ArenaBytes {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+unsafe impl Send for ArenaBytes {}
+unsafe impl Sync for ArenaBytes {}
+
+impl ArenaBytes {
+    // Like `Vec`, running out of memory panics; with chunks this size that takes far
+    // more note text than any vault holds
+    fn from_slice(bytes: &[u8]) -> Self {
+        let mut chunks = ARENA.lock().unwrap_or_else(PoisonError::into_inner);
+        let ptr = match chunks.iter_mut().find_map(|chunk| chunk.take(bytes.len())) {
+            Some(ptr) => ptr,
+            None => {
+                let mut chunk = Chunk::new(bytes.len().max(CHUNK_SIZE))
+                    .unwrap_or_else(|e| panic!("Out of guarded memory for note contents: {}", e));
+                let ptr = chunk.take(bytes.len()).expect("A new chunk fits the allocation");
+                chunks.push(chunk);
+                ptr
+            },
+        };
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.as_ptr(), bytes.len()) };
+        Self { ptr, len: bytes.len() }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for ArenaBytes {
+    fn drop(&mut self) {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }.zeroize();
+        let mut chunks = ARENA.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(index) = chunks.iter().position(|chunk| chunk.contains(self.ptr.as_ptr())) {
+            chunks[index].give_back(self.ptr, self.len);
+            if chunks[index].is_unused() {
+                chunks.swap_remove(index);
+            }
+        }
+    }
+}
+
+/// Text kept in guarded memory, used for note content. Titles are plain `String`s: they
+/// are shown in the note list and window title anyway, so they aren't guarded.
+#[derive(Default)]
+pub struct SecretString(Option<ArenaBytes>);
+
+impl SecretString {
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // Only ever built from a str
+            Some(bytes) => unsafe { std::str::from_utf8_unchecked(bytes.as_slice()) },
+            None => "",
+        }
+    }
+}
+
+impl Clone for SecretString {
+    fn clone(&self) -> Self {
+        Self::from(self.as_str())
+    }
+}
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> Self {
+        Self((!text.is_empty()).then(|| ArenaBytes::from_slice(text.as_bytes())))
+    }
+}
+
+// Copies the text into guarded memory and wipes the String it came from
+impl From<String> for SecretString {
+    fn from(mut text: String) -> Self {
+        let secret = Self::from(text.as_str());
+        text.zeroize();
+        secret
+    }
+}
+
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialEq<str> for SecretString {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SecretString {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString({} bytes)", self.len())
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Zeroize for SecretString {
+    fn zeroize(&mut self) {
+        // Dropping the allocation zeroes it
+        self.0 = None;
+    }
+}
+
+// Serialized exactly like a String, so vault and export formats are unchanged
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = SecretString;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, text: &str) -> Result<SecretString, E> {
+                Ok(SecretString::from(text))
+            }
+
+            fn visit_string<E: serde::de::Error>(self, text: String) -> Result<SecretString, E> {
+                Ok(SecretString::from(text))
+            }
+        }
+
+        deserializer.deserialize_string(Visitor)
+    }
+}
+
+/// Keep secrets out of core dumps and away from other processes of the same user:
+/// no core files, and no ptrace attach or /proc/<pid>/mem reads
+pub fn harden_process() {
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+        let no_core = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+        libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn many_strings_share_a_chunk() {
+        let notes: Vec<SecretString> = (0..2000).map(|i| SecretString::from(format!("note {}", i))).collect();
+        let chunks = ARENA.lock().unwrap();
+        let used = chunks.iter()
+            .filter(|chunk| notes.iter().any(|note| chunk.contains(note.as_str().as_ptr())))
+            .count();
+        assert!(used <= 2, "{} chunks for 2000 short notes", used);
+        drop(chunks);
+        assert!(notes.iter().enumerate().all(|(i, note)| note.as_str() == format!("note {}", i)));
+    }
+
+    #[test]
+    fn freed_ranges_are_reused_and_coalesced() {
+        let mut chunk = Chunk::new(4096).unwrap();
+        let a = chunk.take(100).unwrap();
+        let b = chunk.take(200).unwrap();
+        let c = chunk.take(300).unwrap();
+        chunk.give_back(b, 200);
+        assert_eq!(chunk.take(150), Some(b));
+        chunk.give_back(b, 150);
+        chunk.give_back(a, 100);
+        chunk.give_back(c, 300);
+        assert!(chunk.is_unused());
+        assert!(chunk.take(4097).is_none());
+        assert_eq!(chunk.take(4096), Some(a));
+    }
+
+    #[test]
+    fn large_text_gets_its_own_chunk() {
+        let text = "x".repeat(CHUNK_SIZE + 1);
+        let secret = SecretString::from(text.as_str());
+        assert_eq!(secret.as_str(), text);
+        assert_eq!(secret.clone().as_str(), text);
+    }
+}
@@ -0,0 +1,872 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde_json::Value;
+use zeroize::Zeroize;
+
+use super::{archive::ExportMetadata, data::Note};
+
+// Supported third-party import sources
+#[derive(Debug, Clone)]
+pub enum ImportSource {
+    /// Directory of .md / .txt files, optionally with YAML-style front matter
+    MarkdownFolder(PathBuf),
+    /// Standard Notes decrypted backup (.txt / .json)
+    StandardNotes(PathBuf),
+    /// Simplenote export (notes.json)
+    Simplenote(PathBuf),
+    /// Google Keep Takeout folder (one .json per note) or a single note file
+    GoogleKeep(PathBuf),
+    /// Joplin "RAW - Joplin Export Directory"
+    Joplin(PathBuf),
+}
+
+impl ImportSource {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ImportSource::MarkdownFolder(_) => "Markdown / text folder",
+            ImportSource::StandardNotes(_) => "Standard Notes backup",
+            ImportSource::Simplenote(_) => "Simplenote export",
+            ImportSource::GoogleKeep(_) => "Google Keep Takeout",
+            ImportSource::Joplin(_) => "Joplin raw export",
+        }
+    }
+}
+
+// Notes parsed from a source, before they are merged into the vault
+#[derive(Debug, Default)]
+pub struct ImportedNotes {
+    pub notes: Vec<Note>,
+    pub folders: Vec<String>,
+    /// Entries that were recognised but not importable (trashed, empty, unreadable)
+    pub skipped: usize,
+}
+
+impl ImportedNotes {
+    fn push(&mut self, note: Note) {
+        if let Some(ref folder) = note.folder {
+            if !self.folders.contains(folder) {
+                self.folders.push(folder.clone());
+            }
+        }
+        self.notes.push(note);
+    }
+}
+
+// Result of merging an import into the vault
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+    pub duplicates: usize,
+}
+
+impl std::fmt::Display for ImportSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} imported, {} skipped, {} duplicates", self.imported, self.skipped, self.duplicates)
+    }
+}
+
+// How an incoming note relates to what is already in the vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStatus {
+    /// No note with this ID or content exists
+    New,
+    /// Same content already exists (same ID or not)
+    Identical,
+    /// Same ID, incoming copy was modified more recently
+    Newer,
+    /// Same ID, vault copy was modified more recently
+    Older,
+    /// Same ID and modification time but different content
+    Conflicting,
+}
+
+impl ImportStatus {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ImportStatus::New => "New",
+            ImportStatus::Identical => "Identical",
+            ImportStatus::Newer => "Newer",
+            ImportStatus::Older => "Older",
+            ImportStatus::Conflicting => "Conflict",
+        }
+    }
+
+    // Choice that respects updated_at and never loses data
+    pub fn default_action(&self) -> ImportAction {
+        match self {
+            ImportStatus::New => ImportAction::KeepBoth,
+            ImportStatus::Identical => ImportAction::Skip,
+            ImportStatus::Newer => ImportAction::Overwrite,
+            ImportStatus::Older => ImportAction::Skip,
+            ImportStatus::Conflicting => ImportAction::KeepBoth,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportAction {
+    Skip,
+    /// Add the incoming note, giving it a fresh ID if it collides
+    KeepBoth,
+    /// Replace the vault note with the same ID (falls back to KeepBoth for new notes)
+    Overwrite,
+}
+
+impl ImportAction {
+    pub fn all() -> [ImportAction; 3] {
+        [ImportAction::Skip, ImportAction::KeepBoth, ImportAction::Overwrite]
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            ImportAction::Skip => "Skip",
+            ImportAction::KeepBoth => "Keep both",
+            ImportAction::Overwrite => "Overwrite",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ImportEntry {
+    pub note: Note,
+    pub status: ImportStatus,
+    /// ID of the vault note this entry matched, if any
+    pub existing_id: Option<u64>,
+}
+
+// Incoming notes classified against the vault, awaiting per-note choices
+#[derive(Debug, Default)]
+pub struct ImportPreview {
+    pub entries: Vec<ImportEntry>,
+    pub folders: Vec<String>,
+    pub skipped: usize,
+    /// Present when importing an export archive
+    pub metadata: Option<ExportMetadata>,
+}
+
+impl ImportPreview {
+    pub fn new(imported: ImportedNotes, existing: &[Note]) -> Self {
+        let existing_hashes: Vec<[u8; 32]> = existing.iter().map(Note::content_hash).collect();
+        let mut seen_hashes: Vec<[u8; 32]> = Vec::new();
+        let ImportedNotes { notes, folders, skipped } = imported;
+
+        let entries = notes.into_iter().map(|note| {
+            let hash = note.content_hash();
+            let by_id = existing.iter().position(|n| n.id == note.id);
+            let (status, existing_id) = match by_id {
+                Some(idx) => {
+                    let current = &existing[idx];
+                    let status = if existing_hashes[idx] == hash {
+                        ImportStatus::Identical
+                    } else if note.updated_at > current.updated_at {
+                        ImportStatus::Newer
+                    } else if note.updated_at < current.updated_at {
+                        ImportStatus::Older
+                    } else {
+                        ImportStatus::Conflicting
+                    };
+                    (status, Some(current.id))
+                },
+                None => match existing_hashes.iter().position(|h| *h == hash) {
+                    Some(idx) => (ImportStatus::Identical, Some(existing[idx].id)),
+                    // Repeats within the same import are identical to the first copy
+                    None if seen_hashes.contains(&hash) => (ImportStatus::Identical, None),
+                    None => (ImportStatus::New, None),
+                },
+            };
+            seen_hashes.push(hash);
+            ImportEntry { note, status, existing_id }
+        }).collect();
+
+        Self { entries, folders, skipped, metadata: None }
+    }
+
+    pub fn default_actions(&self) -> Vec<ImportAction> {
+        self.entries.iter().map(|e| e.status.default_action()).collect()
+    }
+}
+
+impl Drop for ImportPreview {
+    fn drop(&mut self) {
+        for entry in &mut self.entries {
+            entry.note.zeroize();
+        }
+    }
+}
+
+pub fn read_source(source: &ImportSource) -> Result<ImportedNotes> {
+    match source {
+        ImportSource::MarkdownFolder(path) => read_markdown_folder(path),
+        ImportSource::StandardNotes(path) => read_standard_notes(path),
+        ImportSource::Simplenote(path) => read_simplenote(path),
+        ImportSource::GoogleKeep(path) => read_google_keep(path),
+        ImportSource::Joplin(path) => read_joplin(path),
+    }
+}
+
+// Build a note with explicit metadata, keeping the collision-free ID scheme of Note::new
+fn build_note(
+    title: String,
+    content: String,
+    folder: Option<String>,
+    tags: &[String],
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    pinned: bool,
+) -> Note {
+    let title = if title.trim().is_empty() {
+        content.lines().find(|l| !l.trim().is_empty()).unwrap_or("Untitled").trim().chars().take(80).collect()
+    } else {
+        title.trim().to_string()
+    };
+    let mut note = Note::new(title, append_tags(content, tags));
+    if let Some(created) = created_at {
+        note.created_at = created;
+        note.updated_at = created;
+    }
+    if let Some(updated) = updated_at {
+        note.updated_at = updated;
+    }
+    note.pinned = pinned;
+    note.folder = folder.filter(|f| !f.trim().is_empty());
+    note
+}
+
+// Notas has no separate tag field, so imported tags become a trailing #hashtag line
+fn append_tags(content: String, tags: &[String]) -> String {
+    let hashtags: Vec<String> = tags.iter()
+        .map(|t| t.trim().trim_start_matches('#').replace(char::is_whitespace, "-"))
+        .filter(|t| !t.is_empty())
+        .map(|t| format!("#{}", t))
+        .filter(|t| !content.contains(t.as_str()))
+        .collect();
+    if hashtags.is_empty() {
+        return content;
+    }
+    let mut content = content.trim_end().to_string();
+    if !content.is_empty() {
+        content.push_str("\n\n");
+    }
+    content.push_str(&hashtags.join(" "));
+    content
+}
+
+// Parse the timestamp formats used by the supported exporters
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().trim_matches('"').trim_matches('\'');
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, fmt) {
+            return Some(Utc.from_utc_datetime(&naive));
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|naive| Utc.from_utc_datetime(&naive));
+    }
+    None
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| anyhow!("{} is not valid JSON: {}", path.display(), e))
+}
+
+// Recursively collect files below `dir` whose extension is in `extensions`.
+// Symlinked directories are not followed, so a link back up the tree can't loop.
+fn collect_files(dir: &Path, extensions: &[&str], out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            // Skip hidden directories such as .obsidian or .git
+            let hidden = path.file_name()
+                .and_then(|n| n.to_str())
+                .map(|n| n.starts_with('.'))
+                .unwrap_or(false);
+            if !hidden {
+                collect_files(&path, extensions, out)?;
+            }
+        } else if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            if extensions.iter().any(|x| x.eq_ignore_ascii_case(ext)) {
+                out.push(path);
+            }
+        }
+    }
+    out.sort();
+    Ok(())
+}
+
+// ---------- Markdown / plain text folders ----------
+
+#[derive(Default)]
+struct FrontMatter {
+    title: Option<String>,
+    tags: Vec<String>,
+    created: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    pinned: bool,
+    folder: Option<String>,
+}
+
+// Split a leading `---` delimited front matter block off the body. Only the flat
+// `key: value` / `key: [a, b]` / `- item` subset of YAML is understood.
+fn split_front_matter(text: &str) -> (Option<FrontMatter>, &str) {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let Some(rest) = text.strip_prefix("---\n").or_else(|| text.strip_prefix("---\r\n")) else {
+        return (None, text);
+    };
+    let mut offset = 0;
+    let mut end = None;
+    for line in rest.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." {
+            end = Some((offset, offset + line.len()));
+            break;
+        }
+        offset += line.len();
+    }
+    let Some((block_end, body_start)) = end else {
+        return (None, text);
+    };
+
+    let mut fm = FrontMatter::default();
+    let mut list_key: Option<String> = None;
+    for line in rest[..block_end].lines() {
+        let trimmed = line.trim();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if matches!(list_key.as_deref(), Some("tags") | Some("keywords")) {
+                fm.tags.push(unquote(item));
+            }
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let value = value.trim();
+        list_key = Some(key.clone());
+        match key.as_str() {
+            "title" => fm.title = Some(unquote(value)),
+            "tags" | "keywords" => {
+                let inner = value.trim_start_matches('[').trim_end_matches(']');
+                fm.tags.extend(inner.split(',').map(unquote).filter(|t| !t.is_empty()));
+            },
+            "date" | "created" | "created_at" => fm.created = parse_timestamp(value),
+            "updated" | "modified" | "updated_at" | "lastmod" => fm.updated = parse_timestamp(value),
+            "pinned" => fm.pinned = value.eq_ignore_ascii_case("true") || value == "yes",
+            "folder" | "notebook" | "category" => fm.folder = Some(unquote(value)),
+            _ => {},
+        }
+    }
+    (Some(fm), &rest[body_start..])
+}
+
+fn unquote(value: &str) -> String {
+    value.trim().trim_matches('"').trim_matches('\'').trim().to_string()
+}
+
+fn read_markdown_folder(dir: &Path) -> Result<ImportedNotes> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a folder", dir.display()));
+    }
+    let mut files = Vec::new();
+    collect_files(dir, &["md", "markdown", "txt"], &mut files)?;
+
+    let mut imported = ImportedNotes::default();
+    for path in files {
+        let Ok(text) = fs::read_to_string(&path) else {
+            imported.skipped += 1;
+            continue;
+        };
+        let (front_matter, body) = split_front_matter(&text);
+        let fm = front_matter.unwrap_or_default();
+        if body.trim().is_empty() && fm.title.is_none() {
+            imported.skipped += 1;
+            continue;
+        }
+
+        // Sub-directories map to folders unless the front matter names one
+        let folder = fm.folder.or_else(|| {
+            path.parent()
+                .and_then(|p| p.strip_prefix(dir).ok())
+                .filter(|rel| !rel.as_os_str().is_empty())
+                .map(|rel| rel.to_string_lossy().to_string())
+        });
+
+        // Title: front matter, then the file name; a leading "# Title" heading
+        // that repeats it is dropped from the body
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let title = fm.title.unwrap_or(stem);
+        let body = body.trim_start_matches(['\r', '\n']);
+        let body = match body.split_once('\n') {
+            Some((first, rest)) if first.trim_start_matches('#').trim() == title && first.starts_with('#') => rest,
+            None if body.trim_start_matches('#').trim() == title && body.starts_with('#') => "",
+            _ => body,
+        };
+
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from);
+        let note = build_note(
+            title,
+            body.trim().to_string(),
+            folder,
+            &fm.tags,
+            fm.created.or(modified),
+            fm.updated.or(modified),
+            fm.pinned,
+        );
+        imported.push(note);
+    }
+    Ok(imported)
+}
+
+// ---------- Standard Notes ----------
+
+fn read_standard_notes(path: &Path) -> Result<ImportedNotes> {
+    let root = read_json(path)?;
+    let items = root.get("items").and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("Not a Standard Notes backup (missing \"items\")"))?;
+
+    // Tags reference their notes, so resolve note uuid -> tag titles first
+    let mut note_tags: std::collections::HashMap<&str, Vec<String>> = std::collections::HashMap::new();
+    for item in items {
+        if item.get("content_type").and_then(Value::as_str) != Some("Tag") {
+            continue;
+        }
+        let Some(content) = item.get("content") else { continue };
+        let Some(tag) = content.get("title").and_then(Value::as_str) else { continue };
+        for reference in content.get("references").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(uuid) = reference.get("uuid").and_then(Value::as_str) {
+                note_tags.entry(uuid).or_default().push(tag.to_string());
+            }
+        }
+    }
+
+    let mut imported = ImportedNotes::default();
+    for item in items {
+        if item.get("content_type").and_then(Value::as_str) != Some("Note") {
+            continue;
+        }
+        // Encrypted backups carry a string here instead of an object
+        let Some(content) = item.get("content").filter(|c| c.is_object()) else {
+            imported.skipped += 1;
+            continue;
+        };
+        let trashed = content.get("trashed").and_then(Value::as_bool).unwrap_or(false);
+        let deleted = item.get("deleted").and_then(Value::as_bool).unwrap_or(false);
+        if trashed || deleted {
+            imported.skipped += 1;
+            continue;
+        }
+        let title = content.get("title").and_then(Value::as_str).unwrap_or("").to_string();
+        let text = content.get("text").and_then(Value::as_str).unwrap_or("").to_string();
+        if title.trim().is_empty() && text.trim().is_empty() {
+            imported.skipped += 1;
+            continue;
+        }
+        let pinned = content.get("pinned").and_then(Value::as_bool)
+            .or_else(|| content.pointer("/appData/org.standardnotes.sn/pinned").and_then(Value::as_bool))
+            .unwrap_or(false);
+        let tags = item.get("uuid").and_then(Value::as_str)
+            .and_then(|uuid| note_tags.get(uuid))
+            .cloned()
+            .unwrap_or_default();
+        let created = item.get("created_at").and_then(Value::as_str).and_then(parse_timestamp);
+        let updated = item.get("updated_at").and_then(Value::as_str).and_then(parse_timestamp);
+        imported.push(build_note(title, text, None, &tags, created, updated, pinned));
+    }
+    Ok(imported)
+}
+
+// ---------- Simplenote ----------
+
+fn read_simplenote(path: &Path) -> Result<ImportedNotes> {
+    let root = read_json(path)?;
+    let active = root.get("activeNotes").and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("Not a Simplenote export (missing \"activeNotes\")"))?;
+
+    let mut imported = ImportedNotes::default();
+    imported.skipped += root.get("trashedNotes").and_then(Value::as_array).map(|t| t.len()).unwrap_or(0);
+
+    for item in active {
+        let content = item.get("content").and_then(Value::as_str).unwrap_or("");
+        if content.trim().is_empty() {
+            imported.skipped += 1;
+            continue;
+        }
+        // Simplenote has no title field; the first line is the title
+        let (title, body) = content.split_once('\n').unwrap_or((content, ""));
+        let tags: Vec<String> = item.get("tags").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|t| t.as_str().map(str::to_string))
+            .collect();
+        let pinned = item.get("pinned").and_then(Value::as_bool).unwrap_or(false);
+        let created = item.get("creationDate").and_then(Value::as_str).and_then(parse_timestamp);
+        let updated = item.get("lastModified").and_then(Value::as_str).and_then(parse_timestamp);
+        imported.push(build_note(title.to_string(), body.trim_start_matches(['\r', '\n']).to_string(), None, &tags, created, updated, pinned));
+    }
+    Ok(imported)
+}
+
+// ---------- Google Keep ----------
+
+fn read_google_keep(path: &Path) -> Result<ImportedNotes> {
+    let mut files = Vec::new();
+    if path.is_dir() {
+        collect_files(path, &["json"], &mut files)?;
+    } else {
+        files.push(path.to_path_buf());
+    }
+
+    let mut imported = ImportedNotes::default();
+    for file in files {
+        let Ok(item) = read_json(&file) else {
+            imported.skipped += 1;
+            continue;
+        };
+        // Takeout folders also contain unrelated JSON (e.g. Labels.json)
+        if item.get("textContent").is_none() && item.get("listContent").is_none() {
+            continue;
+        }
+        if item.get("isTrashed").and_then(Value::as_bool).unwrap_or(false) {
+            imported.skipped += 1;
+            continue;
+        }
+
+        let mut text = item.get("textContent").and_then(Value::as_str).unwrap_or("").to_string();
+        for entry in item.get("listContent").and_then(Value::as_array).into_iter().flatten() {
+            let checked = entry.get("isChecked").and_then(Value::as_bool).unwrap_or(false);
+            let line = entry.get("text").and_then(Value::as_str).unwrap_or("");
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(if checked { "- [x] " } else { "- [ ] " });
+            text.push_str(line);
+        }
+        let title = item.get("title").and_then(Value::as_str).unwrap_or("").to_string();
+        if title.trim().is_empty() && text.trim().is_empty() {
+            imported.skipped += 1;
+            continue;
+        }
+
+        let tags: Vec<String> = item.get("labels").and_then(Value::as_array).into_iter().flatten()
+            .filter_map(|l| l.get("name").and_then(Value::as_str).map(str::to_string))
+            .collect();
+        let folder = item.get("isArchived").and_then(Value::as_bool)
+            .filter(|archived| *archived)
+            .map(|_| "Archive".to_string());
+        let from_usec = |key: &str| item.get(key).and_then(Value::as_i64).and_then(DateTime::from_timestamp_micros);
+        let pinned = item.get("isPinned").and_then(Value::as_bool).unwrap_or(false);
+        imported.push(build_note(
+            title,
+            text,
+            folder,
+            &tags,
+            from_usec("createdTimestampUsec"),
+            from_usec("userEditedTimestampUsec"),
+            pinned,
+        ));
+    }
+    Ok(imported)
+}
+
+// ---------- Joplin raw export ----------
+
+// Joplin item types we care about
+const JOPLIN_NOTE: &str = "1";
+const JOPLIN_FOLDER: &str = "2";
+const JOPLIN_TAG: &str = "5";
+const JOPLIN_NOTE_TAG: &str = "6";
+
+struct JoplinItem {
+    title: String,
+    body: String,
+    meta: std::collections::HashMap<String, String>,
+}
+
+// A raw item is "title\n\nbody\n\nkey: value\n..." with the metadata block last
+fn parse_joplin_item(text: &str) -> Option<JoplinItem> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut meta = std::collections::HashMap::new();
+    let mut split = lines.len();
+    while split > 0 {
+        let line = lines[split - 1];
+        if line.trim().is_empty() {
+            break;
+        }
+        let (key, value) = line.split_once(": ").or_else(|| line.strip_suffix(':').map(|k| (k, "")))?;
+        meta.insert(key.to_string(), value.to_string());
+        split -= 1;
+    }
+    if !meta.contains_key("type_") {
+        return None;
+    }
+    let head = &lines[..split];
+    let title = head.first().map(|l| l.to_string()).unwrap_or_default();
+    let body = head.iter().skip(1).copied().collect::<Vec<_>>().join("\n").trim().to_string();
+    Some(JoplinItem { title, body, meta })
+}
+
+fn read_joplin(dir: &Path) -> Result<ImportedNotes> {
+    if !dir.is_dir() {
+        return Err(anyhow!("{} is not a folder", dir.display()));
+    }
+    let mut files = Vec::new();
+    collect_files(dir, &["md"], &mut files)?;
+
+    let mut imported = ImportedNotes::default();
+    let mut items = Vec::new();
+    for file in files {
+        match fs::read_to_string(&file).ok().as_deref().and_then(parse_joplin_item) {
+            Some(item) => items.push(item),
+            None => imported.skipped += 1,
+        }
+    }
+
+    let meta = |item: &JoplinItem, key: &str| item.meta.get(key).cloned().unwrap_or_default();
+    let mut folders = std::collections::HashMap::new();
+    let mut tags = std::collections::HashMap::new();
+    for item in &items {
+        match item.meta.get("type_").map(String::as_str) {
+            Some(JOPLIN_FOLDER) => { folders.insert(meta(item, "id"), (item.title.clone(), meta(item, "parent_id"))); },
+            Some(JOPLIN_TAG) => { tags.insert(meta(item, "id"), item.title.clone()); },
+            _ => {},
+        }
+    }
+    let mut note_tags: std::collections::HashMap<String, Vec<String>> = std::collections::HashMap::new();
+    for item in &items {
+        if item.meta.get("type_").map(String::as_str) == Some(JOPLIN_NOTE_TAG) {
+            if let Some(tag) = tags.get(&meta(item, "tag_id")) {
+                note_tags.entry(meta(item, "note_id")).or_default().push(tag.clone());
+            }
+        }
+    }
+
+    // Nested notebooks are flattened into "Parent/Child" folder names
+    let folder_path = |id: &str| -> Option<String> {
+        let mut parts = Vec::new();
+        let mut current = id.to_string();
+        while let Some((name, parent)) = folders.get(&current) {
+            parts.push(name.clone());
+            if parts.len() > 32 {
+                break;
+            }
+            current = parent.clone();
+        }
+        parts.reverse();
+        (!parts.is_empty()).then(|| parts.join("/"))
+    };
+
+    for item in &items {
+        if item.meta.get("type_").map(String::as_str) != Some(JOPLIN_NOTE) {
+            continue;
+        }
+        if !meta(item, "deleted_time").is_empty() && meta(item, "deleted_time") != "0" {
+            imported.skipped += 1;
+            continue;
+        }
+        let note = build_note(
+            item.title.clone(),
+            item.body.clone(),
+            folder_path(&meta(item, "parent_id")),
+            note_tags.get(&meta(item, "id")).map(Vec::as_slice).unwrap_or(&[]),
+            item.meta.get("user_created_time").or(item.meta.get("created_time")).and_then(|v| parse_timestamp(v)),
+            item.meta.get("user_updated_time").or(item.meta.get("updated_time")).and_then(|v| parse_timestamp(v)),
+            false,
+        );
+        imported.push(note);
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write `files` (relative path, contents) into a fresh directory named after the test
+    fn source_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("notas-import-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    fn timestamp(value: &str) -> DateTime<Utc> {
+        parse_timestamp(value).unwrap()
+    }
+
+    #[test]
+    fn front_matter_sets_title_tags_dates_and_folder() {
+        let text = "---\ntitle: \"Groceries\"\ntags: [home, to do]\ncreated: 2024-03-01\nupdated: 2024-03-02 10:30:00\npinned: true\nfolder: Lists\n---\n# Groceries\nmilk\n";
+        let (fm, body) = split_front_matter(text);
+        let fm = fm.unwrap();
+        assert_eq!(fm.title.as_deref(), Some("Groceries"));
+        assert_eq!(fm.tags, ["home", "to do"]);
+        assert_eq!(fm.created, Some(timestamp("2024-03-01T00:00:00Z")));
+        assert_eq!(fm.updated, Some(timestamp("2024-03-02T10:30:00Z")));
+        assert!(fm.pinned);
+        assert_eq!(fm.folder.as_deref(), Some("Lists"));
+        assert_eq!(body, "# Groceries\nmilk\n");
+
+        let (fm, _) = split_front_matter("---\ntags:\n  - one\n  - \"two\"\n---\nbody");
+        assert_eq!(fm.unwrap().tags, ["one", "two"]);
+    }
+
+    #[test]
+    fn front_matter_must_be_closed() {
+        let text = "---\ntitle: Open\nno closing line";
+        let (fm, body) = split_front_matter(text);
+        assert!(fm.is_none());
+        assert_eq!(body, text);
+        assert!(split_front_matter("plain text").0.is_none());
+    }
+
+    #[test]
+    fn markdown_folder_maps_sub_folders_and_drops_repeated_headings() {
+        let dir = source_dir("markdown", &[
+            ("Top.md", "# Top\nfirst note"),
+            ("Work/Plan.txt", "---\ntags: [q3]\n---\nship it"),
+            ("Empty.md", "  \n"),
+            (".obsidian/config.md", "not a note"),
+            ("image.png", "not a note"),
+        ]);
+        let imported = read_markdown_folder(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.skipped, 1);
+        assert_eq!(imported.folders, ["Work"]);
+        let [top, plan] = &imported.notes[..] else { panic!("expected two notes") };
+        assert_eq!((top.title.as_str(), top.content.as_str(), top.folder.as_deref()), ("Top", "first note", None));
+        assert_eq!((plan.title.as_str(), plan.content.as_str(), plan.folder.as_deref()), ("Plan", "ship it\n\n#q3", Some("Work")));
+    }
+
+    #[test]
+    fn markdown_folder_does_not_follow_directory_links() {
+        let dir = source_dir("symlink", &[("sub/note.md", "inside")]);
+        std::os::unix::fs::symlink("..", dir.join("sub/up")).unwrap();
+        let imported = read_markdown_folder(&dir);
+        let _ = fs::remove_dir_all(&dir);
+
+        let notes = imported.unwrap().notes;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].folder.as_deref(), Some("sub"));
+    }
+
+    #[test]
+    fn standard_notes_resolves_tags_and_skips_trashed_and_encrypted_items() {
+        let dir = source_dir("standard-notes", &[("backup.txt", r#"{"items": [
+            {"uuid": "a", "content_type": "Note", "created_at": "2023-01-02T03:04:05.000Z",
+             "content": {"title": "Ideas", "text": "write more", "appData": {"org.standardnotes.sn": {"pinned": true}}}},
+            {"uuid": "b", "content_type": "Note", "content": {"title": "Old", "text": "gone", "trashed": true}},
+            {"uuid": "c", "content_type": "Note", "content": "004:encrypted"},
+            {"uuid": "d", "content_type": "Note", "content": {}},
+            {"uuid": "e", "content_type": "Note"},
+            {"uuid": "t", "content_type": "Tag", "content": {"title": "writing", "references": [{"uuid": "a"}]}}
+        ]}"#)]);
+        let imported = read_standard_notes(&dir.join("backup.txt")).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.skipped, 4);
+        let [note] = &imported.notes[..] else { panic!("expected one note") };
+        assert_eq!((note.title.as_str(), note.content.as_str()), ("Ideas", "write more\n\n#writing"));
+        assert!(note.pinned);
+        assert_eq!(note.created_at, timestamp("2023-01-02T03:04:05Z"));
+    }
+
+    #[test]
+    fn standard_notes_rejects_other_json() {
+        let dir = source_dir("standard-notes-invalid", &[("other.json", r#"{"notes": []}"#), ("broken.json", "{\"items\": [")]);
+        let missing_items = read_standard_notes(&dir.join("other.json"));
+        let broken = read_standard_notes(&dir.join("broken.json"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(missing_items.unwrap_err().to_string().contains("missing \"items\""));
+        assert!(broken.unwrap_err().to_string().contains("is not valid JSON"));
+    }
+
+    #[test]
+    fn simplenote_uses_the_first_line_as_title() {
+        let dir = source_dir("simplenote", &[("notes.json", r#"{
+            "activeNotes": [
+                {"content": "Trip\r\npack bags", "tags": ["travel"], "pinned": true, "lastModified": "2022-06-01T12:00:00.000Z"},
+                {"content": "   "},
+                {"id": "no content"}
+            ],
+            "trashedNotes": [{"content": "deleted"}]
+        }"#), ("broken.json", "[1, 2")]);
+        let imported = read_simplenote(&dir.join("notes.json")).unwrap();
+        let broken = read_simplenote(&dir.join("broken.json"));
+        let missing = read_simplenote(&dir.join("absent.json"));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.skipped, 3);
+        let [note] = &imported.notes[..] else { panic!("expected one note") };
+        assert_eq!((note.title.as_str(), note.content.as_str()), ("Trip", "pack bags\n\n#travel"));
+        assert!(note.pinned);
+        assert_eq!(note.updated_at, timestamp("2022-06-01T12:00:00Z"));
+        assert!(broken.is_err());
+        assert!(missing.unwrap_err().to_string().contains("Failed to read"));
+    }
+
+    #[test]
+    fn google_keep_turns_lists_into_checkboxes() {
+        let dir = source_dir("keep", &[
+            ("Takeout/Keep/Shopping.json", r#"{"title": "Shopping", "isArchived": true, "isPinned": true,
+                "listContent": [{"text": "eggs", "isChecked": true}, {"text": "bread"}],
+                "labels": [{"name": "home life"}], "createdTimestampUsec": 1700000000000000}"#),
+            ("Takeout/Keep/Trashed.json", r#"{"title": "Bin", "textContent": "x", "isTrashed": true}"#),
+            ("Takeout/Keep/Blank.json", r#"{"title": "", "textContent": ""}"#),
+            ("Takeout/Keep/Labels.json", r#"{"labels": []}"#),
+            ("Takeout/Keep/Broken.json", r#"{"title": "#),
+        ]);
+        let imported = read_google_keep(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.skipped, 3);
+        assert_eq!(imported.folders, ["Archive"]);
+        let [note] = &imported.notes[..] else { panic!("expected one note") };
+        assert_eq!(note.title, "Shopping");
+        assert_eq!(note.content.as_str(), "- [x] eggs\n- [ ] bread\n\n#home-life");
+        assert!(note.pinned);
+        assert_eq!(note.created_at, DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+    }
+
+    #[test]
+    fn joplin_items_need_a_metadata_block() {
+        let item = parse_joplin_item("Title\n\nSome body\n\nid: 1\nparent_id:\ntype_: 1").unwrap();
+        assert_eq!((item.title.as_str(), item.body.as_str()), ("Title", "Some body"));
+        assert_eq!(item.meta.get("parent_id").map(String::as_str), Some(""));
+        assert!(parse_joplin_item("Just a markdown file\n\nwith text").is_none());
+        assert!(parse_joplin_item("Title\n\nid: 1").is_none());
+    }
+
+    #[test]
+    fn joplin_export_resolves_notebooks_and_tags() {
+        let dir = source_dir("joplin", &[
+            ("n1.md", "Meeting\n\nagenda\n\nid: n1\nparent_id: f2\ncreated_time: 2021-05-06T07:08:09.000Z\ntype_: 1"),
+            ("n2.md", "Removed\n\nx\n\nid: n2\nparent_id: f1\ndeleted_time: 2021-05-07T00:00:00.000Z\ntype_: 1"),
+            ("f1.md", "Work\n\nid: f1\nparent_id: \ntype_: 2"),
+            ("f2.md", "Meetings\n\nid: f2\nparent_id: f1\ntype_: 2"),
+            ("t1.md", "urgent\n\nid: t1\ntype_: 5"),
+            ("nt.md", "id: nt\nnote_id: n1\ntag_id: t1\ntype_: 6"),
+            ("readme.md", "not a Joplin item"),
+        ]);
+        let imported = read_joplin(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(imported.skipped, 2);
+        let [note] = &imported.notes[..] else { panic!("expected one note") };
+        assert_eq!((note.title.as_str(), note.content.as_str()), ("Meeting", "agenda\n\n#urgent"));
+        assert_eq!(note.folder.as_deref(), Some("Work/Meetings"));
+        assert_eq!(note.created_at, timestamp("2021-05-06T07:08:09Z"));
+    }
+}
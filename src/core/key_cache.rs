@@ -0,0 +1,253 @@
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use zeroize::Zeroize;
+
+use super::{data::KeyCacheBackend, guarded::GuardedBytes};
+
+// Where an unlocked vault's key material can be left for the rest of the login session,
+// so another Notas process (or a restart) opens the vault without the password. Entries
+// are named per vault and expire on their own; locking Notas removes them.
+pub trait KeyProvider {
+    fn store(&self, name: &str, secret: &[u8], timeout: Duration) -> Result<()>;
+    // `None` if there is no entry, or it has expired
+    fn load(&self, name: &str) -> Result<Option<GuardedBytes>>;
+    fn remove(&self, name: &str) -> Result<()>;
+}
+
+pub fn provider(backend: KeyCacheBackend) -> Option<Box<dyn KeyProvider>> {
+    match backend {
+        KeyCacheBackend::Off => None,
+        KeyCacheBackend::KernelKeyring => Some(Box::new(KernelKeyring)),
+        KeyCacheBackend::SecretService => Some(Box::new(SecretService::default())),
+    }
+}
+
+// From linux/keyctl.h
+const KEY_SPEC_SESSION_KEYRING: libc::c_long = -3;
+const KEYCTL_SEARCH: libc::c_long = 10;
+const KEYCTL_READ: libc::c_long = 11;
+const KEYCTL_SET_TIMEOUT: libc::c_long = 15;
+const KEYCTL_INVALIDATE: libc::c_long = 21;
+
+/// A "user" key in the kernel session keyring. The kernel expires it after the timeout,
+/// and the whole keyring goes away when the login session ends.
+pub struct KernelKeyring;
+
+impl KernelKeyring {
+    fn description(name: &str) -> Result<CString> {
+        CString::new(format!("notas:{}", name)).map_err(|_| anyhow!("Invalid key name"))
+    }
+
+    // Serial of the key in the session keyring, if there is one that hasn't expired
+    fn search(name: &str) -> Result<Option<libc::c_long>> {
+        let description = Self::description(name)?;
+        let serial = unsafe {
+            libc::syscall(libc::SYS_keyctl, KEYCTL_SEARCH, KEY_SPEC_SESSION_KEYRING, c"user".as_ptr(), description.as_ptr(), 0)
+        };
+        if serial >= 0 {
+            return Ok(Some(serial));
+        }
+        match io::Error::last_os_error().raw_os_error() {
+            Some(libc::ENOKEY) | Some(libc::EKEYEXPIRED) | Some(libc::EKEYREVOKED) => Ok(None),
+            _ => Err(anyhow!("Cannot search the kernel keyring: {}", io::Error::last_os_error())),
+        }
+    }
+}
+
+impl KeyProvider for KernelKeyring {
+    fn store(&self, name: &str, secret: &[u8], timeout: Duration) -> Result<()> {
+        let description = Self::description(name)?;
+        // Replaces the payload of an existing key with the same description
+        let serial = unsafe {
+            libc::syscall(libc::SYS_add_key, c"user".as_ptr(), description.as_ptr(), secret.as_ptr(), secret.len(), KEY_SPEC_SESSION_KEYRING)
+        };
+        if serial < 0 {
+            return Err(anyhow!("Cannot add to the kernel keyring: {}", io::Error::last_os_error()));
+        }
+        let seconds = timeout.as_secs().max(1) as libc::c_long;
+        if unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_SET_TIMEOUT, serial, seconds) } < 0 {
+            let error = io::Error::last_os_error();
+            unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) };
+            return Err(anyhow!("Cannot set the key timeout: {}", error));
+        }
+        Ok(())
+    }
+
+    fn load(&self, name: &str) -> Result<Option<GuardedBytes>> {
+        let Some(serial) = Self::search(name)? else {
+            return Ok(None);
+        };
+        let len = unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_READ, serial, std::ptr::null_mut::<u8>(), 0) };
+        if len < 0 {
+            return Err(anyhow!("Cannot read the kernel keyring: {}", io::Error::last_os_error()));
+        }
+        let mut secret = GuardedBytes::new(len as usize)?;
+        let read = unsafe {
+            libc::syscall(libc::SYS_keyctl, KEYCTL_READ, serial, secret.as_mut_slice().as_mut_ptr(), secret.len())
+        };
+        if read != len {
+            return Err(anyhow!("Cannot read the kernel keyring: {}", io::Error::last_os_error()));
+        }
+        Ok(Some(secret))
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        if let Some(serial) = Self::search(name)? {
+            if unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_INVALIDATE, serial) } < 0 {
+                return Err(anyhow!("Cannot remove from the kernel keyring: {}", io::Error::last_os_error()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An item in the session collection of the freedesktop Secret Service (GNOME Keyring,
+/// KWallet), through libsecret's `secret-tool`. That collection lives in memory only and
+/// is gone at logout, so the key never lands in the on-disk login keyring. The service
+/// has no expiry of its own, so the expiry time is stored with the secret and checked on
+/// load. `secret-tool` uses DBUS_SESSION_BUS_ADDRESS, so a stand-in service on a private
+/// bus works too.
+pub struct SecretService {
+    // secret-tool, looked up on PATH unless given as a path
+    program: PathBuf,
+}
+
+const SECRET_TOOL: &str = "secret-tool";
+const APPLICATION: &str = "com.jegly.Notas";
+
+impl Default for SecretService {
+    fn default() -> Self {
+        Self::with_program(SECRET_TOOL)
+    }
+}
+
+impl SecretService {
+    /// Use another program with secret-tool's command line
+    pub fn with_program(program: impl Into<PathBuf>) -> Self {
+        Self { program: program.into() }
+    }
+
+    fn run(&self, args: &[&str], input: Option<&[u8]>) -> Result<(bool, Vec<u8>)> {
+        let mut child = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => anyhow!("secret-tool is not installed (package libsecret-tools)"),
+                _ => anyhow!("Cannot run secret-tool: {}", e),
+            })?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Some(input) = input {
+                stdin.write_all(input)?;
+            }
+        }
+        let mut output = Vec::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_end(&mut output)?;
+        }
+        Ok((child.wait()?.success(), output))
+    }
+}
+
+impl KeyProvider for SecretService {
+    fn store(&self, name: &str, secret: &[u8], timeout: Duration) -> Result<()> {
+        let expires = super::totp::unix_now() + timeout.as_secs();
+        // Stored as text: expiry (8 bytes, big-endian) | secret, base64
+        let mut payload = GuardedBytes::new(8 + secret.len())?;
+        payload.as_mut_slice()[..8].copy_from_slice(&expires.to_be_bytes());
+        payload.as_mut_slice()[8..].copy_from_slice(secret);
+        let mut encoded = BASE64.encode(payload.as_slice());
+        let result = self.run(
+            &["store", "--collection=session", "--label=Notas vault key", "application", APPLICATION, "vault", name],
+            Some(encoded.as_bytes()),
+        );
+        encoded.zeroize();
+        match result? {
+            (true, _) => Ok(()),
+            (false, _) => Err(anyhow!("The Secret Service refused to store the key")),
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<Option<GuardedBytes>> {
+        // Exits with an error when there is no such item
+        let (found, mut output) = self.run(&["lookup", "application", APPLICATION, "vault", name], None)?;
+        let decoded = BASE64.decode(output.trim_ascii());
+        output.zeroize();
+        if !found {
+            return Ok(None);
+        }
+        let mut decoded = decoded.map_err(|_| anyhow!("Secret Service item is corrupted"))?;
+        let payload = GuardedBytes::from_slice(&decoded);
+        decoded.zeroize();
+        let payload = payload?;
+
+        let Some((expires, secret)) = payload.as_slice().split_first_chunk::<8>() else {
+            return Err(anyhow!("Secret Service item is corrupted"));
+        };
+        if u64::from_be_bytes(*expires) <= super::totp::unix_now() {
+            self.remove(name)?;
+            return Ok(None);
+        }
+        GuardedBytes::from_slice(secret).map(Some)
+    }
+
+    fn remove(&self, name: &str) -> Result<()> {
+        // Clearing an item that doesn't exist isn't an error for us
+        self.run(&["clear", "application", APPLICATION, "vault", name], None)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    // Stands in for secret-tool and the service behind it: each item is a file named
+    // after its attributes, in a directory per collection
+    const SECRET_TOOL_STAND_IN: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+command=$1; shift
+collection=default
+case $1 in --collection=*) collection=${1#--collection=}; shift;; esac
+case $1 in --label=*) shift;; esac
+item=$(echo "$*" | tr ' /' '_-')
+case $command in
+    store) mkdir -p "$dir/$collection" && cat > "$dir/$collection/$item";;
+    lookup) cat "$dir"/*/"$item" 2>/dev/null;;
+    clear) rm -f "$dir"/*/"$item";;
+    *) exit 2;;
+esac
+"#;
+
+    #[test]
+    fn secret_service_keeps_keys_in_the_session_collection() {
+        let dir = env::temp_dir().join(format!("notas-secret-service-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let stand_in = dir.join(SECRET_TOOL);
+        fs::write(&stand_in, SECRET_TOOL_STAND_IN).unwrap();
+        fs::set_permissions(&stand_in, fs::Permissions::from_mode(0o755)).unwrap();
+        let service = SecretService::with_program(&stand_in);
+
+        service.store("vault", b"key material", Duration::from_secs(60)).unwrap();
+        assert!(!dir.join("default").exists());
+        assert_eq!(fs::read_dir(dir.join("session")).unwrap().count(), 1);
+        assert_eq!(service.load("vault").unwrap().unwrap().as_slice(), b"key material");
+
+        // Expired entries are removed when found
+        service.store("expired", b"old key", Duration::ZERO).unwrap();
+        assert!(service.load("expired").unwrap().is_none());
+        assert_eq!(fs::read_dir(dir.join("session")).unwrap().count(), 1);
+
+        service.remove("vault").unwrap();
+        assert!(service.load("vault").unwrap().is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
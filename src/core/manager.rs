@@ -1,48 +1,78 @@
-use std::{fs, path::PathBuf};
-use std::sync::RwLock;
+use std::{fs, path::{Path, PathBuf}, time::Duration};
 use anyhow::{Result, anyhow};
-use aes_gcm::Key;
 use dirs::data_dir;
-use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
 use zeroize::Zeroize;
 
 use super::{
-    data::{NoteList, MasterPassword, AppSettings, SecureBuffer, Argon2Params},
-    crypto::{self, EncryptedData, SALT_LEN},
+    data::{NoteList, MasterPassword, AppSettings, SecureBuffer, Argon2Params, Cipher, VaultEntry},
+    crypto,
+    guarded::{GuardedBytes, GuardedKey},
+    vault::{DecoyArea, Keyfile, KeySlot, SlotKind, UnlockFactors, VaultFile, VaultHeader, WrongCredentials},
+    archive::{self, ExportMetadata, ExportPayload, ExportScope, SharedNote},
+    attempts::{AttemptLog, AttemptRecord},
+    note_lock::NoteLock,
+    quick_unlock::QuickUnlock,
+    key_cache,
+    recipients,
+    recovery,
+    totp::{self, TotpSecret},
+    import::{self, ImportAction, ImportEntry, ImportPreview, ImportSource, ImportStatus, ImportSummary, ImportedNotes},
 };
 
-// Use RwLock for the crypto state to allow proper clearing
-static CRYPTO_STATE: OnceCell<RwLock<Option<CryptoState>>> = OnceCell::new();
-
 // Redirect file name - placed in default location to point to custom location
 const REDIRECT_FILE: &str = "notes.redirect";
 
-struct CryptoState {
-    key: Key<aes_gcm::Aes256Gcm>,
-    salt: [u8; SALT_LEN],
+/// Key material of an unlocked vault. Each manager owns its own (so several vaults,
+/// or tests, can be open side by side); dropping it wipes the key.
+pub struct Session {
+    // Random data key the notes are encrypted with; the header's slots wrap it
+    key: GuardedKey,
+    header: VaultHeader,
+    bound: BoundFactors,
 }
 
-impl Zeroize for CryptoState {
-    fn zeroize(&mut self) {
-        // Key is 32 bytes, we need to zeroize the underlying data
-        let key_bytes: &mut [u8; 32] = unsafe {
-            &mut *(self.key.as_mut_ptr() as *mut [u8; 32])
-        };
-        key_bytes.zeroize();
-        self.salt.zeroize();
-    }
+// Second factors the password slot is bound to, kept so the slot can be rewrapped
+// (new password, new KDF parameters) without asking for them again
+#[derive(Default, Clone)]
+struct BoundFactors {
+    keyfile: Option<Keyfile>,
+    totp_secret: Option<TotpSecret>,
 }
 
-impl Drop for CryptoState {
-    fn drop(&mut self) {
-        self.zeroize();
-    }
+// Key of the locked note currently open in the editor; dropped on note switch and lock
+struct OpenNote {
+    id: u64,
+    key: GuardedKey,
+}
+
+// Quick unlock set up for the vault at `path`. It outlives `lock`, but not a switch
+// to another vault.
+struct ArmedQuickUnlock {
+    path: PathBuf,
+    // Decoy flag, data key, then the bound keyfile digest and TOTP secret (see
+    // `session_secret`)
+    sealed: QuickUnlock,
+}
+
+// Which of the two vaults in the file was unlocked. It is re-encrypted on save; the
+// other is written back byte for byte.
+enum OpenedVault {
+    Main { decoy: DecoyArea },
+    // `main` is the main vault's header and body as read from disk
+    Decoy { decoy: DecoyArea, main: Vec<u8> },
 }
 
 pub struct CoreManager {
     data_path: PathBuf,
     note_list: NoteList,
     settings: AppSettings,
+    open_note: Option<OpenNote>,
+    opened: Option<OpenedVault>,
+    session: Option<Session>,
+    quick_unlock: Option<ArmedQuickUnlock>,
+    // Failed attempts seen by the last successful unlock, until the UI shows them
+    unlock_report: Option<AttemptRecord>,
 }
 
 impl CoreManager {
@@ -60,11 +90,19 @@ impl CoreManager {
         }
         
         let data_path = Self::resolve_data_path(&settings)?;
+        if Self::register_vault(&mut settings, &data_path, None) {
+            let _ = settings.save();
+        }
 
         Ok(Self {
             data_path,
             note_list: NoteList::new(),
             settings,
+            open_note: None,
+            opened: None,
+            session: None,
+            quick_unlock: None,
+            unlock_report: None,
         })
     }
     
@@ -130,6 +168,124 @@ impl CoreManager {
     pub fn get_data_path(&self) -> &PathBuf {
         &self.data_path
     }
+
+    /// Vaults listed in the picker, the one in use included
+    pub fn vaults(&self) -> &[VaultEntry] {
+        &self.settings.vaults
+    }
+
+    pub fn current_vault(&self) -> Option<&VaultEntry> {
+        self.settings.vaults.iter().find(|entry| entry.path == self.data_path)
+    }
+
+    // Add `path` to the registry if it isn't there yet; returns whether it was added
+    fn register_vault(settings: &mut AppSettings, path: &Path, name: Option<&str>) -> bool {
+        if settings.vaults.iter().any(|entry| entry.path == path) {
+            return false;
+        }
+        let is_default = Self::get_default_db_path().map(|default| default == path).unwrap_or(false);
+        let name = match name {
+            Some(name) => name.to_string(),
+            None if is_default => "Notes".to_string(),
+            None => path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_else(|| "Vault".to_string()),
+        };
+        settings.vaults.push(VaultEntry { name, path: path.to_path_buf(), last_opened: None });
+        true
+    }
+
+    /// Lock the vault in use and make `path` the one the lock screen opens. Each vault
+    /// keeps its own credentials and failed-attempt record; nothing carries over.
+    pub fn switch_vault(&mut self, path: &Path) -> Result<()> {
+        if path == self.data_path {
+            return Ok(());
+        }
+        self.lock();
+        self.unlock_report = None;
+        self.quick_unlock = None;
+
+        let is_default = Self::get_default_db_path()? == path;
+        self.settings.custom_db_path = (!is_default).then(|| path.to_path_buf());
+        if is_default {
+            Self::remove_redirect_file()?;
+        } else {
+            Self::write_redirect_file(&path.to_path_buf())?;
+        }
+        self.data_path = Self::resolve_data_path(&self.settings)?;
+        Self::register_vault(&mut self.settings, path, None);
+        self.settings.save()
+    }
+
+    /// Register a vault that doesn't exist yet and switch to it; the next unlock
+    /// creates it with the password given there
+    pub fn create_vault(&mut self, name: &str, path: Option<PathBuf>) -> Result<PathBuf> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Vault name cannot be empty"));
+        }
+        let path = match path {
+            Some(path) => path,
+            None => Self::new_vault_path(name)?,
+        };
+        if path.exists() {
+            return Err(anyhow!("A file already exists at {}", path.display()));
+        }
+        Self::register_vault(&mut self.settings, &path, Some(name));
+        self.switch_vault(&path)?;
+        Ok(path)
+    }
+
+    /// Register an existing vault file (another device's, a backup) and switch to it
+    pub fn add_vault_file(&mut self, path: &Path) -> Result<()> {
+        VaultFile::read(path).map_err(|_| anyhow!("This file is not a Notas vault"))?;
+        Self::register_vault(&mut self.settings, path, None);
+        self.switch_vault(path)
+    }
+
+    pub fn rename_vault(&mut self, path: &Path, name: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("Vault name cannot be empty"));
+        }
+        let entry = self.settings.vaults.iter_mut().find(|entry| entry.path == path)
+            .ok_or_else(|| anyhow!("Unknown vault"))?;
+        entry.name = name.to_string();
+        self.settings.save()
+    }
+
+    /// Drop a vault from the picker; the file itself is left alone
+    pub fn forget_vault(&mut self, path: &Path) -> Result<()> {
+        if path == self.data_path {
+            return Err(anyhow!("Switch to another vault before removing this one"));
+        }
+        self.settings.vaults.retain(|entry| entry.path != path);
+        self.settings.save()
+    }
+
+    // Next to the default vault, named after the vault
+    fn new_vault_path(name: &str) -> Result<PathBuf> {
+        let stem: String = name.chars()
+            .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+            .collect::<String>()
+            .split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+        let stem = if stem.is_empty() { "vault".to_string() } else { stem };
+        let app_dir = Self::get_default_app_dir()?;
+        let mut path = app_dir.join(format!("{}.dat", stem));
+        let mut n = 2;
+        while path.exists() {
+            path = app_dir.join(format!("{}-{}.dat", stem, n));
+            n += 1;
+        }
+        Ok(path)
+    }
+
+    // Remember when the vault in use was last unlocked
+    fn touch_vault(&mut self) {
+        Self::register_vault(&mut self.settings, &self.data_path, None);
+        if let Some(entry) = self.settings.vaults.iter_mut().find(|entry| entry.path == self.data_path) {
+            entry.last_opened = Some(chrono::Utc::now());
+        }
+        let _ = self.settings.save();
+    }
     
     pub fn get_settings(&self) -> &AppSettings {
         &self.settings
@@ -147,7 +303,11 @@ impl CoreManager {
             || self.settings.argon2_params.time_cost != settings.argon2_params.time_cost
             || self.settings.argon2_params.parallelism != settings.argon2_params.parallelism;
         
-        if path_changed && Self::is_unlocked() {
+        if path_changed && self.is_unlocked() {
+            // Never move a vault over another file (another vault, most likely)
+            if new_path.exists() {
+                return Err(anyhow!("A file already exists at {}", new_path.display()));
+            }
             // Move data file to new location
             if old_path.exists() {
                 if let Some(parent) = new_path.parent() {
@@ -155,6 +315,7 @@ impl CoreManager {
                 }
                 fs::copy(&old_path, &new_path)?;
                 fs::remove_file(&old_path)?;
+                AttemptLog::for_vault(&old_path).move_to(&new_path)?;
             }
             self.data_path = new_path.clone();
             
@@ -174,204 +335,750 @@ impl CoreManager {
             Self::remove_redirect_file()?;
         }
         
+        let key_cache_changed = settings.key_cache != self.settings.key_cache;
+        if key_cache_changed {
+            let _ = self.forget_cached_key();
+        }
         self.settings = settings;
+        if key_cache_changed && self.is_unlocked() {
+            self.cache_key();
+        }
+        if self.settings.quick_unlock_minutes == 0 {
+            self.quick_unlock = None;
+        }
+        // The registry follows a moved vault
+        if path_changed && self.is_unlocked() {
+            if let Some(entry) = self.settings.vaults.iter_mut().find(|entry| entry.path == old_path) {
+                entry.path = new_path.clone();
+            }
+        }
+        Self::register_vault(&mut self.settings, &self.data_path, None);
         self.settings.save()?;
         
         // If Argon2 params changed, we need user to re-enter password to re-encrypt
         // This is handled separately via re_encrypt_with_new_params
-        if params_changed && Self::is_unlocked() {
+        if params_changed && self.is_unlocked() {
             // Just save settings, user needs to call re_encrypt_with_new_params with password
         }
         
+        // A new cipher choice also re-encrypts the open vault
+        if self.is_unlocked() {
+            self.set_cipher(self.settings.cipher)?;
+        }
+        
         Ok(())
     }
     
-    /// Re-encrypt the vault with new Argon2 parameters
+    /// Rewrap the password slot with new Argon2 parameters
     #[allow(dead_code)]
     pub fn re_encrypt_with_params(&mut self, password: MasterPassword, new_params: &Argon2Params) -> Result<()> {
-        if !Self::is_unlocked() {
+        if !self.is_unlocked() {
             return Err(anyhow!("Application must be unlocked to change encryption parameters"));
         }
         
         let password_buffer = SecureBuffer::new(password.0.clone());
-        let (new_key, new_salt) = crypto::generate_test_key_with_params(password_buffer.as_slice(), new_params)?;
-        
-        // Re-encrypt with new parameters
-        let serialized = bincode::serialize(&self.note_list)?;
-        let encrypted = crypto::encrypt(&new_key, &new_salt, &serialized)?;
-        fs::write(&self.data_path, encrypted.to_bytes())?;
-        
-        // Update crypto state
-        if let Some(state) = CRYPTO_STATE.get() {
-            if let Ok(mut guard) = state.write() {
-                if let Some(ref mut crypto) = *guard {
-                    crypto.zeroize();
-                }
-                *guard = Some(CryptoState { 
-                    key: new_key, 
-                    salt: new_salt 
-                });
-            }
-        }
-        
-        Ok(())
+        let bound = self.current_state()?.2;
+        self.rewrap_password_slot(password_buffer.as_slice(), password_buffer.as_slice(), bound, Some(new_params.clone()))
     }
 
-    fn init_crypto_state() {
-        let _ = CRYPTO_STATE.set(RwLock::new(None));
-    }
-
-    pub fn is_unlocked() -> bool {
-        if let Some(state) = CRYPTO_STATE.get() {
-            state.read().map(|s| s.is_some()).unwrap_or(false)
-        } else {
-            false
-        }
+    pub fn is_unlocked(&self) -> bool {
+        self.session.is_some()
     }
     
     pub fn lock(&mut self) {
         // Zeroize the note list in memory
         self.note_list.zeroize();
         self.note_list = NoteList::new();
-        
-        // Clear the crypto state
-        if let Some(state) = CRYPTO_STATE.get() {
-            if let Ok(mut guard) = state.write() {
-                if let Some(ref mut crypto) = *guard {
-                    crypto.zeroize();
-                }
-                *guard = None;
+        self.open_note = None;
+        self.opened = None;
+        // Dropping the session zeroizes the key
+        self.session = None;
+    }
+
+    pub fn unlock(&mut self, master_password: MasterPassword, factors: UnlockFactors) -> Result<()> {
+        if self.is_unlocked() {
+            return Ok(());
+        }
+
+        // Failed attempts are only counted against a vault that exists
+        let attempts = AttemptLog::for_vault(&self.data_path);
+        if !self.vault_exists() {
+            self.session = Some(self.open_vault(master_password, factors)?);
+            let _ = attempts.reset();
+            self.touch_vault();
+            self.cache_key();
+            return Ok(());
+        }
+
+        Self::check_attempts(&attempts)?;
+
+        match self.open_vault(master_password, factors) {
+            Ok(session) => {
+                self.session = Some(session);
+                self.unlock_report = attempts.reset().ok().filter(|record| record.failures > 0 || record.tampered);
+                self.touch_vault();
+                self.cache_key();
+                Ok(())
+            },
+            // A missing factor or an unreadable or newer-format file is not a guess
+            Err(e) if e.downcast_ref::<WrongCredentials>().is_none() => Err(e),
+            Err(e) => Err(self.record_failed_attempt(&attempts, e)),
+        }
+    }
+
+    // Refuse an unlock attempt while the back-off from earlier failures lasts
+    fn check_attempts(attempts: &AttemptLog) -> Result<()> {
+        let retry_in = attempts.load().retry_in(totp::unix_now());
+        if retry_in > 0 {
+            return Err(anyhow!("Too many failed attempts. Try again in {} s.", retry_in));
+        }
+        Ok(())
+    }
+
+    // Count a wrong password or code, erasing the vault once the limit in settings is
+    // reached. Returns the error to report.
+    fn record_failed_attempt(&mut self, attempts: &AttemptLog, error: anyhow::Error) -> anyhow::Error {
+        // If the record can't be written there's nothing more to enforce
+        let Ok(record) = attempts.record_failure(totp::unix_now()) else {
+            return error;
+        };
+        let limit = self.settings.wipe_after_failures;
+        if limit > 0 && record.failures >= limit {
+            let _ = self.forget_cached_key();
+            if let Err(e) = self.erase_vault() {
+                return e;
             }
+            let _ = attempts.remove();
+            return anyhow!("Too many failed attempts. The vault has been erased.");
         }
+        error
     }
 
-    pub fn unlock(&mut self, master_password: MasterPassword) -> Result<()> {
-        // Initialize crypto state container if needed
-        Self::init_crypto_state();
-        
-        if Self::is_unlocked() {
+    /// Let `pin` reopen this vault after it locks, for `quick_unlock_minutes` from now.
+    /// The key material is sealed under the PIN in memory only, so a restart, a switch
+    /// to another vault or too many wrong PINs mean the full password again.
+    pub fn enable_quick_unlock(&mut self, pin: &str) -> Result<()> {
+        let minutes = self.settings.quick_unlock_minutes;
+        if minutes == 0 {
+            return Err(anyhow!("Quick unlock is turned off"));
+        }
+        let mut secret = self.session_secret()?;
+        let sealed = QuickUnlock::seal(pin, secret.as_slice(), Duration::from_secs(minutes * 60));
+        secret.as_mut_slice().zeroize();
+        self.quick_unlock = Some(ArmedQuickUnlock { path: self.data_path.clone(), sealed: sealed? });
+        Ok(())
+    }
+
+    pub fn disable_quick_unlock(&mut self) {
+        self.quick_unlock = None;
+    }
+
+    /// Whether the locked vault can be reopened with a PIN
+    pub fn has_quick_unlock(&self) -> bool {
+        self.quick_unlock.as_ref().is_some_and(|armed| {
+            armed.path == self.data_path && armed.sealed.tries_left() > 0 && !armed.sealed.is_expired()
+        })
+    }
+
+    /// Reopen the vault with the quick unlock PIN
+    pub fn quick_unlock(&mut self, pin: &str) -> Result<()> {
+        if self.is_unlocked() {
             return Ok(());
         }
+        if !self.has_quick_unlock() {
+            self.quick_unlock = None;
+            return Err(anyhow!("Quick unlock has expired. Enter your password."));
+        }
+        let Some(armed) = self.quick_unlock.as_mut() else {
+            return Err(anyhow!("Quick unlock has expired. Enter your password."));
+        };
+        let secret = match armed.sealed.open(pin) {
+            Ok(secret) => secret,
+            Err(e) => {
+                let tries_left = armed.sealed.tries_left();
+                if tries_left == 0 {
+                    self.quick_unlock = None;
+                    return Err(anyhow!("Too many incorrect PINs. Enter your password."));
+                }
+                return Err(anyhow!("{} ({} {} left)", e, tries_left, if tries_left == 1 { "try" } else { "tries" }));
+            },
+        };
+        let result = self.restore_session(secret.as_slice());
+        if result.is_err() {
+            // The file changed under us (e.g. replaced); only the password can tell
+            self.quick_unlock = None;
+        }
+        result
+    }
+
+    // Serialized key material of the open session, for sealing under a quick unlock PIN
+    // or leaving in the key cache
+    fn session_secret(&self) -> Result<GuardedBytes> {
+        let session = self.session.as_ref().ok_or_else(|| anyhow!("Application is locked"))?;
+        let keyfile = session.bound.keyfile.as_ref().map(Keyfile::as_bytes).unwrap_or(&[]);
+        let totp_secret = session.bound.totp_secret.as_ref().map(TotpSecret::as_bytes).unwrap_or(&[]);
+        let decoy = matches!(self.opened, Some(OpenedVault::Decoy { .. }));
+
+        // decoy (1) | data key (32) | keyfile length (1) | keyfile digest | TOTP secret
+        let mut secret = GuardedBytes::new(1 + 32 + 1 + keyfile.len() + totp_secret.len())?;
+        let bytes = secret.as_mut_slice();
+        bytes[0] = decoy as u8;
+        bytes[1..33].copy_from_slice(session.key.key());
+        bytes[33] = keyfile.len() as u8;
+        bytes[34..34 + keyfile.len()].copy_from_slice(keyfile);
+        bytes[34 + keyfile.len()..].copy_from_slice(totp_secret);
+        Ok(secret)
+    }
+
+    // Decrypt the vault on disk with key material from `session_secret`
+    fn restore_session(&mut self, secret: &[u8]) -> Result<()> {
+        let corrupted = || anyhow!("Quick unlock data is corrupted");
+        let (&decoy, rest) = secret.split_first().ok_or_else(corrupted)?;
+        let (key, rest) = rest.split_at_checked(32).ok_or_else(corrupted)?;
+        let (&keyfile_len, rest) = rest.split_first().ok_or_else(corrupted)?;
+        let (keyfile, totp_secret) = rest.split_at_checked(keyfile_len as usize).ok_or_else(corrupted)?;
+        let key = GuardedKey::from_slice(key)?;
+        let bound = BoundFactors {
+            keyfile: (!keyfile.is_empty()).then(|| Keyfile::from_bytes(keyfile)).transpose()?,
+            totp_secret: (!totp_secret.is_empty()).then(|| TotpSecret::from_bytes(totp_secret)).transpose()?,
+        };
+
+        let VaultFile::Enveloped { header, body, associated_data, decoy: decoy_area } = VaultFile::read(&self.data_path)? else {
+            return Err(anyhow!("Vault format has changed. Enter your password."));
+        };
+        let (mut decrypted_bytes, opened) = match (decoy, decoy_area) {
+            (0, decoy_area) => {
+                let bytes = header.open_body(key.key(), &body, &associated_data)
+                    .map_err(|_| anyhow!("Vault has changed. Enter your password."))?;
+                let decoy_area = match decoy_area {
+                    Some(decoy_area) => decoy_area,
+                    None => DecoyArea::filler(header.password_slot().map(|slot| slot.params.clone()).unwrap_or_default()),
+                };
+                (bytes, OpenedVault::Main { decoy: decoy_area })
+            },
+            (_, Some(decoy_area)) => {
+                let bytes = decoy_area.open_body(key.key())
+                    .map_err(|_| anyhow!("Vault has changed. Enter your password."))?;
+                let mut main = associated_data;
+                main.extend_from_slice(&body);
+                (bytes, OpenedVault::Decoy { decoy: decoy_area, main })
+            },
+            (_, None) => return Err(anyhow!("Vault has changed. Enter your password.")),
+        };
+        let note_list = NoteList::from_bytes(&decrypted_bytes);
+        decrypted_bytes.zeroize();
+        self.note_list = note_list?;
+        self.opened = Some(opened);
+        self.session = Some(Session { key, header, bound });
+        self.touch_vault();
+        Ok(())
+    }
+
+    /// Unlock with key material another Notas process left in the key cache this login
+    /// session. Returns whether there was any; the vault stays locked if not.
+    pub fn unlock_from_key_cache(&mut self) -> Result<bool> {
+        if self.is_unlocked() {
+            return Ok(true);
+        }
+        let Some(provider) = key_cache::provider(self.settings.key_cache) else {
+            return Ok(false);
+        };
+        let Some(secret) = provider.load(&self.key_cache_name())? else {
+            return Ok(false);
+        };
+        if let Err(e) = self.restore_session(secret.as_slice()) {
+            if e.downcast_ref::<std::io::Error>().is_some() {
+                return Err(e);
+            }
+            // Stale (the vault was replaced or rekeyed); the password will recreate it
+            let _ = provider.remove(&self.key_cache_name());
+            return Err(anyhow!("The unlock shared with this login session is out of date. Enter your password."));
+        }
+        Ok(true)
+    }
+
+    /// Remove this vault's entry from the key cache, so every Notas process needs the
+    /// password again. Locking from the UI does this.
+    pub fn forget_cached_key(&self) -> Result<()> {
+        match key_cache::provider(self.settings.key_cache) {
+            Some(provider) => provider.remove(&self.key_cache_name()),
+            None => Ok(()),
+        }
+    }
+
+    // Leave the open session's key material in the key cache, if one is chosen. Failing
+    // to is no reason to fail the unlock.
+    fn cache_key(&self) {
+        let Some(provider) = key_cache::provider(self.settings.key_cache) else {
+            return;
+        };
+        if let Ok(mut secret) = self.session_secret() {
+            let timeout = Duration::from_secs(self.settings.key_cache_minutes.max(1) * 60);
+            let _ = provider.store(&self.key_cache_name(), secret.as_slice(), timeout);
+            secret.as_mut_slice().zeroize();
+        }
+    }
 
+    // Per-vault entry name that doesn't reveal the vault's location
+    fn key_cache_name(&self) -> String {
+        let path = fs::canonicalize(&self.data_path).unwrap_or_else(|_| self.data_path.clone());
+        let digest = Sha256::digest(path.to_string_lossy().as_bytes());
+        digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Failed unlock attempts recorded for the vault on disk
+    pub fn failed_attempts(&self) -> AttemptRecord {
+        if self.vault_exists() {
+            AttemptLog::for_vault(&self.data_path).load()
+        } else {
+            AttemptRecord::default()
+        }
+    }
+
+    /// Failed attempts (or a tampered record) found by the last unlock, once
+    pub fn take_unlock_report(&mut self) -> Option<AttemptRecord> {
+        self.unlock_report.take()
+    }
+
+    // Overwrite the vault with random bytes before removing it, so the ciphertext isn't
+    // simply left in free blocks (best effort: SSDs and copy-on-write filesystems may
+    // keep old copies)
+    fn erase_vault(&self) -> Result<()> {
+        let len = fs::metadata(&self.data_path)?.len() as usize;
+        let mut noise = vec![0u8; len];
+        crypto::fill_random(&mut noise);
+        let mut file = fs::OpenOptions::new().write(true).open(&self.data_path)?;
+        std::io::Write::write_all(&mut file, &noise)?;
+        file.sync_all()?;
+        drop(file);
+        fs::remove_file(&self.data_path)?;
+        Ok(())
+    }
+
+    // Decrypt the vault on disk (creating it if there is none), returning its session
+    fn open_vault(&mut self, master_password: MasterPassword, factors: UnlockFactors) -> Result<Session> {
         // Use SecureBuffer to protect password in memory
         let password_buffer = SecureBuffer::new(master_password.0.clone());
         let password_bytes = password_buffer.as_slice();
-        
-        // ALWAYS use default params - this ensures backward compatibility
-        // and prevents issues from settings corruption
-        let default_params = Argon2Params::default();
 
-        let encrypted_data = match fs::read(&self.data_path) {
-            Ok(bytes) => EncryptedData::from_bytes(&bytes)?,
+        let vault = match fs::read(&self.data_path) {
+            Ok(bytes) => VaultFile::parse(&bytes)?,
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                // New vault - create with default params
-                let (key, salt) = crypto::generate_test_key_with_params(password_bytes, &default_params)?;
-                let test_note_list = NoteList::new();
-                let serialized = bincode::serialize(&test_note_list)?;
-                let encrypted = crypto::encrypt(&key, &salt, &serialized)?;
-
-                if let Some(state) = CRYPTO_STATE.get() {
-                    if let Ok(mut guard) = state.write() {
-                        *guard = Some(CryptoState { key, salt });
-                    }
-                }
+                self.check_password_policy(password_bytes)?;
+                // New vault - random data key wrapped by a password slot with default params
+                let cipher = Self::checked_cipher(self.settings.cipher)?;
+                let key = GuardedKey::random()?;
+                let slot = KeySlot::wrap(SlotKind::Password, password_bytes, factors.keyfile.as_ref(), None, Argon2Params::default(), key.key())?;
+                let header = VaultHeader::new(cipher, vec![slot]);
 
-                fs::write(&self.data_path, encrypted.to_bytes())?;
-                self.note_list = test_note_list;
-                return Ok(());
+                self.note_list = NoteList::new();
+                self.opened = Some(OpenedVault::Main { decoy: DecoyArea::filler(Argon2Params::default()) });
+                self.write_vault(&key, &header)?;
+                return Ok(Session { key, header, bound: BoundFactors { keyfile: factors.keyfile, totp_secret: None } });
             },
             Err(e) => return Err(e.into()),
         };
 
-        // Always use default params for key derivation - ignore settings.argon2_params
-        // This ensures vaults encrypted with default params will always work
-        let key = crypto::derive_key_with_params(password_bytes, &encrypted_data.header.salt, &default_params)?;
-        
-        let decrypted_bytes = crypto::decrypt(&key, &encrypted_data)
-            .map_err(|_| anyhow!("Invalid password or corrupted data."))?;
+        // KDF parameters come from the vault file (defaults for pre-header vaults),
+        // never from settings, so a corrupted settings file cannot lock the user out
+        let session = match vault {
+            VaultFile::Enveloped { header, body, associated_data, decoy } => {
+                // Always try the decoy slot as well, so an attempt takes as long whichever
+                // password matches, and whether or not the file holds a decoy at all
+                let unwrapped = header.unwrap_key(SlotKind::Password, password_bytes, &factors);
+                let decoy_key = decoy.as_ref().map(|decoy| decoy.unwrap_key(password_bytes));
 
-        self.note_list = bincode::deserialize(&decrypted_bytes)?;
-        
-        if let Some(state) = CRYPTO_STATE.get() {
-            if let Ok(mut guard) = state.write() {
-                *guard = Some(CryptoState { 
-                    key, 
-                    salt: encrypted_data.header.salt 
-                });
-            }
-        }
+                match (unwrapped, decoy_key, decoy) {
+                    (Ok((key, totp_secret)), _, decoy) => {
+                        let key = GuardedKey::from_key(key)?;
+                        let mut decrypted_bytes = header.open_body(key.key(), &body, &associated_data)
+                            .map_err(|_| anyhow!("Invalid password or corrupted data."))?;
+                        let note_list = NoteList::from_bytes(&decrypted_bytes);
+                        decrypted_bytes.zeroize();
+                        self.note_list = note_list?;
 
-        Ok(())
+                        let params = header.password_slot().map(|slot| slot.params.clone()).unwrap_or_default();
+                        let decoy = match decoy {
+                            Some(decoy) => decoy,
+                            None => DecoyArea::filler(params),
+                        };
+                        self.opened = Some(OpenedVault::Main { decoy });
+                        Session { key, header, bound: BoundFactors { keyfile: factors.keyfile, totp_secret } }
+                    },
+                    (Err(_), Some(Ok(key)), Some(decoy)) => {
+                        let key = GuardedKey::from_key(key)?;
+                        let mut decrypted_bytes = decoy.open_body(key.key())
+                            .map_err(|_| anyhow!("Invalid password or corrupted data."))?;
+                        let note_list = NoteList::from_bytes(&decrypted_bytes);
+                        decrypted_bytes.zeroize();
+                        self.note_list = note_list?;
+
+                        let mut main = associated_data;
+                        main.extend_from_slice(&body);
+                        self.opened = Some(OpenedVault::Decoy { decoy, main });
+                        Session { key, header, bound: BoundFactors::default() }
+                    },
+                    (Err(e), _, _) => return Err(e),
+                }
+            },
+            VaultFile::Direct(direct) => {
+                let mut decrypted_bytes = direct.decrypt(password_bytes, &factors)?;
+                let note_list = NoteList::from_bytes(&decrypted_bytes);
+                decrypted_bytes.zeroize();
+                self.note_list = note_list?;
+
+                // Migrate to envelope encryption, keeping the vault's KDF parameters
+                let cipher = Self::checked_cipher(self.settings.cipher)?;
+                let key = GuardedKey::random()?;
+                let slot = KeySlot::wrap(SlotKind::Password, password_bytes, factors.keyfile.as_ref(), None, direct.params.clone(), key.key())?;
+                let header = VaultHeader::new(cipher, vec![slot]);
+                self.opened = Some(OpenedVault::Main { decoy: DecoyArea::filler(direct.params) });
+                self.write_vault(&key, &header)?;
+                Session { key, header, bound: BoundFactors { keyfile: factors.keyfile, totp_secret: None } }
+            },
+        };
+
+        Ok(session)
+    }
+
+    /// Whether the vault on disk needs a keyfile in addition to the password
+    pub fn requires_keyfile(&self) -> bool {
+        VaultFile::read(&self.data_path)
+            .map(|vault| vault.requires_keyfile())
+            .unwrap_or(false)
+    }
+
+    /// Whether the vault on disk needs an authenticator code in addition to the password
+    pub fn requires_totp(&self) -> bool {
+        VaultFile::read(&self.data_path)
+            .map(|vault| vault.requires_totp())
+            .unwrap_or(false)
     }
     
     pub fn change_password(&mut self, old_password: MasterPassword, new_password: MasterPassword) -> Result<()> {
-        if !Self::is_unlocked() {
+        if !self.is_unlocked() {
             return Err(anyhow!("Application must be unlocked to change password"));
         }
         
-        // Always use default params for consistency
-        let default_params = Argon2Params::default();
-        
-        // Verify old password first
-        let encrypted_data = fs::read(&self.data_path)?;
-        let encrypted_data = EncryptedData::from_bytes(&encrypted_data)?;
-        
         let old_buffer = SecureBuffer::new(old_password.0.clone());
-        let old_key = crypto::derive_key_with_params(old_buffer.as_slice(), &encrypted_data.header.salt, &default_params)?;
-        
-        // Try to decrypt with old password to verify
-        crypto::decrypt(&old_key, &encrypted_data)
-            .map_err(|_| anyhow!("Current password is incorrect"))?;
-        
-        // Generate new salt and key with new password (using default params)
         let new_buffer = SecureBuffer::new(new_password.0.clone());
-        let (new_key, new_salt) = crypto::generate_test_key_with_params(new_buffer.as_slice(), &default_params)?;
-        
-        // Re-encrypt with new password
-        let serialized = bincode::serialize(&self.note_list)?;
-        let encrypted = crypto::encrypt(&new_key, &new_salt, &serialized)?;
-        fs::write(&self.data_path, encrypted.to_bytes())?;
-        
-        // Update crypto state
-        if let Some(state) = CRYPTO_STATE.get() {
-            if let Ok(mut guard) = state.write() {
-                if let Some(ref mut crypto) = *guard {
-                    crypto.zeroize();
-                }
-                *guard = Some(CryptoState { 
-                    key: new_key, 
-                    salt: new_salt 
-                });
-            }
+        self.check_password_policy(new_buffer.as_slice())?;
+        // From a decoy, only the decoy's own password changes
+        let (key, _, _) = self.current_state()?;
+        if let Some(OpenedVault::Decoy { ref mut decoy, .. }) = self.opened {
+            decoy.unwrap_key(old_buffer.as_slice())
+                .map_err(|_| anyhow!("Current password is incorrect"))?;
+            decoy.rewrap(new_buffer.as_slice(), key.key())?;
+            return self.save_notes();
         }
-        
+        let bound = self.current_state()?.2;
+        self.rewrap_password_slot(old_buffer.as_slice(), new_buffer.as_slice(), bound, None)
+    }
+
+    /// Add, replace or remove (`None`) the keyfile required to unlock the vault
+    pub fn set_keyfile(&mut self, password: MasterPassword, keyfile: Option<Keyfile>) -> Result<()> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to change the keyfile"));
+        }
+
+        let password_buffer = SecureBuffer::new(password.0.clone());
+        let bound = BoundFactors { keyfile, ..self.current_state()?.2 };
+        self.rewrap_password_slot(password_buffer.as_slice(), password_buffer.as_slice(), bound, None)
+    }
+
+    /// Require a code from an authenticator app, enrolled with `secret`, at every unlock.
+    /// `code` must be the app's current code, so a mistyped or unscanned secret can't
+    /// lock the user out.
+    pub fn enable_totp(&mut self, password: MasterPassword, secret: TotpSecret, code: &str) -> Result<()> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to set up two-factor codes"));
+        }
+        if !totp::self_test() {
+            return Err(anyhow!("Two-factor self-test failed; codes would not be accepted"));
+        }
+        if !secret.verify(code, totp::unix_now()) {
+            return Err(anyhow!("Authenticator code is incorrect. Check the time on this computer and your phone."));
+        }
+
+        let password_buffer = SecureBuffer::new(password.0.clone());
+        let bound = BoundFactors { totp_secret: Some(secret), ..self.current_state()?.2 };
+        self.rewrap_password_slot(password_buffer.as_slice(), password_buffer.as_slice(), bound, None)
+    }
+
+    pub fn disable_totp(&mut self, password: MasterPassword) -> Result<()> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to turn off two-factor codes"));
+        }
+
+        let password_buffer = SecureBuffer::new(password.0.clone());
+        let bound = BoundFactors { totp_secret: None, ..self.current_state()?.2 };
+        self.rewrap_password_slot(password_buffer.as_slice(), password_buffer.as_slice(), bound, None)
+    }
+
+    /// Cipher the unlocked vault's notes are encrypted with
+    pub fn vault_cipher(&self) -> Option<Cipher> {
+        self.current_state().ok().map(|(_, header, _)| header.cipher)
+    }
+
+    /// Re-encrypt the notes with `cipher`. The data key and key slots are unchanged,
+    /// so no password is needed.
+    pub fn set_cipher(&mut self, cipher: Cipher) -> Result<()> {
+        let (key, mut header, bound) = self.current_state()?;
+        if header.cipher == cipher {
+            return Ok(());
+        }
+        header.cipher = Self::checked_cipher(cipher)?;
+        self.write_vault(&key, &header)?;
+        self.session = Some(Session { key, header, bound });
         Ok(())
     }
 
-    fn save_notes(&self) -> Result<()> {
-        let (key, salt) = {
-            let state = CRYPTO_STATE.get().ok_or_else(|| anyhow!("Application is locked"))?;
-            let guard = state.read().map_err(|_| anyhow!("Lock poisoned"))?;
-            let crypto = guard.as_ref().ok_or_else(|| anyhow!("Application is locked"))?;
-            (crypto.key.clone(), crypto.salt)
+    // New master passwords have to meet the policy in settings
+    fn check_password_policy(&self, password: &[u8]) -> Result<()> {
+        let password = std::str::from_utf8(password).map_err(|_| anyhow!("Password is not valid text"))?;
+        self.settings.password_policy.check(password)
+    }
+
+    // Refuse to write a vault if the cipher implementations fail their known answers
+    fn checked_cipher(cipher: Cipher) -> Result<Cipher> {
+        if !crypto::self_test() {
+            return Err(anyhow!("Cipher self-test failed; the vault was not written"));
+        }
+        Ok(cipher)
+    }
+
+    /// Whether the vault file exists yet (a new vault is created on first unlock)
+    pub fn vault_exists(&self) -> bool {
+        self.data_path.exists()
+    }
+
+    /// Whether a recovery code can unlock the vault on disk
+    pub fn has_recovery_code(&self) -> bool {
+        match VaultFile::read(&self.data_path) {
+            Ok(VaultFile::Enveloped { header, .. }) => header.slots.iter().any(|slot| slot.kind == SlotKind::RecoveryCode),
+            _ => false,
+        }
+    }
+
+    /// Create a new recovery code, revoking any previous one. The code is returned
+    /// for display once; only a slot wrapping the data key with it is stored.
+    pub fn generate_recovery_code(&mut self) -> Result<String> {
+        let code = recovery::generate();
+        let mut secret = recovery::normalize(&code)?;
+        let result = self.replace_key_slots(SlotKind::RecoveryCode, &secret);
+        secret.zeroize();
+        result.map(|_| code)
+    }
+
+    pub fn revoke_recovery_code(&mut self) -> Result<()> {
+        self.remove_key_slots(SlotKind::RecoveryCode)
+    }
+
+    /// Unlock with a recovery code and replace the forgotten password. The new password
+    /// slot is bound to the keyfile in `factors`, if one was given. Two-factor codes are
+    /// turned off, since a lost phone is a common reason to need the recovery code.
+    pub fn recover(&mut self, code: &str, new_password: MasterPassword, factors: UnlockFactors) -> Result<()> {
+        if self.is_unlocked() {
+            return Err(anyhow!("Vault is already unlocked"));
+        }
+
+        self.check_password_policy(&new_password.0)?;
+
+        let VaultFile::Enveloped { mut header, body, associated_data, decoy } = VaultFile::read(&self.data_path)? else {
+            return Err(anyhow!("This vault has no recovery code"));
+        };
+        if !header.slots.iter().any(|slot| slot.kind == SlotKind::RecoveryCode) {
+            return Err(anyhow!("This vault has no recovery code"));
+        }
+
+        // Recovery codes are guesses like passwords, and count towards the same limit
+        let attempts = AttemptLog::for_vault(&self.data_path);
+        Self::check_attempts(&attempts)?;
+
+        let mut secret = recovery::normalize(code)?;
+        let key = header.unwrap_key(SlotKind::RecoveryCode, &secret, &UnlockFactors::default());
+        secret.zeroize();
+        let key = match key {
+            Ok((key, _)) => GuardedKey::from_key(key)?,
+            Err(_) => return Err(self.record_failed_attempt(&attempts, anyhow!("Recovery code is incorrect"))),
         };
 
-        let serialized = bincode::serialize(&self.note_list)?;
-        let encrypted = crypto::encrypt(&key, &salt, &serialized)?;
-        fs::write(&self.data_path, encrypted.to_bytes())?;
+        let mut decrypted_bytes = header.open_body(key.key(), &body, &associated_data)
+            .map_err(|_| anyhow!("Vault data is corrupted"))?;
+        let note_list = NoteList::from_bytes(&decrypted_bytes);
+        decrypted_bytes.zeroize();
+        self.note_list = note_list?;
 
+        let params = header.password_slot().map(|slot| slot.params.clone()).unwrap_or_default();
+        let decoy = match decoy {
+            Some(decoy) => decoy,
+            None => DecoyArea::filler(params.clone()),
+        };
+        let password_buffer = SecureBuffer::new(new_password.0.clone());
+        let slot = KeySlot::wrap(SlotKind::Password, password_buffer.as_slice(), factors.keyfile.as_ref(), None, params, key.key())?;
+        header.set_slot(slot);
+        self.opened = Some(OpenedVault::Main { decoy });
+        self.write_vault(&key, &header)?;
+        self.session = Some(Session { key, header, bound: BoundFactors { keyfile: factors.keyfile, totp_secret: None } });
+        // Proving ownership with the recovery code clears the failed attempts
+        self.unlock_report = attempts.reset().ok()
+            .filter(|record| record.failures > 0 || record.tampered);
         Ok(())
     }
 
-    pub fn get_notes(&self) -> Vec<super::data::Note> {
-        self.note_list.notes.clone()
+    // Replace every slot of `kind` with one wrapping the data key under `secret`
+    fn replace_key_slots(&mut self, kind: SlotKind, secret: &[u8]) -> Result<()> {
+        let (key, mut header, bound) = self.current_state()?;
+        let slot = KeySlot::wrap(kind, secret, None, None, Argon2Params::default(), key.key())?;
+        header.remove_slots(kind);
+        header.add_slot(slot)?;
+        self.write_vault(&key, &header)?;
+        self.session = Some(Session { key, header, bound });
+        Ok(())
+    }
+
+    /// Revoke every slot of `kind`. The password slot cannot be removed.
+    fn remove_key_slots(&mut self, kind: SlotKind) -> Result<()> {
+        let (key, mut header, bound) = self.current_state()?;
+        header.remove_slots(kind);
+        self.write_vault(&key, &header)?;
+        self.session = Some(Session { key, header, bound });
+        Ok(())
+    }
+
+    // Verify `old_password` against the password slot, then replace the slot with one
+    // wrapping the same data key under `new_password` and the `bound` factors. The notes
+    // are not re-encrypted. `params` defaults to the slot's current KDF parameters.
+    fn rewrap_password_slot(&mut self, old_password: &[u8], new_password: &[u8], bound: BoundFactors, params: Option<Argon2Params>) -> Result<()> {
+        let (key, mut header, old_bound) = self.current_state()?;
+        self.verify_password(&header, old_password, old_bound.keyfile.as_ref())?;
+
+        let current = header.password_slot().ok_or_else(|| anyhow!("Vault has no password slot"))?;
+        let params = params.unwrap_or_else(|| current.params.clone());
+        let slot = KeySlot::wrap(SlotKind::Password, new_password, bound.keyfile.as_ref(), bound.totp_secret.as_ref(), params, key.key())?;
+        header.set_slot(slot);
+        self.write_vault(&key, &header)?;
+        self.session = Some(Session { key, header, bound });
+        // A PIN set up under the old password or factors shouldn't outlive them
+        self.quick_unlock = None;
+        self.cache_key();
+        Ok(())
+    }
+
+    fn current_state(&self) -> Result<(GuardedKey, VaultHeader, BoundFactors)> {
+        let session = self.session.as_ref().ok_or_else(|| anyhow!("Application is locked"))?;
+        Ok((session.key.try_clone()?, session.header.clone(), session.bound.clone()))
+    }
+
+    // Check the password of whichever vault is open. From a decoy, settings changes go
+    // through as usual but only reach the in-memory header: `write_vault` writes the main
+    // vault back as it was read, so nothing shows they didn't stick.
+    fn verify_password(&self, header: &VaultHeader, password: &[u8], keyfile: Option<&Keyfile>) -> Result<()> {
+        let verified = match self.opened {
+            Some(OpenedVault::Decoy { ref decoy, .. }) => decoy.unwrap_key(password).map(drop),
+            _ => header.password_slot()
+                .ok_or_else(|| anyhow!("Vault has no password slot"))?
+                .verify_secret(password, keyfile),
+        };
+        verified.map_err(|_| anyhow!("Current password is incorrect"))
+    }
+
+    fn write_vault(&self, key: &GuardedKey, header: &VaultHeader) -> Result<()> {
+        // Serialize straight into guarded memory; it's wiped when dropped
+        let mut plaintext = GuardedBytes::new(bincode::serialized_size(&self.note_list)? as usize)?;
+        bincode::serialize_into(plaintext.as_mut_slice(), &self.note_list)?;
+        let serialized = plaintext.as_slice();
+        let encoded = match self.opened {
+            Some(OpenedVault::Main { ref decoy }) => VaultFile::encode(header, key.key(), serialized, decoy),
+            Some(OpenedVault::Decoy { ref decoy, ref main }) => {
+                let mut decoy = decoy.clone();
+                decoy.seal_body(key.key(), serialized).map(|_| {
+                    let mut bytes = main.clone();
+                    decoy.write(&mut bytes);
+                    bytes
+                })
+            },
+            None => Err(anyhow!("Application is locked")),
+        };
+        fs::write(&self.data_path, encoded?)?;
+        Ok(())
+    }
+
+    /// Set (or replace) a duress password. It opens a separate, initially empty decoy
+    /// vault stored in the same file; nothing on disk shows whether one is set.
+    pub fn set_duress_password(&mut self, password: MasterPassword, duress_password: MasterPassword) -> Result<()> {
+        let (_, header, bound) = self.current_state()?;
+        let password_buffer = SecureBuffer::new(password.0.clone());
+        let duress_buffer = SecureBuffer::new(duress_password.0.clone());
+        self.verify_password(&header, password_buffer.as_slice(), bound.keyfile.as_ref())?;
+        let slot = header.password_slot().ok_or_else(|| anyhow!("Vault has no password slot"))?;
+        if duress_buffer.as_slice().is_empty() {
+            return Err(anyhow!("Duress password can't be empty"));
+        }
+        if duress_buffer.as_slice() == password_buffer.as_slice() {
+            return Err(anyhow!("Duress password must differ from the vault password"));
+        }
+
+        let key = GuardedKey::random()?;
+        let mut empty = bincode::serialize(&NoteList::new())?;
+        let decoy = DecoyArea::create(duress_buffer.as_slice(), slot.params.clone(), key.key(), &empty);
+        empty.zeroize();
+        self.replace_decoy(decoy?);
+        self.save_notes()
+    }
+
+    /// Replace the decoy area with random bytes, removing any duress password
+    pub fn remove_duress_password(&mut self, password: MasterPassword) -> Result<()> {
+        let (_, header, bound) = self.current_state()?;
+        let password_buffer = SecureBuffer::new(password.0.clone());
+        self.verify_password(&header, password_buffer.as_slice(), bound.keyfile.as_ref())?;
+
+        let slot = header.password_slot().ok_or_else(|| anyhow!("Vault has no password slot"))?;
+        self.replace_decoy(DecoyArea::filler(slot.params.clone()));
+        self.save_notes()
+    }
+
+    // From a decoy, the decoy area in use stays; as with other settings, the change
+    // only appears to be made
+    fn replace_decoy(&mut self, decoy: DecoyArea) {
+        if let Some(OpenedVault::Main { .. }) = self.opened {
+            self.opened = Some(OpenedVault::Main { decoy });
+        }
+    }
+
+    fn save_notes(&self) -> Result<()> {
+        // Borrow the session rather than copying the key out of guarded memory
+        let session = self.session.as_ref().ok_or_else(|| anyhow!("Application is locked"))?;
+        self.write_vault(&session.key, &session.header)
+    }
+
+    pub fn get_notes(&self) -> &[super::data::Note] {
+        &self.note_list.notes
     }
     
     pub fn get_folders(&self) -> Vec<String> {
         self.note_list.folders.clone()
     }
+    
+    // All #hashtags used across notes, sorted case-insensitively
+    pub fn get_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for note in &self.note_list.notes {
+            for tag in note.tags() {
+                if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                    tags.push(tag);
+                }
+            }
+        }
+        tags.sort_by_key(|t| t.to_lowercase());
+        tags
+    }
 
     pub fn create_note(&mut self, title: String, content: String) -> Result<u64> {
+        // Widgets being emptied on lock can fire the editor's auto-create
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
         let note = super::data::Note::new(title, content);
         let id = note.id;
         self.note_list.add_note(note);
@@ -388,6 +1095,20 @@ impl CoreManager {
     }
 
     pub fn update_note(&mut self, id: u64, title: String, content: String, folder: Option<String>) -> Result<()> {
+        // Locked notes keep their content sealed; it can only change while the note is open
+        let content = match self.note_list.notes.iter_mut().find(|n| n.id == id) {
+            Some(super::data::Note { lock: Some(ref mut lock), .. }) => {
+                let open = self.open_note.as_ref()
+                    .filter(|open| open.id == id)
+                    .ok_or_else(|| anyhow!("Note is locked"))?;
+                let mut content = content;
+                let sealed = lock.reseal(open.key.key(), &content);
+                content.zeroize();
+                sealed?;
+                String::new()
+            },
+            _ => content,
+        };
         if self.note_list.update_note(id, title, content, folder) {
             self.save_notes()
         } else {
@@ -396,6 +1117,9 @@ impl CoreManager {
     }
 
     pub fn delete_note(&mut self, id: u64) -> Result<()> {
+        if self.open_note.as_ref().is_some_and(|open| open.id == id) {
+            self.open_note = None;
+        }
         if self.note_list.delete_note(id) {
             self.save_notes()
         } else {
@@ -403,6 +1127,64 @@ impl CoreManager {
         }
     }
     
+    /// Lock a note with its own passphrase. Its content is sealed and cleared; the
+    /// title stays readable so the note can still be listed. The note stays open
+    /// until `relock_note` so unsaved edits in the editor can still be saved.
+    pub fn lock_note(&mut self, id: u64, passphrase: MasterPassword) -> Result<()> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
+        let note = self.note_list.notes.iter_mut()
+            .find(|n| n.id == id)
+            .ok_or_else(|| anyhow!("Note with ID {} not found", id))?;
+        if note.is_locked() {
+            return Err(anyhow!("Note is already locked"));
+        }
+
+        let passphrase_buffer = SecureBuffer::new(passphrase.0.clone());
+        let (lock, key) = NoteLock::seal(passphrase_buffer.as_slice(), &note.content)?;
+        note.content.zeroize();
+        note.lock = Some(lock);
+        self.open_note = Some(OpenNote { id, key: GuardedKey::from_key(key)? });
+        self.save_notes()
+    }
+
+    /// Decrypt a locked note for the editor. The note stays open (edits are re-sealed)
+    /// until `relock_note`, another note is opened, or the app locks.
+    pub fn unlock_note(&mut self, id: u64, passphrase: MasterPassword) -> Result<String> {
+        self.open_note = None;
+        let lock = self.note_list.notes.iter()
+            .find(|n| n.id == id)
+            .ok_or_else(|| anyhow!("Note with ID {} not found", id))?
+            .lock.as_ref()
+            .ok_or_else(|| anyhow!("Note is not locked"))?;
+
+        let passphrase_buffer = SecureBuffer::new(passphrase.0.clone());
+        let (content, key) = lock.open(passphrase_buffer.as_slice())?;
+        self.open_note = Some(OpenNote { id, key: GuardedKey::from_key(key)? });
+        Ok(content)
+    }
+
+    /// Forget the key of the open locked note
+    pub fn relock_note(&mut self) {
+        self.open_note = None;
+    }
+
+    pub fn is_note_open(&self, id: u64) -> bool {
+        self.open_note.as_ref().is_some_and(|open| open.id == id)
+    }
+
+    /// Remove a note's lock, storing its content with the rest of the vault again
+    pub fn remove_note_lock(&mut self, id: u64, passphrase: MasterPassword) -> Result<()> {
+        let content = self.unlock_note(id, passphrase)?;
+        self.open_note = None;
+        if let Some(note) = self.note_list.notes.iter_mut().find(|n| n.id == id) {
+            note.content = content.into();
+            note.lock = None;
+        }
+        self.save_notes()
+    }
+
     pub fn toggle_pin(&mut self, id: u64) -> Result<bool> {
         if self.note_list.toggle_pin(id) {
             self.save_notes()?;
@@ -427,49 +1209,273 @@ impl CoreManager {
         self.save_notes()
     }
 
-    pub fn export_all_encrypted(&self, export_path: &PathBuf) -> Result<()> {
-        let (key, salt) = {
-            let state = CRYPTO_STATE.get().ok_or_else(|| anyhow!("Application is locked"))?;
-            let guard = state.read().map_err(|_| anyhow!("Lock poisoned"))?;
-            let crypto = guard.as_ref().ok_or_else(|| anyhow!("Application is locked"))?;
-            (crypto.key.clone(), crypto.salt)
+    /// Export notes to an archive protected by its own passphrase. The archive gets a
+    /// fresh salt and records its KDF parameters, so it is independent of the vault key.
+    pub fn export_encrypted(&self, export_path: &PathBuf, passphrase: MasterPassword, scope: &ExportScope) -> Result<ExportMetadata> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
+
+        let payload = ExportPayload::new(scope.filter(&self.note_list));
+        if payload.note_list.notes.is_empty() {
+            return Err(anyhow!("No notes match the export selection"));
+        }
+        let metadata = payload.metadata.clone();
+
+        let passphrase_buffer = SecureBuffer::new(passphrase.0.clone());
+        let mut serialized = bincode::serialize(&payload)?;
+        let sealed = archive::seal(archive::EXPORT_MAGIC, passphrase_buffer.as_slice(), &self.settings.argon2_params, &serialized);
+        serialized.zeroize();
+        fs::write(export_path, sealed?)?;
+
+        Ok(metadata)
+    }
+
+    /// The vault's age public key ("age1..."), creating the key pair on first use
+    pub fn public_key(&mut self) -> Result<String> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
+        if self.note_list.identity.is_none() {
+            self.note_list.identity = Some(recipients::generate_identity());
+            self.save_notes()?;
+        }
+        let identity = self.note_list.identity.as_deref().unwrap_or_default();
+        recipients::public_key(identity)
+    }
+
+    /// Export notes as an age file encrypted to the given X25519 public keys. Any one
+    /// recipient can decrypt it with their own vault (or the `age` CLI); no passphrase
+    /// needs to be exchanged.
+    pub fn export_to_recipients(&self, export_path: &PathBuf, recipient_keys: &str, scope: &ExportScope, armored: bool) -> Result<ExportMetadata> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
+        let recipient_list = recipients::parse_recipients(recipient_keys)?;
+
+        let payload = ExportPayload::new(scope.filter(&self.note_list));
+        if payload.note_list.notes.is_empty() {
+            return Err(anyhow!("No notes match the export selection"));
+        }
+        let metadata = payload.metadata.clone();
+
+        let mut serialized = bincode::serialize(&payload)?;
+        let encrypted = recipients::encrypt(&recipient_list, &serialized, armored);
+        serialized.zeroize();
+        fs::write(export_path, encrypted?)?;
+
+        Ok(metadata)
+    }
+
+    /// Whether a file is an age archive (opened with the vault key, no passphrase)
+    pub fn is_recipient_file(path: &PathBuf) -> bool {
+        let mut header = [0u8; 64];
+        let read = fs::File::open(path)
+            .and_then(|mut file| std::io::Read::read(&mut file, &mut header))
+            .unwrap_or(0);
+        recipients::is_age_file(&header[..read])
+    }
+
+    /// Seal a single note with a one-off passphrase, as raw bytes or ASCII armor
+    pub fn share_note(&self, id: u64, passphrase: MasterPassword, armored: bool) -> Result<Vec<u8>> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application is locked"));
+        }
+        let note = self.note_list.notes.iter()
+            .find(|n| n.id == id)
+            .ok_or_else(|| anyhow!("Note with ID {} not found", id))?;
+        if note.is_locked() {
+            return Err(anyhow!("Remove the note's lock before sharing it"));
+        }
+
+        let shared = SharedNote::new(note.clone());
+        let passphrase_buffer = SecureBuffer::new(passphrase.0.clone());
+        let mut serialized = bincode::serialize(&shared)?;
+        let sealed = archive::seal(archive::SHARE_MAGIC, passphrase_buffer.as_slice(), &self.settings.argon2_params, &serialized);
+        serialized.zeroize();
+
+        let sealed = sealed?;
+        Ok(if armored { archive::armor(&sealed).into_bytes() } else { sealed })
+    }
+
+    /// Decrypt a shared note (file contents or pasted armor) without adding it to the vault
+    pub fn open_shared_note(data: &[u8], passphrase: MasterPassword) -> Result<super::data::Note> {
+        let sealed = if archive::is_armored(data) {
+            archive::dearmor(&String::from_utf8_lossy(data))?
+        } else {
+            data.to_vec()
         };
+        if !archive::has_magic(&sealed, archive::SHARE_MAGIC) {
+            return Err(anyhow!("Not a Notas shared note"));
+        }
 
-        let serialized = bincode::serialize(&self.note_list)?;
-        let encrypted = crypto::encrypt(&key, &salt, &serialized)?;
-        fs::write(export_path, encrypted.to_bytes())?;
+        let passphrase_buffer = SecureBuffer::new(passphrase.0.clone());
+        let mut decrypted = archive::open(archive::SHARE_MAGIC, passphrase_buffer.as_slice(), &sealed)?;
+        let shared = SharedNote::from_bytes(&decrypted);
+        decrypted.zeroize();
+        let mut shared = shared?;
+        Ok(std::mem::replace(&mut shared.note, super::data::Note::new(String::new(), String::new())))
+    }
 
-        Ok(())
+    /// Add a note received via `open_shared_note` to the vault
+    pub fn add_shared_note(&mut self, mut note: super::data::Note) -> Result<u64> {
+        // Shared notes keep their content but never collide with local IDs
+        if self.note_list.notes.iter().any(|n| n.id == note.id) {
+            note.id = super::data::Note::next_id();
+        }
+        note.pinned = false;
+        if let Some(ref folder) = note.folder {
+            self.note_list.add_folder(folder.clone());
+        }
+        let id = note.id;
+        self.note_list.add_note(note);
+        self.save_notes()?;
+        Ok(id)
+    }
+
+    /// Import a Notas backup using the default merge choices (see `ImportStatus::default_action`)
+    #[allow(dead_code)]
+    pub fn import_encrypted(&mut self, import_path: &PathBuf, master_password: MasterPassword) -> Result<ImportSummary> {
+        let preview = self.preview_encrypted_import(import_path, master_password)?;
+        let actions = preview.default_actions();
+        self.apply_import(preview, &actions)
     }
 
-    pub fn import_encrypted(&mut self, import_path: &PathBuf, master_password: MasterPassword) -> Result<()> {
+    /// Decrypt a Notas backup and classify its notes against the vault without changing anything
+    pub fn preview_encrypted_import(&self, import_path: &PathBuf, master_password: MasterPassword) -> Result<ImportPreview> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to import notes"));
+        }
+
         let password_buffer = SecureBuffer::new(master_password.0.clone());
         
-        let encrypted_data = fs::read(import_path).map_err(|e| anyhow!("Failed to read import file: {}", e))?;
-        let encrypted_data = EncryptedData::from_bytes(&encrypted_data)?;
+        let file_bytes = fs::read(import_path).map_err(|e| anyhow!("Failed to read import file: {}", e))?;
 
-        // Try with default params for imported files
-        let key = crypto::derive_key(password_buffer.as_slice(), &encrypted_data.header.salt)?;
-        let decrypted_bytes = crypto::decrypt(&key, &encrypted_data)?;
-        let imported_note_list: NoteList = bincode::deserialize(&decrypted_bytes)?;
+        let (mut imported_note_list, metadata) = if archive::has_magic(&file_bytes, archive::EXPORT_MAGIC) {
+            let mut decrypted_bytes = archive::open(archive::EXPORT_MAGIC, password_buffer.as_slice(), &file_bytes)?;
+            let payload = ExportPayload::from_bytes(&decrypted_bytes);
+            decrypted_bytes.zeroize();
+            let payload = payload?;
+            (payload.note_list, Some(payload.metadata))
+        } else {
+            // Older exports are a copy of the vault file itself
+            let vault = VaultFile::parse(&file_bytes)?;
+            if vault.requires_keyfile() {
+                return Err(anyhow!("Vaults protected by a keyfile cannot be imported"));
+            }
+            if vault.requires_totp() {
+                return Err(anyhow!("Vaults protected by two-factor codes cannot be imported"));
+            }
+            let no_factors = UnlockFactors::default();
+            let mut decrypted_bytes = match vault {
+                VaultFile::Direct(direct) => direct.decrypt(password_buffer.as_slice(), &no_factors)?,
+                VaultFile::Enveloped { header, body, associated_data, .. } => {
+                    let key = GuardedKey::from_key(header.unwrap_key(SlotKind::Password, password_buffer.as_slice(), &no_factors)?.0)?;
+                    header.open_body(key.key(), &body, &associated_data)?
+                },
+            };
+            let note_list = NoteList::from_bytes(&decrypted_bytes);
+            decrypted_bytes.zeroize();
+            (note_list?, None)
+        };
 
-        // Import folders
-        for folder in &imported_note_list.folders {
-            self.note_list.add_folder(folder.clone());
+        Ok(self.preview_note_list(&mut imported_note_list, metadata))
+    }
+
+    /// Decrypt an age archive encrypted to this vault's public key and classify its notes
+    pub fn preview_recipient_import(&self, import_path: &PathBuf) -> Result<ImportPreview> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to import notes"));
         }
+        let identity = self.note_list.identity.as_deref()
+            .ok_or_else(|| anyhow!("This vault has no public key yet, so nothing can have been encrypted to it"))?;
 
-        for mut note in imported_note_list.notes.clone() {
-            // If the imported note's ID already exists in the vault, assign a new
-            // unique ID so the existing note is not silently shadowed or clobbered.
-            if self.note_list.notes.iter().any(|n| n.id == note.id) {
-                let seq = super::data::ID_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed) & 0xF_FFFF;
-                note.id = (chrono::Utc::now().timestamp_millis() as u64) << 20 | seq;
+        let file_bytes = fs::read(import_path).map_err(|e| anyhow!("Failed to read import file: {}", e))?;
+        let mut decrypted_bytes = recipients::decrypt(identity, &file_bytes)?;
+        let payload = ExportPayload::from_bytes(&decrypted_bytes);
+        decrypted_bytes.zeroize();
+        let mut payload = payload.map_err(|_| anyhow!("File does not contain Notas notes"))?;
+
+        let metadata = payload.metadata.clone();
+        Ok(self.preview_note_list(&mut payload.note_list, Some(metadata)))
+    }
+
+    fn preview_note_list(&self, imported_note_list: &mut NoteList, metadata: Option<ExportMetadata>) -> ImportPreview {
+        let imported = ImportedNotes {
+            notes: std::mem::take(&mut imported_note_list.notes),
+            folders: std::mem::take(&mut imported_note_list.folders),
+            skipped: 0,
+        };
+        let mut preview = ImportPreview::new(imported, &self.note_list.notes);
+        preview.metadata = metadata;
+        preview
+    }
+
+    /// Parse a third-party export and classify its notes against the vault
+    pub fn preview_source_import(&self, source: &ImportSource) -> Result<ImportPreview> {
+        if !self.is_unlocked() {
+            return Err(anyhow!("Application must be unlocked to import notes"));
+        }
+
+        let imported = import::read_source(source)?;
+        Ok(ImportPreview::new(imported, &self.note_list.notes))
+    }
+
+    /// Merge a previewed import, applying one action per entry
+    pub fn apply_import(&mut self, mut preview: ImportPreview, actions: &[ImportAction]) -> Result<ImportSummary> {
+        if actions.len() != preview.entries.len() {
+            return Err(anyhow!("Import choices do not match the preview"));
+        }
+
+        let mut summary = ImportSummary {
+            skipped: preview.skipped,
+            ..Default::default()
+        };
+
+        for (entry, action) in std::mem::take(&mut preview.entries).into_iter().zip(actions) {
+            let ImportEntry { mut note, status, existing_id } = entry;
+            match (action, existing_id) {
+                (ImportAction::Skip, _) => {
+                    if status == ImportStatus::Identical {
+                        summary.duplicates += 1;
+                    } else {
+                        summary.skipped += 1;
+                    }
+                    note.zeroize();
+                    continue;
+                },
+                (ImportAction::Overwrite, Some(id)) => {
+                    if let Some(ref folder) = note.folder {
+                        self.note_list.add_folder(folder.clone());
+                    }
+                    // The open note's key doesn't belong to the replacement's lock
+                    if self.is_note_open(id) {
+                        self.open_note = None;
+                    }
+                    self.note_list.replace_note(id, note);
+                },
+                (ImportAction::KeepBoth, _) | (ImportAction::Overwrite, None) => {
+                    // If the imported note's ID already exists in the vault, assign a new
+                    // unique ID so the existing note is not silently shadowed or clobbered.
+                    if self.note_list.notes.iter().any(|n| n.id == note.id) {
+                        note.id = super::data::Note::next_id();
+                    }
+                    if let Some(ref folder) = note.folder {
+                        self.note_list.add_folder(folder.clone());
+                    }
+                    self.note_list.add_note(note);
+                },
             }
-            self.note_list.add_note(note);
+            summary.imported += 1;
+        }
+
+        // Keep empty folders from the import as well
+        for folder in &preview.folders {
+            self.note_list.add_folder(folder.clone());
         }
         
         self.save_notes()?;
-        Ok(())
+        Ok(summary)
     }
 }
 
@@ -1,3 +1,16 @@
 pub mod data;
 pub mod crypto;
 pub mod manager;
+pub mod import;
+pub mod archive;
+pub mod recipients;
+pub mod vault;
+pub mod recovery;
+pub mod totp;
+pub mod note_lock;
+pub mod attempts;
+pub mod strength;
+pub mod guarded;
+pub mod quick_unlock;
+pub mod key_cache;
+pub mod secrets;
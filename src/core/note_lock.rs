@@ -0,0 +1,53 @@
+use aes_gcm::{Aes256Gcm, Key};
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Deserialize};
+use zeroize::Zeroize;
+
+use super::{
+    crypto::{self, SALT_LEN},
+    data::Argon2Params,
+};
+
+// Content of a note locked with its own passphrase. The vault key alone can't read it:
+// the content is sealed under a key derived from the note passphrase, and the note
+// itself keeps only its title and an empty content string while locked.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NoteLock {
+    pub params: Argon2Params,
+    pub salt: [u8; SALT_LEN],
+    // nonce | tag | content (AES-256-GCM)
+    pub sealed: Vec<u8>,
+}
+
+impl NoteLock {
+    // Seal `content` under a fresh salt; returns the lock and the derived key so the
+    // caller can keep the note open
+    pub fn seal(passphrase: &[u8], content: &str) -> Result<(Self, Key<Aes256Gcm>)> {
+        let params = Argon2Params::default();
+        let salt = crypto::generate_salt();
+        let key = crypto::derive_key_with_params(passphrase, &salt, &params)?;
+        let sealed = crypto::seal(&key, content.as_bytes())?;
+        Ok((Self { params, salt, sealed }, key))
+    }
+
+    // Re-seal edited content under a key returned by `seal` or `open`
+    pub fn reseal(&mut self, key: &Key<Aes256Gcm>, content: &str) -> Result<()> {
+        self.sealed = crypto::seal(key, content.as_bytes())?;
+        Ok(())
+    }
+
+    // Decrypt the content, returning it with the derived key
+    pub fn open(&self, passphrase: &[u8]) -> Result<(String, Key<Aes256Gcm>)> {
+        let key = crypto::derive_key_with_params(passphrase, &self.salt, &self.params)?;
+        let content = self.open_with_key(&key)?;
+        Ok((content, key))
+    }
+
+    pub fn open_with_key(&self, key: &Key<Aes256Gcm>) -> Result<String> {
+        let mut bytes = crypto::open(key, &self.sealed)
+            .map_err(|_| anyhow!("Wrong note passphrase"))?;
+        let content = String::from_utf8(bytes.clone());
+        bytes.zeroize();
+        content.map_err(|_| anyhow!("Locked note is corrupted"))
+    }
+}
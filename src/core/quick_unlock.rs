@@ -0,0 +1,98 @@
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use zeroize::Zeroize;
+
+use super::{
+    crypto::{self, SALT_LEN},
+    data::Argon2Params,
+    guarded::{GuardedBytes, GuardedKey},
+};
+
+/// Wrong PINs allowed before the password is required again
+pub const MAX_TRIES: u8 = 3;
+pub const MIN_PIN_LEN: usize = 4;
+
+// Key material of an unlocked vault, sealed in memory under a key derived from a short
+// PIN, so the vault can be reopened after an auto-lock without the full password. It is
+// never written to disk, and it's given up for good after MAX_TRIES wrong PINs or once
+// it expires. A PIN alone is easy to brute-force, so the retry limit is what protects it.
+pub struct QuickUnlock {
+    salt: [u8; SALT_LEN],
+    // nonce | tag | secret (AES-256-GCM)
+    sealed: GuardedBytes,
+    tries_left: u8,
+    // Time since boot, suspend included (see `boot_time`)
+    expires: Duration,
+}
+
+impl QuickUnlock {
+    pub fn seal(pin: &str, secret: &[u8], valid_for: Duration) -> Result<Self> {
+        if pin.chars().count() < MIN_PIN_LEN {
+            return Err(anyhow!("PIN must be at least {} characters", MIN_PIN_LEN));
+        }
+        let salt = crypto::generate_salt();
+        let key = Self::derive_key(pin, &salt)?;
+        let mut sealed = crypto::seal(key.key(), secret)?;
+        let guarded = GuardedBytes::from_slice(&sealed);
+        sealed.zeroize();
+        Ok(Self { salt, sealed: guarded?, tries_left: MAX_TRIES, expires: boot_time() + valid_for })
+    }
+
+    // Decrypt the secret. A wrong PIN uses up a try; check `tries_left` afterwards.
+    pub fn open(&mut self, pin: &str) -> Result<GuardedBytes> {
+        if self.tries_left == 0 || self.is_expired() {
+            return Err(anyhow!("Quick unlock is no longer available"));
+        }
+        let key = Self::derive_key(pin, &self.salt)?;
+        match crypto::open(key.key(), self.sealed.as_slice()) {
+            Ok(mut secret) => {
+                let guarded = GuardedBytes::from_slice(&secret);
+                secret.zeroize();
+                guarded
+            },
+            Err(_) => {
+                self.tries_left -= 1;
+                Err(anyhow!("Incorrect PIN"))
+            },
+        }
+    }
+
+    pub fn tries_left(&self) -> u8 {
+        self.tries_left
+    }
+
+    pub fn is_expired(&self) -> bool {
+        boot_time() >= self.expires
+    }
+
+    fn derive_key(pin: &str, salt: &[u8; SALT_LEN]) -> Result<GuardedKey> {
+        crypto::derive_key_with_params(pin.as_bytes(), salt, &Argon2Params::default()).and_then(GuardedKey::from_key)
+    }
+}
+
+// CLOCK_BOOTTIME keeps counting while the machine is suspended, unlike `Instant`
+// (CLOCK_MONOTONIC), so a quick unlock can't outlive its minutes by a night asleep. It
+// doesn't follow changes to the wall clock either.
+fn boot_time() -> Duration {
+    let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // Can't fail for a valid clock and pointer
+    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiry_follows_the_boot_clock() {
+        let mut spent = QuickUnlock::seal("1234", b"secret", Duration::ZERO).unwrap();
+        assert!(spent.is_expired());
+        assert!(spent.open("1234").is_err());
+
+        let mut armed = QuickUnlock::seal("1234", b"secret", Duration::from_secs(60)).unwrap();
+        assert!(!armed.is_expired());
+        assert!(armed.expires - boot_time() <= Duration::from_secs(60));
+        assert_eq!(armed.open("1234").unwrap().as_slice(), b"secret");
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{Read, Write};
+use std::str::FromStr;
+use anyhow::{Result, anyhow};
+use age::armor::{ArmoredReader, ArmoredWriter, Format};
+use age::secrecy::ExposeSecret;
+
+// Public-key sharing uses the age file format (https://age-encryption.org/v1), so
+// archives can be exchanged with the `age` CLI and other age implementations.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+const AGE_ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+// Fresh X25519 identity, returned in its "AGE-SECRET-KEY-1..." encoding
+pub fn generate_identity() -> String {
+    age::x25519::Identity::generate().to_string().expose_secret().to_string()
+}
+
+// The "age1..." public key for a stored identity
+pub fn public_key(identity: &str) -> Result<String> {
+    let identity = age::x25519::Identity::from_str(identity)
+        .map_err(|_| anyhow!("Vault identity is corrupted"))?;
+    Ok(identity.to_public().to_string())
+}
+
+// Split user input (one key per line, or separated by commas/spaces) into recipients.
+// Lines starting with '#' are comments, as in age recipient files.
+pub fn parse_recipients(text: &str) -> Result<Vec<age::x25519::Recipient>> {
+    let mut recipients = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        for key in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|k| !k.is_empty()) {
+            let recipient = age::x25519::Recipient::from_str(key)
+                .map_err(|_| anyhow!("Invalid public key: {}", key))?;
+            recipients.push(recipient);
+        }
+    }
+    if recipients.is_empty() {
+        return Err(anyhow!("Enter at least one recipient public key"));
+    }
+    Ok(recipients)
+}
+
+pub fn is_age_file(bytes: &[u8]) -> bool {
+    if bytes.starts_with(AGE_MAGIC) {
+        return true;
+    }
+    // Armored files may be preceded by whitespace
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+    bytes[start..].starts_with(AGE_ARMOR_BEGIN.as_bytes())
+}
+
+// Encrypt `plaintext` so that any one of `recipients` can decrypt it
+pub fn encrypt(recipients: &[age::x25519::Recipient], plaintext: &[u8], armored: bool) -> Result<Vec<u8>> {
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))
+        .map_err(|e| anyhow!("Failed to encrypt to recipients: {}", e))?;
+
+    let format = if armored { Format::AsciiArmor } else { Format::Binary };
+    let mut output = Vec::with_capacity(plaintext.len() + 512);
+    let armor = ArmoredWriter::wrap_output(&mut output, format)?;
+    let mut writer = encryptor.wrap_output(armor)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?.finish()?;
+    Ok(output)
+}
+
+// Decrypt an age file (binary or armored) with the vault identity
+pub fn decrypt(identity: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+    let identity = age::x25519::Identity::from_str(identity)
+        .map_err(|_| anyhow!("Vault identity is corrupted"))?;
+
+    let decryptor = age::Decryptor::new(ArmoredReader::new(bytes))
+        .map_err(|e| anyhow!("Not a valid age file: {}", e))?;
+    if decryptor.is_scrypt() {
+        return Err(anyhow!("This file is passphrase-encrypted with age, not encrypted to your public key"));
+    }
+    let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity))
+        .map_err(|_| anyhow!("This file was not encrypted to your public key"))?;
+
+    let mut plaintext = Vec::new();
+    reader.read_to_end(&mut plaintext)
+        .map_err(|_| anyhow!("Encrypted file is corrupted"))?;
+    Ok(plaintext)
+}
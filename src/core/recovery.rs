@@ -0,0 +1,73 @@
+use anyhow::{Result, anyhow};
+use chrono::Local;
+use zeroize::Zeroize;
+
+use super::crypto;
+
+// Recovery codes are 25 Crockford base32 characters (125 random bits), shown in groups
+// of five. The alphabet leaves out I, L, O and U, and decoding maps the look-alikes
+// back, so a code copied by hand from paper still works.
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CODE_LEN: usize = 25;
+const GROUP_LEN: usize = 5;
+
+pub fn generate() -> String {
+    let mut random = [0u8; CODE_LEN];
+    crypto::fill_random(&mut random);
+
+    let mut code = String::with_capacity(CODE_LEN + CODE_LEN / GROUP_LEN);
+    for (i, byte) in random.iter().enumerate() {
+        if i > 0 && i % GROUP_LEN == 0 {
+            code.push('-');
+        }
+        // 256 is a multiple of 32, so taking the low 5 bits is unbiased
+        code.push(ALPHABET[(byte & 0x1f) as usize] as char);
+    }
+    random.zeroize();
+    code
+}
+
+// Canonical form of a typed code, used as the key slot secret: separators and
+// whitespace removed, upper case, look-alike characters mapped into the alphabet
+pub fn normalize(input: &str) -> Result<Vec<u8>> {
+    let mut canonical = Vec::with_capacity(CODE_LEN);
+    for c in input.chars() {
+        if c == '-' || c.is_whitespace() {
+            continue;
+        }
+        let c = match c.to_ascii_uppercase() {
+            'O' => '0',
+            'I' | 'L' => '1',
+            c => c,
+        };
+        if !c.is_ascii() || !ALPHABET.contains(&(c as u8)) {
+            canonical.zeroize();
+            return Err(anyhow!("Recovery code contains invalid characters"));
+        }
+        canonical.push(c as u8);
+    }
+    if canonical.len() != CODE_LEN {
+        canonical.zeroize();
+        return Err(anyhow!("Recovery code must be {} characters", CODE_LEN));
+    }
+    Ok(canonical)
+}
+
+// Plain-text sheet for printing or storing offline
+pub fn sheet(code: &str, vault_path: &str) -> String {
+    format!(
+        "NOTAS RECOVERY CODE\n\
+         ===================\n\n\
+         Recovery code:  {}\n\n\
+         Vault:          {}\n\
+         Created:        {}\n\n\
+         Use this code with \"Forgot password?\" on the Notas unlock screen to\n\
+         regain access and choose a new master password.\n\n\
+         Anyone holding this code can open the vault. Keep it offline, somewhere\n\
+         safe and separate from your computer. Generating a new code in\n\
+         Preferences revokes this one.\n",
+        code,
+        vault_path,
+        Local::now().format("%Y-%m-%d %H:%M"),
+    )
+}
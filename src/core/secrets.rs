@@ -0,0 +1,145 @@
+use std::ops::Range;
+
+// Secrets written inside a note, either inline as `{{secret:VALUE}}` or as a fenced block:
+//
+//     ```secret
+//     VALUE
+//     ```
+//
+// The editor shows them masked, and they're kept out of list previews, search and tags.
+// An inline field ends at the first `}}` on its line; a block without its closing fence
+// runs to the end of the note.
+
+const INLINE_OPEN: &str = "{{secret:";
+const INLINE_CLOSE: &str = "}}";
+const FENCE_OPEN: &str = "```secret";
+const FENCE_CLOSE: &str = "```";
+
+/// Stands in for a secret wherever note text is shown outside the editor
+pub const MASK: &str = "••••••";
+
+/// Byte ranges of one secret field in a note's text
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecretSpan {
+    // The whole field, markup included
+    pub field: Range<usize>,
+    // The secret itself
+    pub value: Range<usize>,
+}
+
+pub fn find_secrets(text: &str) -> Vec<SecretSpan> {
+    let mut spans = Vec::new();
+    // Start of the open fenced block and of its first line
+    let mut block: Option<(usize, usize)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        if let Some((field_start, value_start)) = block {
+            if line.trim() == FENCE_CLOSE {
+                // Up to the line break before the closing fence
+                let value_end = value_start + text[value_start..start].trim_end_matches(['\n', '\r']).len();
+                if value_end > value_start {
+                    spans.push(SecretSpan { field: field_start..start + line.len(), value: value_start..value_end });
+                }
+                block = None;
+            }
+            continue;
+        }
+        if line.trim() == FENCE_OPEN {
+            block = Some((start, offset));
+            continue;
+        }
+
+        let mut from = 0;
+        while let Some(open) = line[from..].find(INLINE_OPEN) {
+            let value_start = from + open + INLINE_OPEN.len();
+            let Some(close) = line[value_start..].find(INLINE_CLOSE) else { break };
+            let value_end = value_start + close;
+            if value_end > value_start {
+                spans.push(SecretSpan {
+                    field: start + from + open..start + value_end + INLINE_CLOSE.len(),
+                    value: start + value_start..start + value_end,
+                });
+            }
+            from = value_end + INLINE_CLOSE.len();
+        }
+    }
+
+    if let Some((field_start, value_start)) = block {
+        if value_start < text.len() {
+            spans.push(SecretSpan { field: field_start..text.len(), value: value_start..text.len() });
+        }
+    }
+    spans
+}
+
+/// The text with every secret field, markup included, replaced by `MASK`
+pub fn mask_secrets(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut last = 0;
+    for span in find_secrets(text) {
+        masked.push_str(&text[last..span.field.start]);
+        masked.push_str(MASK);
+        last = span.field.end;
+    }
+    masked.push_str(&text[last..]);
+    masked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(text: &str) -> Vec<&str> {
+        find_secrets(text).into_iter().map(|span| &text[span.value]).collect()
+    }
+
+    #[test]
+    fn several_inline_fields_on_one_line() {
+        let text = "user {{secret:alice}} pass {{secret:hunter2}} pin {{secret:1234}}";
+        let spans = find_secrets(text);
+        assert_eq!(values(text), ["alice", "hunter2", "1234"]);
+        assert_eq!(&text[spans[1].field.clone()], "{{secret:hunter2}}");
+        assert_eq!(mask_secrets(text), format!("user {MASK} pass {MASK} pin {MASK}"));
+    }
+
+    #[test]
+    fn inline_field_ends_at_its_line() {
+        let text = "{{secret:open\nnext}} line {{secret:shut}}";
+        assert_eq!(values(text), ["shut"]);
+    }
+
+    #[test]
+    fn empty_values_are_skipped() {
+        let text = "{{secret:}} and\n```secret\n```\nafter {{secret:x}}";
+        assert_eq!(values(text), ["x"]);
+        assert_eq!(mask_secrets(text), format!("{{{{secret:}}}} and\n```secret\n```\nafter {MASK}"));
+    }
+
+    #[test]
+    fn fenced_block_keeps_its_lines() {
+        let text = "before\n```secret\nline one\nline two\n```\nafter";
+        let spans = find_secrets(text);
+        assert_eq!(values(text), ["line one\nline two"]);
+        assert_eq!(&text[spans[0].field.clone()], "```secret\nline one\nline two\n```");
+        assert_eq!(mask_secrets(text), format!("before\n{MASK}\nafter"));
+    }
+
+    #[test]
+    fn unclosed_fence_runs_to_the_end() {
+        let text = "notes\n```secret\nkey one\n{{secret:inside}}\n";
+        let spans = find_secrets(text);
+        assert_eq!(spans.len(), 1);
+        assert_eq!(&text[spans[0].value.clone()], "key one\n{{secret:inside}}\n");
+        assert_eq!(spans[0].field.end, text.len());
+        assert_eq!(mask_secrets(text), format!("notes\n{MASK}"));
+
+        // Nothing after the opening fence: nothing to hide
+        assert!(find_secrets("```secret\n").is_empty());
+        assert!(find_secrets("```secret").is_empty());
+    }
+}
@@ -0,0 +1,668 @@
+use std::collections::HashMap;
+use chrono::Datelike;
+use once_cell::sync::Lazy;
+
+// Password strength estimate in the style of zxcvbn: the password is split into the
+// cheapest sequence of patterns an attacker would try (common passwords and words,
+// keyboard runs, sequences, repeats, dates), and the guesses needed for that sequence
+// give the score. Brute force covers whatever no pattern explains.
+
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 10_000.0;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+const MIN_YEAR_SPACE: f64 = 20.0;
+// Guesses per second against the vault's slow key derivation
+const OFFLINE_GUESSES_PER_SECOND: f64 = 10_000.0;
+// Longer input is scored on this prefix alone, keeping the estimate quick as you type
+const MAX_ANALYZED_LEN: usize = 100;
+
+static PASSWORDS: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| ranked(include_str!("wordlists/passwords.txt")));
+static ENGLISH: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| ranked(include_str!("wordlists/english.txt")));
+
+fn ranked(list: &'static str) -> HashMap<&'static str, usize> {
+    list.lines().filter(|w| !w.is_empty()).enumerate().map(|(i, w)| (w, i + 1)).collect()
+}
+
+const KEYBOARD_ROWS: [(&str, &str); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+"),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|"),
+    ("asdfghjkl;'", "ASDFGHJKL:\""),
+    ("zxcvbnm,./", "ZXCVBNM<>?"),
+];
+const KEYBOARD_KEYS: f64 = 94.0;
+const KEYBOARD_AVERAGE_DEGREE: f64 = 4.6;
+
+const L33T_TABLE: [(char, char); 15] = [
+    ('4', 'a'), ('@', 'a'), ('8', 'b'), ('(', 'c'), ('3', 'e'), ('6', 'g'), ('9', 'g'),
+    ('!', 'i'), ('|', 'i'), ('0', 'o'), ('$', 's'), ('5', 's'), ('7', 't'), ('+', 't'), ('2', 'z'),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Dictionary { rank: usize, common_password: bool, reversed: bool, l33t: bool, whole: bool },
+    Spatial { turns: usize, shifted: usize },
+    Sequence,
+    Repeat { base_len: usize },
+    Date { year_only: bool, recent: bool },
+    Bruteforce,
+}
+
+#[derive(Debug, Clone)]
+struct Match {
+    i: usize,
+    j: usize,
+    pattern: Pattern,
+    guesses: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable)
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub warning: Option<&'static str>,
+    pub suggestions: Vec<&'static str>,
+}
+
+impl Strength {
+    pub fn label(&self) -> &'static str {
+        score_label(self.score)
+    }
+
+    // Rough time to guess offline, given the slow key derivation
+    pub fn crack_time(&self) -> String {
+        let seconds = 10f64.powf(self.guesses_log10) / OFFLINE_GUESSES_PER_SECOND;
+        let units = [(60.0, "second"), (60.0, "minute"), (24.0, "hour"), (30.0, "day"), (12.0, "month"), (100.0, "year")];
+        if seconds < 1.0 {
+            return "less than a second".to_string();
+        }
+        let mut value = seconds;
+        for (size, name) in units {
+            if value < size {
+                let n = value.round() as u64;
+                return format!("{} {}{}", n, name, if n == 1 { "" } else { "s" });
+            }
+            value /= size;
+        }
+        "centuries".to_string()
+    }
+}
+
+pub fn score_label(score: u8) -> &'static str {
+    match score {
+        0 => "Very weak",
+        1 => "Weak",
+        2 => "Fair",
+        3 => "Strong",
+        _ => "Very strong",
+    }
+}
+
+pub fn estimate(password: &str) -> Strength {
+    estimate_in(password, chrono::Local::now().year())
+}
+
+// Estimate as of `current_year`, which recent-year and date guesses are counted from
+fn estimate_in(password: &str, current_year: i32) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+    if chars.is_empty() {
+        return Strength {
+            score: 0,
+            guesses_log10: 0.0,
+            warning: None,
+            suggestions: vec!["Use a few words, avoid common phrases", "No need for symbols, digits, or uppercase letters"],
+        };
+    }
+    let analyzed = &chars[..chars.len().min(MAX_ANALYZED_LEN)];
+    let (guesses, sequence) = most_guessable(analyzed, current_year);
+    let guesses_log10 = guesses.log10();
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    let (warning, suggestions) = feedback(score, &sequence, analyzed);
+    Strength { score, guesses_log10, warning, suggestions }
+}
+
+// Cheapest split of the password into matches, as total guesses and the sequence
+fn most_guessable(chars: &[char], current_year: i32) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    let mut matches = omnimatch(chars, current_year);
+    for m in &mut matches {
+        let min = if m.i == 0 && m.j == n - 1 {
+            1.0
+        } else if m.j == m.i {
+            MIN_SUBMATCH_GUESSES_SINGLE_CHAR
+        } else {
+            MIN_SUBMATCH_GUESSES_MULTI_CHAR
+        };
+        m.guesses = m.guesses.max(min);
+    }
+
+    // best[k][l]: cheapest sequence of l matches covering chars[..=k], as
+    // (product of guesses, total guesses, last match)
+    let mut best: Vec<HashMap<usize, (f64, f64, Match)>> = vec![HashMap::new(); n];
+    let update = |best: &mut Vec<HashMap<usize, (f64, f64, Match)>>, m: Match, l: usize, product: f64| {
+        let k = m.j;
+        let product = product * m.guesses;
+        let total = factorial(l) * product + MIN_GUESSES_BEFORE_GROWING_SEQUENCE.powi(l as i32 - 1);
+        // A longer sequence only wins if it's cheaper than every shorter one
+        if best[k].iter().any(|(&other, &(_, g, _))| other <= l && g <= total) {
+            return;
+        }
+        best[k].insert(l, (product, total, m));
+    };
+    for k in 0..n {
+        for m in matches.iter().filter(|m| m.j == k) {
+            if m.i == 0 {
+                update(&mut best, m.clone(), 1, 1.0);
+            } else {
+                let previous: Vec<(usize, f64)> = best[m.i - 1].iter().map(|(&l, &(p, _, _))| (l, p)).collect();
+                for (l, product) in previous {
+                    update(&mut best, m.clone(), l + 1, product);
+                }
+            }
+        }
+        update(&mut best, bruteforce(0, k), 1, 1.0);
+        for i in 1..=k {
+            // Two brute-force runs in a row are never cheaper than one
+            let previous: Vec<(usize, f64)> = best[i - 1].iter()
+                .filter(|(_, (_, _, last))| last.pattern != Pattern::Bruteforce)
+                .map(|(&l, &(p, _, _))| (l, p))
+                .collect();
+            for (l, product) in previous {
+                update(&mut best, bruteforce(i, k), l + 1, product);
+            }
+        }
+    }
+
+    let (&l, &(_, guesses, _)) = best[n - 1].iter()
+        .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+        .expect("every position is reachable by brute force");
+    let mut sequence = Vec::with_capacity(l);
+    let (mut k, mut l) = (n - 1, l);
+    loop {
+        let m = best[k][&l].2.clone();
+        let start = m.i;
+        sequence.push(m);
+        if start == 0 {
+            break;
+        }
+        k = start - 1;
+        l -= 1;
+    }
+    sequence.reverse();
+    (guesses, sequence)
+}
+
+fn bruteforce(i: usize, j: usize) -> Match {
+    let len = (j - i + 1) as i32;
+    let mut guesses = BRUTEFORCE_CARDINALITY.powi(len);
+    // A run of unknown characters is still harder than any single pattern of that length
+    guesses = guesses.max(if len == 1 { MIN_SUBMATCH_GUESSES_SINGLE_CHAR + 1.0 } else { MIN_SUBMATCH_GUESSES_MULTI_CHAR + 1.0 });
+    Match { i, j, pattern: Pattern::Bruteforce, guesses: guesses.min(f64::MAX / 1e10) }
+}
+
+fn factorial(n: usize) -> f64 {
+    (2..=n).map(|k| k as f64).product()
+}
+
+fn omnimatch(chars: &[char], current_year: i32) -> Vec<Match> {
+    let mut matches = Vec::new();
+    dictionary_matches(chars, &mut matches);
+    spatial_matches(chars, &mut matches);
+    sequence_matches(chars, &mut matches);
+    repeat_matches(chars, &mut matches, current_year);
+    date_matches(chars, &mut matches, current_year);
+    matches
+}
+
+fn dictionary_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let n = chars.len();
+    let lower: Vec<char> = chars.iter().flat_map(|c| c.to_lowercase()).collect();
+    // Case folding that changes the length (rare) would misalign positions
+    if lower.len() != n {
+        return;
+    }
+    let reversed: Vec<char> = lower.iter().rev().copied().collect();
+    let unl33t: Vec<Vec<char>> = ['i', 'l'].iter().map(|&one| {
+        lower.iter().map(|&c| if c == '1' { one } else { l33t_letter(c).unwrap_or(c) }).collect()
+    }).collect();
+
+    for i in 0..n {
+        for j in i..n {
+            let whole = i == 0 && j == n - 1;
+            if j - i < 2 && !whole {
+                continue;
+            }
+            let word: String = lower[i..=j].iter().collect();
+            for (list, common_password) in [(&*PASSWORDS, true), (&*ENGLISH, false)] {
+                if let Some(&rank) = list.get(word.as_str()) {
+                    matches.push(dictionary_match(chars, i, j, rank, common_password, false, false, whole));
+                }
+                let backwards: String = reversed[n - 1 - j..=n - 1 - i].iter().collect();
+                if backwards != word {
+                    if let Some(&rank) = list.get(backwards.as_str()) {
+                        matches.push(dictionary_match(chars, i, j, rank, common_password, true, false, whole));
+                    }
+                }
+                for variant in &unl33t {
+                    let plain: String = variant[i..=j].iter().collect();
+                    if plain == word {
+                        continue;
+                    }
+                    if let Some(&rank) = list.get(plain.as_str()) {
+                        matches.push(dictionary_match(chars, i, j, rank, common_password, false, true, whole));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn dictionary_match(chars: &[char], i: usize, j: usize, rank: usize, common_password: bool, reversed: bool, l33t: bool, whole: bool) -> Match {
+    let token = &chars[i..=j];
+    let mut guesses = rank as f64 * uppercase_variations(token);
+    if l33t {
+        guesses *= l33t_variations(token);
+    }
+    if reversed {
+        guesses *= 2.0;
+    }
+    Match { i, j, pattern: Pattern::Dictionary { rank, common_password, reversed, l33t, whole }, guesses }
+}
+
+fn l33t_letter(c: char) -> Option<char> {
+    L33T_TABLE.iter().find(|(sub, _)| *sub == c).map(|(_, letter)| *letter)
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (1..=k).fold(1.0, |acc, i| acc * (n + 1 - i) as f64 / i as f64)
+}
+
+fn uppercase_variations(token: &[char]) -> f64 {
+    let upper = token.iter().filter(|c| c.is_uppercase()).count();
+    let lower = token.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        return 1.0;
+    }
+    // Capitalised, all caps and a trailing capital are the first things tried
+    let first_upper = token[0].is_uppercase() && upper == 1;
+    let last_upper = token[token.len() - 1].is_uppercase() && upper == 1;
+    if first_upper || last_upper || lower == 0 {
+        return 2.0;
+    }
+    (1..=upper.min(lower)).map(|k| binomial(upper + lower, k)).sum()
+}
+
+fn l33t_variations(token: &[char]) -> f64 {
+    let mut variations = 1.0;
+    for &(sub, letter) in &L33T_TABLE {
+        let subbed = token.iter().filter(|&&c| c == sub).count();
+        if subbed == 0 {
+            continue;
+        }
+        let unsubbed = token.iter().filter(|c| c.to_lowercase().eq(std::iter::once(letter))).count();
+        variations *= if unsubbed == 0 {
+            2.0
+        } else {
+            (1..=subbed.min(unsubbed)).map(|k| binomial(subbed + unsubbed, k)).sum::<f64>()
+        };
+    }
+    variations
+}
+
+fn key_position(c: char) -> Option<(usize, usize, bool)> {
+    KEYBOARD_ROWS.iter().enumerate().find_map(|(row, (plain, shifted))| {
+        plain.chars().position(|k| k == c).map(|col| (row, col, false))
+            .or_else(|| shifted.chars().position(|k| k == c).map(|col| (row, col, true)))
+    })
+}
+
+// Direction from one key to a neighbouring key, or None if they aren't adjacent.
+// Rows are staggered, so a key touches the one above at the same column and the next.
+fn key_direction(from: (usize, usize), to: (usize, usize)) -> Option<u8> {
+    let (r1, c1) = (from.0 as i32, from.1 as i32);
+    let (r2, c2) = (to.0 as i32, to.1 as i32);
+    match (r2 - r1, c2 - c1) {
+        (0, -1) => Some(0),
+        (0, 1) => Some(1),
+        (-1, 0) => Some(2),
+        (-1, 1) => Some(3),
+        (1, -1) => Some(4),
+        (1, 0) => Some(5),
+        _ => None,
+    }
+}
+
+fn spatial_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let n = chars.len();
+    let mut i = 0;
+    while i + 2 < n {
+        let Some(start) = key_position(chars[i]) else {
+            i += 1;
+            continue;
+        };
+        let mut j = i;
+        let mut turns = 0;
+        let mut shifted = start.2 as usize;
+        let mut last_direction = None;
+        let mut position = (start.0, start.1);
+        while j + 1 < n {
+            let Some(next) = key_position(chars[j + 1]) else { break };
+            let Some(direction) = key_direction(position, (next.0, next.1)) else { break };
+            if last_direction != Some(direction) {
+                turns += 1;
+                last_direction = Some(direction);
+            }
+            shifted += next.2 as usize;
+            position = (next.0, next.1);
+            j += 1;
+        }
+        if j - i >= 2 {
+            matches.push(Match { i, j, pattern: Pattern::Spatial { turns, shifted }, guesses: spatial_guesses(j - i + 1, turns, shifted) });
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn spatial_guesses(len: usize, turns: usize, shifted: usize) -> f64 {
+    let mut guesses = 0.0;
+    for i in 2..=len {
+        for j in 1..=turns.min(i - 1) {
+            guesses += binomial(i - 1, j - 1) * KEYBOARD_KEYS * KEYBOARD_AVERAGE_DEGREE.powi(j as i32);
+        }
+    }
+    let unshifted = len - shifted;
+    if shifted > 0 {
+        guesses *= if unshifted == 0 {
+            2.0
+        } else {
+            (1..=shifted.min(unshifted)).map(|k| binomial(shifted + unshifted, k)).sum::<f64>()
+        };
+    }
+    guesses
+}
+
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let n = chars.len();
+    let mut i = 0;
+    while i + 2 < n {
+        let delta = chars[i + 1] as i64 - chars[i] as i64;
+        if delta == 0 || delta.abs() > 5 {
+            i += 1;
+            continue;
+        }
+        let mut j = i + 1;
+        while j + 1 < n && chars[j + 1] as i64 - chars[j] as i64 == delta {
+            j += 1;
+        }
+        if j - i >= 2 {
+            let first = chars[i];
+            let mut base = if matches!(first, 'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9') {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            if delta < 0 {
+                base *= 2.0;
+            }
+            matches.push(Match { i, j, pattern: Pattern::Sequence, guesses: base * (j - i + 1) as f64 });
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>, current_year: i32) {
+    let n = chars.len();
+    for i in 0..n {
+        for base_len in 1..=(n - i) / 2 {
+            let base = &chars[i..i + base_len];
+            let mut count = 1;
+            while i + (count + 1) * base_len <= n && &chars[i + count * base_len..i + (count + 1) * base_len] == base {
+                count += 1;
+            }
+            if count < 2 || (base_len == 1 && count < 3) {
+                continue;
+            }
+            // Skip repeats of a repeat ("abab" as a base); the shorter base covers them
+            if (1..base_len).any(|d| base_len % d == 0 && base.chunks(d).all(|c| c == &base[..d])) {
+                continue;
+            }
+            let (base_guesses, _) = most_guessable(base, current_year);
+            let j = i + count * base_len - 1;
+            matches.push(Match { i, j, pattern: Pattern::Repeat { base_len }, guesses: base_guesses * count as f64 });
+        }
+    }
+}
+
+fn year_guesses(year: i32, current_year: i32) -> f64 {
+    ((year - current_year).abs() as f64).max(MIN_YEAR_SPACE)
+}
+
+fn is_plausible_year(year: i32) -> bool {
+    (1900..=2050).contains(&year)
+}
+
+fn date_matches(chars: &[char], matches: &mut Vec<Match>, current_year: i32) {
+    let n = chars.len();
+    for i in 0..n {
+        for j in i + 3..n.min(i + 10) {
+            let token = &chars[i..=j];
+            if token.len() == 4 && token.iter().all(|c| c.is_ascii_digit()) {
+                let year: i32 = token.iter().collect::<String>().parse().unwrap_or(0);
+                if is_plausible_year(year) {
+                    let recent = (year - current_year).abs() <= 20;
+                    matches.push(Match { i, j, pattern: Pattern::Date { year_only: true, recent }, guesses: year_guesses(year, current_year) });
+                }
+            }
+            if let Some((year, separated)) = parse_date(token) {
+                let mut guesses = year_guesses(year, current_year) * 365.0;
+                if separated {
+                    guesses *= 4.0;
+                }
+                matches.push(Match { i, j, pattern: Pattern::Date { year_only: false, recent: false }, guesses });
+            }
+        }
+    }
+}
+
+// Day, month and year in any common order, with or without separators
+fn parse_date(token: &[char]) -> Option<(i32, bool)> {
+    let separator = token.iter().find(|c| !c.is_ascii_digit());
+    let groups: Vec<String> = match separator {
+        Some(&sep) if matches!(sep, '/' | '-' | '.' | '_' | ' ') => {
+            let parts: Vec<String> = token.iter().collect::<String>().split(sep).map(str::to_string).collect();
+            if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) {
+                return None;
+            }
+            parts
+        },
+        Some(_) => return None,
+        None => {
+            let digits: String = token.iter().collect();
+            let splits: &[(usize, usize)] = match digits.len() {
+                4 => &[(1, 2), (2, 3)],
+                5 => &[(1, 2), (2, 3), (1, 3), (2, 4)],
+                6 => &[(2, 4), (1, 2), (4, 5)],
+                7 => &[(1, 3), (2, 3), (4, 5), (4, 6)],
+                8 => &[(2, 4), (4, 6)],
+                _ => return None,
+            };
+            return splits.iter().find_map(|&(a, b)| {
+                date_from_groups(&[&digits[..a], &digits[a..b], &digits[b..]]).map(|year| (year, false))
+            });
+        },
+    };
+    date_from_groups(&[&groups[0], &groups[1], &groups[2]]).map(|year| (year, true))
+}
+
+fn date_from_groups(groups: &[&str; 3]) -> Option<i32> {
+    let values: Vec<i32> = groups.iter().map(|g| g.parse().ok()).collect::<Option<_>>()?;
+    // Year first (yyyy-mm-dd) or last (dd-mm-yyyy / mm-dd-yyyy)
+    for (y, a, b) in [(0, 1, 2), (2, 0, 1)] {
+        let (year, a, b) = (values[y], values[a], values[b]);
+        let year = match groups[y].len() {
+            2 => if year > 50 { 1900 + year } else { 2000 + year },
+            4 => year,
+            _ => continue,
+        };
+        if !is_plausible_year(year) {
+            continue;
+        }
+        let valid = |day: i32, month: i32| (1..=31).contains(&day) && (1..=12).contains(&month);
+        if valid(a, b) || valid(b, a) {
+            return Some(year);
+        }
+    }
+    None
+}
+
+fn feedback(score: u8, sequence: &[Match], chars: &[char]) -> (Option<&'static str>, Vec<&'static str>) {
+    if score > 2 {
+        return (None, Vec::new());
+    }
+    let mut suggestions = vec!["Add another word or two. Uncommon words are better."];
+    let Some(longest) = sequence.iter().max_by_key(|m| m.j - m.i) else {
+        return (None, suggestions);
+    };
+    let warning = match &longest.pattern {
+        Pattern::Dictionary { rank, common_password, reversed, l33t, whole } => {
+            let token = &chars[longest.i..=longest.j];
+            if token[0].is_uppercase() && token[1..].iter().all(|c| !c.is_uppercase()) {
+                suggestions.push("Capitalization doesn't help very much");
+            } else if token.iter().all(|c| !c.is_lowercase()) && token.iter().any(|c| c.is_uppercase()) {
+                suggestions.push("All-uppercase is almost as easy to guess as all-lowercase");
+            }
+            if *reversed && longest.j - longest.i >= 3 {
+                suggestions.push("Reversed words aren't much harder to guess");
+            }
+            if *l33t {
+                suggestions.push("Predictable substitutions like '@' instead of 'a' don't help very much");
+            }
+            match (common_password, whole) {
+                (true, true) if *rank <= 10 => Some("This is a top-10 common password"),
+                (true, true) if *rank <= 100 => Some("This is a top-100 common password"),
+                (true, true) => Some("This is a very common password"),
+                (true, false) => Some("This is similar to a commonly used password"),
+                (false, true) => Some("A word by itself is easy to guess"),
+                (false, false) => None,
+            }
+        },
+        Pattern::Spatial { turns, .. } => {
+            suggestions.push("Use a longer keyboard pattern with more turns");
+            Some(if *turns == 1 { "Straight rows of keys are easy to guess" } else { "Short keyboard patterns are easy to guess" })
+        },
+        Pattern::Repeat { base_len } => {
+            suggestions.push("Avoid repeated words and characters");
+            Some(if *base_len == 1 {
+                "Repeats like \"aaa\" are easy to guess"
+            } else {
+                "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\""
+            })
+        },
+        Pattern::Sequence => {
+            suggestions.push("Avoid sequences");
+            Some("Sequences like abc or 6543 are easy to guess")
+        },
+        Pattern::Date { year_only, recent } => {
+            suggestions.push("Avoid dates and years that are associated with you");
+            Some(if *year_only && *recent { "Recent years are easy to guess" } else { "Dates are often easy to guess" })
+        },
+        Pattern::Bruteforce => None,
+    };
+    (warning, suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data::PasswordPolicy;
+
+    // Dates are scored relative to this year, so the tests don't age
+    const YEAR: i32 = 2026;
+
+    fn assert_weak(password: &str, warning: &str) {
+        let strength = estimate_in(password, YEAR);
+        assert!(strength.score <= 1, "{:?} scored {}", password, strength.score);
+        assert_eq!(strength.warning, Some(warning), "{:?}", password);
+    }
+
+    #[test]
+    fn common_passwords_are_weak() {
+        assert_eq!(estimate_in("password", YEAR).score, 0);
+        assert_weak("password", "This is a top-10 common password");
+        assert_weak("sunshine77k", "This is similar to a commonly used password");
+        assert_eq!(estimate_in("", YEAR).score, 0);
+    }
+
+    #[test]
+    fn keyboard_walks_are_weak() {
+        assert_weak("poiuytr", "Straight rows of keys are easy to guess");
+        assert_weak("zxcvfr", "Short keyboard patterns are easy to guess");
+    }
+
+    #[test]
+    fn dates_and_recent_years_are_weak() {
+        assert_weak("1987-06-15", "Dates are often easy to guess");
+        assert_weak("15061987", "Dates are often easy to guess");
+        assert_weak("2019", "Recent years are easy to guess");
+        // The same year is no longer recent decades later
+        assert_eq!(estimate_in("2019", YEAR + 40).warning, Some("Dates are often easy to guess"));
+        assert!(estimate_in("1970", 1970).guesses_log10 < estimate_in("1970", YEAR).guesses_log10);
+    }
+
+    #[test]
+    fn l33t_substitutions_barely_help() {
+        let strength = estimate_in("p@ssw0rd", YEAR);
+        assert!(strength.score <= 1);
+        assert!(strength.suggestions.contains(&"Predictable substitutions like '@' instead of 'a' don't help very much"));
+    }
+
+    #[test]
+    fn repeats_and_sequences_are_weak() {
+        assert_weak("aaaaaaaaaaaa", "Repeats like \"aaa\" are easy to guess");
+        assert_weak("abcabcabcabc", "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\"");
+        assert_weak("abcdefgh", "Sequences like abc or 6543 are easy to guess");
+    }
+
+    #[test]
+    fn passphrases_are_strong() {
+        for passphrase in ["correct horse battery staple", "velvet orbit mango lantern", "Tx8#qL2!vR9@zK4$"] {
+            let strength = estimate_in(passphrase, YEAR);
+            assert_eq!(strength.score, 4, "{:?}", passphrase);
+            assert!(strength.warning.is_none() && strength.suggestions.is_empty());
+        }
+    }
+
+    #[test]
+    fn policy_checks_length_then_score() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.check("velvet").unwrap_err().to_string(), "Min 8 characters");
+        // Length is counted in characters, not bytes
+        assert_eq!(policy.check("ééééééé").unwrap_err().to_string(), "Min 8 characters");
+        assert!(policy.check("password123").unwrap_err().to_string().starts_with("Password is too weak (very weak)"));
+        assert!(policy.check("velvet orbit mango lantern").is_ok());
+
+        let strict = PasswordPolicy { min_length: 12, min_score: 4 };
+        assert!(strict.check("aaaaaaaaaaaaaaaa").unwrap_err().to_string().contains("very strong or better is required"));
+        assert!(strict.check("velvet").is_err());
+        assert!(strict.check("velvet orbit mango lantern").is_ok());
+        let none = PasswordPolicy { min_length: 0, min_score: 0 };
+        assert!(none.check("").is_ok());
+    }
+}
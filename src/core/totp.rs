@@ -0,0 +1,220 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use hmac::{Hmac, Mac, digest::KeyInit};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use super::crypto;
+
+// Time-based one-time passwords (RFC 6238) as used by authenticator apps:
+// HMAC-SHA1, 6 digits, 30 second steps.
+pub const DIGITS: u32 = 6;
+pub const PERIOD: u64 = 30;
+// Codes from one step before or after the current one are accepted to allow for
+// clock drift between this machine and the phone
+pub const SKEW_STEPS: u64 = 1;
+pub const SECRET_LEN: usize = 20;
+const ISSUER: &str = "Notas";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+// HOTP value (RFC 4226) for `counter`, truncated to `digits` decimal digits
+pub fn hotp(algorithm: Algorithm, secret: &[u8], counter: u64, digits: u32) -> u32 {
+    let message = counter.to_be_bytes();
+    let mut digest = match algorithm {
+        Algorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(secret, &message),
+        Algorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(secret, &message),
+        Algorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(secret, &message),
+    };
+
+    // Dynamic truncation
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    digest.zeroize();
+    binary % 10u32.pow(digits)
+}
+
+fn hmac_digest<M: Mac + KeyInit>(secret: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn totp(algorithm: Algorithm, secret: &[u8], unix_time: u64, digits: u32) -> u32 {
+    hotp(algorithm, secret, unix_time / PERIOD, digits)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// Known-answer test with the vectors from RFC 6238 Appendix B. Run before enrolling a
+// secret so a broken build can never lock someone out of their vault.
+pub fn self_test() -> bool {
+    const SEED_SHA1: &[u8] = b"12345678901234567890";
+    const SEED_SHA256: &[u8] = b"12345678901234567890123456789012";
+    const SEED_SHA512: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+    const VECTORS: [(u64, u32, u32, u32); 6] = [
+        (59, 94287082, 46119246, 90693936),
+        (1111111109, 7081804, 68084774, 25091201),
+        (1111111111, 14050471, 67062674, 99943326),
+        (1234567890, 89005924, 91819424, 93441116),
+        (2000000000, 69279037, 90698825, 38618901),
+        (20000000000, 65353130, 77737706, 47863826),
+    ];
+
+    VECTORS.iter().all(|&(time, sha1, sha256, sha512)| {
+        totp(Algorithm::Sha1, SEED_SHA1, time, 8) == sha1
+            && totp(Algorithm::Sha256, SEED_SHA256, time, 8) == sha256
+            && totp(Algorithm::Sha512, SEED_SHA512, time, 8) == sha512
+    })
+}
+
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct TotpSecret([u8; SECRET_LEN]);
+
+impl TotpSecret {
+    pub fn generate() -> Self {
+        let mut secret = [0u8; SECRET_LEN];
+        crypto::fill_random(&mut secret);
+        Self(secret)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let secret: [u8; SECRET_LEN] = bytes.try_into()
+            .map_err(|_| anyhow!("Two-factor secret is corrupted"))?;
+        Ok(Self(secret))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    // RFC 4648 base32 without padding, as authenticator apps expect
+    pub fn to_base32(&self) -> String {
+        const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+        let mut encoded = String::with_capacity((SECRET_LEN * 8).div_ceil(5));
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for &byte in &self.0 {
+            buffer = (buffer << 8) | byte as u32;
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+        }
+        buffer.zeroize();
+        encoded
+    }
+
+    // Enrollment URI for QR codes (https://github.com/google/google-authenticator/wiki/Key-Uri-Format)
+    pub fn otpauth_uri(&self, account: &str) -> String {
+        let label = percent_encode(&format!("{}:{}", ISSUER, account));
+        format!(
+            "otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            label,
+            self.to_base32(),
+            ISSUER,
+            DIGITS,
+            PERIOD,
+        )
+    }
+
+    // Accept the code for the current time step or one within the skew window
+    pub fn verify(&self, code: &str, unix_time: u64) -> bool {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+
+        let step = unix_time / PERIOD;
+        let mut matched = false;
+        for candidate in step.saturating_sub(SKEW_STEPS)..=step + SKEW_STEPS {
+            let expected = format!("{:0width$}", hotp(Algorithm::Sha1, &self.0, candidate, DIGITS), width = DIGITS as usize);
+            // Compare every byte and check every step so timing doesn't reveal which matched
+            let difference = expected.bytes().zip(code.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b));
+            matched |= difference == 0;
+        }
+        matched
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~' | b':') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: &[u8] = b"12345678901234567890";
+
+    // RFC 6238 Appendix B, SHA-1
+    #[test]
+    fn rfc6238_sha1_vectors() {
+        for (time, expected) in [
+            (59, 94287082),
+            (1111111109, 7081804),
+            (1111111111, 14050471),
+            (1234567890, 89005924),
+            (2000000000, 69279037),
+            (20000000000, 65353130),
+        ] {
+            assert_eq!(totp(Algorithm::Sha1, SEED, time, 8), expected, "time {}", time);
+        }
+        assert!(self_test());
+    }
+
+    fn code_at(secret: &TotpSecret, unix_time: u64) -> String {
+        format!("{:06}", totp(Algorithm::Sha1, secret.as_bytes(), unix_time, DIGITS))
+    }
+
+    #[test]
+    fn verify_accepts_one_step_of_skew() {
+        let secret = TotpSecret::from_bytes(SEED).unwrap();
+        // The first and last second of the step the code belongs to
+        let issued = 1234567890 / PERIOD * PERIOD;
+        let code = code_at(&secret, issued);
+
+        assert!(secret.verify(&code, issued));
+        assert!(secret.verify(&code, issued - 1));
+        assert!(secret.verify(&code, issued - PERIOD));
+        assert!(secret.verify(&code, issued + PERIOD));
+        assert!(secret.verify(&code, issued + 2 * PERIOD - 1));
+        assert!(!secret.verify(&code, issued - PERIOD - 1));
+        assert!(!secret.verify(&code, issued + 2 * PERIOD));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_codes() {
+        let secret = TotpSecret::from_bytes(SEED).unwrap();
+        let code = code_at(&secret, 59);
+        assert!(secret.verify(&format!("{} {}", &code[..3], &code[3..]), 59));
+        assert!(!secret.verify(&code[..5], 59));
+        assert!(!secret.verify("12345a", 59));
+        assert!(!secret.verify("", 59));
+    }
+}
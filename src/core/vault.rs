@@ -0,0 +1,704 @@
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use aes_gcm::{Aes256Gcm, Key};
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use super::{
+    data::{Argon2Params, Cipher},
+    crypto::{self, EncryptedData, KEY_LEN, PARAMS_LEN, SALT_LEN},
+    totp::{self, TotpSecret},
+};
+
+// On-disk vault layout (version 5, envelope encryption):
+//   magic (8) | version (1) | cipher id (1) | slot count (1) | key slots | nonce | tag | ciphertext
+//   | decoy area
+// The notes are encrypted with a random data key using the cipher named in the header,
+// with every header byte before the nonce as associated data: a changed parameter,
+// flag, slot or version number makes decryption fail instead of being trusted.
+// Each key slot wraps that data key (always with AES-256-GCM) under a key derived from
+// one secret (the password, a recovery code, ...), so a slot can be added, rewrapped or
+// revoked without touching the notes.
+//
+// Key slot: kind (1) | flags (1) | memory_cost | time_cost | parallelism (u32 LE each)
+//           | salt (16) | wrapped data key (nonce | tag | key)
+//           [ | sealed TOTP secret (nonce | tag | secret), if FLAG_TOTP ]
+//
+// The decoy area (see DecoyArea) holds a second vault opened by a duress password, or
+// random bytes of the same shape when there is none.
+//
+// Older files are still read. Versions 2 to 4 are rewritten as version 5 on the next save:
+//   version 4: as version 5 without the decoy area
+//   version 3: as version 4, but the header is not authenticated
+//   version 2: as version 3 without the cipher id (always AES-256-GCM)
+// Versions 1 and 0 encrypt the notes directly with the password-derived key and are
+// migrated to envelope encryption on unlock:
+//   version 1: magic | 1 | flags | KDF params | salt | nonce | tag | ciphertext
+//   version 0: salt | nonce | tag | ciphertext (no magic, default KDF params)
+pub const VAULT_MAGIC: &[u8; 8] = b"NOTASVLT";
+const MAGIC_LEN: usize = 8;
+pub const VAULT_VERSION: u8 = 5;
+const NO_DECOY_VERSION: u8 = 4;
+const UNAUTHENTICATED_VERSION: u8 = 3;
+const AES_ONLY_VERSION: u8 = 2;
+const DIRECT_VERSION: u8 = 1;
+const WRAPPED_KEY_LEN: usize = crypto::sealed_len(KEY_LEN);
+const SLOT_LEN: usize = 2 + PARAMS_LEN + SALT_LEN + WRAPPED_KEY_LEN;
+const SEALED_TOTP_LEN: usize = crypto::sealed_len(totp::SECRET_LEN);
+const DECOY_SLOT_LEN: usize = PARAMS_LEN + SALT_LEN + WRAPPED_KEY_LEN;
+const DECOY_TRAILER_LEN: usize = DECOY_SLOT_LEN + 4;
+// Decoy notes are padded to a multiple of this before sealing, and filler is one bucket
+const DECOY_BUCKET: usize = 16 * 1024;
+const MAX_SLOTS: usize = 16;
+
+// Slot (or, in version 1, vault) key is derived from the secret plus the SHA-256 of a keyfile
+pub const FLAG_KEYFILE: u8 = 0x01;
+// Slot also requires a TOTP code. The TOTP secret is sealed under the secret-derived key
+// and the data key is wrapped under a key bound to both, so the requirement cannot be
+// dropped by editing the header. It guards against someone who only learned the
+// password; an attacker holding both the vault file and the password can still open
+// the secret offline, as with any TOTP verifier that has to store its secret.
+pub const FLAG_TOTP: u8 = 0x02;
+const KNOWN_FLAGS: u8 = FLAG_KEYFILE | FLAG_TOTP;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotKind {
+    Password,
+    RecoveryCode,
+}
+
+impl SlotKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            SlotKind::Password => 1,
+            SlotKind::RecoveryCode => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            1 => Ok(SlotKind::Password),
+            2 => Ok(SlotKind::RecoveryCode),
+            _ => Err(anyhow!("Vault uses a key slot type this version of Notas does not support")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KeySlot {
+    pub kind: SlotKind,
+    pub flags: u8,
+    pub params: Argon2Params,
+    salt: [u8; SALT_LEN],
+    wrapped_key: Vec<u8>,
+    sealed_totp: Option<Vec<u8>>,
+}
+
+impl KeySlot {
+    // Wrap `data_key` under a key derived from `secret` and, if given, a keyfile and TOTP secret
+    pub fn wrap(kind: SlotKind, secret: &[u8], keyfile: Option<&Keyfile>, totp_secret: Option<&TotpSecret>, params: Argon2Params, data_key: &Key<Aes256Gcm>) -> Result<Self> {
+        let salt = crypto::generate_salt();
+        let mut flags = 0;
+        if keyfile.is_some() {
+            flags |= FLAG_KEYFILE;
+        }
+        if totp_secret.is_some() {
+            flags |= FLAG_TOTP;
+        }
+
+        let kek = crypto::derive_key_with_secret(secret, keyfile.map(Keyfile::as_bytes), &salt, &params)?;
+        let (wrapping_key, sealed_totp) = match totp_secret {
+            Some(totp_secret) => (bind_totp(&kek, totp_secret), Some(crypto::seal(&kek, totp_secret.as_bytes())?)),
+            None => (kek, None),
+        };
+        let wrapped_key = crypto::seal(&wrapping_key, data_key.as_slice())?;
+        Ok(Self { kind, flags, params, salt, wrapped_key, sealed_totp })
+    }
+
+    // Recover the data key, and the TOTP secret if the slot has one. Fails if the
+    // secret, keyfile or TOTP code is wrong.
+    pub fn unwrap(&self, secret: &[u8], factors: &UnlockFactors) -> Result<(Key<Aes256Gcm>, Option<TotpSecret>)> {
+        let (kek, totp_secret) = self.open_factors(secret, factors.keyfile.as_ref())?;
+        let wrapping_key = match totp_secret {
+            Some(ref totp_secret) => {
+                let code = factors.totp_code.as_deref()
+                    .ok_or_else(|| anyhow!("Enter the code from your authenticator app"))?;
+                if !totp_secret.verify(code, totp::unix_now()) {
+                    return Err(WrongCredentials::TotpCode.into());
+                }
+                bind_totp(&kek, totp_secret)
+            },
+            None => kek,
+        };
+
+        let mut key_bytes = crypto::open(&wrapping_key, &self.wrapped_key)
+            .map_err(|_| WrongCredentials::Password)?;
+        let key = Key::<Aes256Gcm>::clone_from_slice(&key_bytes);
+        key_bytes.zeroize();
+        Ok((key, totp_secret))
+    }
+
+    // Check `secret` (and keyfile) without needing a TOTP code, e.g. to confirm the
+    // current password before changing it while the vault is unlocked
+    pub fn verify_secret(&self, secret: &[u8], keyfile: Option<&Keyfile>) -> Result<()> {
+        let (kek, totp_secret) = self.open_factors(secret, keyfile)?;
+        if totp_secret.is_none() {
+            crypto::open(&kek, &self.wrapped_key).map_err(|_| anyhow!("Invalid password"))?;
+        }
+        Ok(())
+    }
+
+    // Derive the slot key and, for TOTP slots, open the sealed TOTP secret with it
+    fn open_factors(&self, secret: &[u8], keyfile: Option<&Keyfile>) -> Result<(Key<Aes256Gcm>, Option<TotpSecret>)> {
+        let keyfile = if self.requires_keyfile() { keyfile } else { None };
+        // Derive before checking for a missing keyfile, so the time taken doesn't show
+        // which factors the slot needs
+        let kek = crypto::derive_key_with_secret(secret, keyfile.map(Keyfile::as_bytes), &self.salt, &self.params)?;
+        if self.requires_keyfile() && keyfile.is_none() {
+            return Err(anyhow!("This vault requires its keyfile"));
+        }
+        let totp_secret = match self.sealed_totp {
+            Some(ref sealed) => {
+                let mut secret_bytes = crypto::open(&kek, sealed)
+                    .map_err(|_| WrongCredentials::Password)?;
+                let totp_secret = TotpSecret::from_bytes(&secret_bytes);
+                secret_bytes.zeroize();
+                Some(totp_secret?)
+            },
+            None => None,
+        };
+        Ok((kek, totp_secret))
+    }
+
+    pub fn requires_keyfile(&self) -> bool {
+        self.flags & FLAG_KEYFILE != 0
+    }
+
+    pub fn requires_totp(&self) -> bool {
+        self.flags & FLAG_TOTP != 0
+    }
+
+    fn encoded_len(flags: u8) -> usize {
+        if flags & FLAG_TOTP != 0 { SLOT_LEN + SEALED_TOTP_LEN } else { SLOT_LEN }
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.kind.to_byte());
+        bytes.push(self.flags);
+        crypto::write_params(&self.params, bytes);
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.wrapped_key);
+        if let Some(ref sealed) = self.sealed_totp {
+            bytes.extend_from_slice(sealed);
+        }
+    }
+
+    // `bytes` holds exactly `encoded_len(flags)` bytes
+    fn read(bytes: &[u8]) -> Result<Self> {
+        let kind = SlotKind::from_byte(bytes[0])?;
+        let flags = check_flags(bytes[1])?;
+        let params = read_params(&bytes[2..2 + PARAMS_LEN])?;
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bytes[2 + PARAMS_LEN..2 + PARAMS_LEN + SALT_LEN]);
+        let wrapped_key = bytes[2 + PARAMS_LEN + SALT_LEN..SLOT_LEN].to_vec();
+        let sealed_totp = (flags & FLAG_TOTP != 0).then(|| bytes[SLOT_LEN..].to_vec());
+        Ok(Self { kind, flags, params, salt, wrapped_key, sealed_totp })
+    }
+}
+
+// Key the data key wrap with the TOTP secret as well as the password-derived key
+fn bind_totp(kek: &Key<Aes256Gcm>, totp_secret: &TotpSecret) -> Key<Aes256Gcm> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"notas-totp-slot");
+    hasher.update(kek.as_slice());
+    hasher.update(totp_secret.as_bytes());
+    let mut digest: [u8; KEY_LEN] = hasher.finalize().into();
+    let key = Key::<Aes256Gcm>::from(digest);
+    digest.zeroize();
+    key
+}
+
+#[derive(Debug, Clone)]
+pub struct VaultHeader {
+    pub cipher: Cipher,
+    pub slots: Vec<KeySlot>,
+}
+
+impl VaultHeader {
+    pub fn new(cipher: Cipher, slots: Vec<KeySlot>) -> Self {
+        Self { cipher, slots }
+    }
+
+    pub fn password_slot(&self) -> Option<&KeySlot> {
+        self.slots.iter().find(|slot| slot.kind == SlotKind::Password)
+    }
+
+    pub fn requires_keyfile(&self) -> bool {
+        self.password_slot().map(KeySlot::requires_keyfile).unwrap_or(false)
+    }
+
+    pub fn requires_totp(&self) -> bool {
+        self.password_slot().map(KeySlot::requires_totp).unwrap_or(false)
+    }
+
+    // Replace every slot of `slot.kind` with `slot`
+    pub fn set_slot(&mut self, slot: KeySlot) {
+        self.slots.retain(|s| s.kind != slot.kind);
+        self.slots.push(slot);
+    }
+
+    pub fn add_slot(&mut self, slot: KeySlot) -> Result<()> {
+        if self.slots.len() >= MAX_SLOTS {
+            return Err(anyhow!("Vault already has the maximum number of key slots"));
+        }
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    pub fn remove_slots(&mut self, kind: SlotKind) {
+        // The password slot is what normal unlocking uses; never leave a vault without it
+        if kind != SlotKind::Password {
+            self.slots.retain(|s| s.kind != kind);
+        }
+    }
+
+    // Try each slot of `kind` until one yields the data key (and the slot's TOTP secret)
+    pub fn unwrap_key(&self, kind: SlotKind, secret: &[u8], factors: &UnlockFactors) -> Result<(Key<Aes256Gcm>, Option<TotpSecret>)> {
+        let mut last_error = anyhow!("Vault has no key slot for this unlock method");
+        for slot in self.slots.iter().filter(|slot| slot.kind == kind) {
+            // The KDF runs either way, so the time taken doesn't show which factors apply
+            let unwrapped = slot.unwrap(secret, factors);
+            if kind == SlotKind::Password && !slot.requires_keyfile() && factors.keyfile.is_some() {
+                return Err(anyhow!("This vault does not use a keyfile"));
+            }
+            match unwrapped {
+                Ok(unwrapped) => return Ok(unwrapped),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    // Decrypt the notes that follow this header. `associated_data` is the header as
+    // read from disk (empty for versions before 4).
+    pub fn open_body(&self, data_key: &Key<Aes256Gcm>, body: &[u8], associated_data: &[u8]) -> Result<Vec<u8>> {
+        crypto::open_with(self.cipher, data_key, body, associated_data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC_LEN + 3 + self.slots.len() * (SLOT_LEN + SEALED_TOTP_LEN));
+        bytes.extend_from_slice(VAULT_MAGIC);
+        bytes.push(VAULT_VERSION);
+        bytes.push(self.cipher.id());
+        bytes.push(self.slots.len() as u8);
+        for slot in &self.slots {
+            slot.write(&mut bytes);
+        }
+        bytes
+    }
+}
+
+// Second vault stored after the main one, opened by a duress password:
+//   decoy body (nonce | tag | ciphertext) | memory_cost | time_cost | parallelism
+//   | salt (16) | wrapped data key (nonce | tag | key) | body length (u32 LE)
+// The decoy's notes are sealed as length (u32 LE) | notes | zero padding, making the
+// body a whole number of DECOY_BUCKETs. Every version 5 file has a decoy area. Without
+// a duress password it is random bytes the size of one bucket, carrying the password
+// slot's KDF parameters, so the file doesn't show whether a decoy exists (unless the
+// decoy's notes outgrow a bucket). The body is always AES-256-GCM, authenticated
+// with the slot bytes as associated data, and never depends on the main vault's header:
+// either vault can be rewritten while the other is carried along unchanged.
+#[derive(Debug, Clone)]
+pub struct DecoyArea {
+    params: Argon2Params,
+    salt: [u8; SALT_LEN],
+    wrapped_key: Vec<u8>,
+    body: Vec<u8>,
+}
+
+impl DecoyArea {
+    // Random stand-in, the size of a decoy with few notes
+    pub fn filler(params: Argon2Params) -> Self {
+        let mut wrapped_key = vec![0u8; WRAPPED_KEY_LEN];
+        crypto::fill_random(&mut wrapped_key);
+        let mut body = vec![0u8; crypto::sealed_len(DECOY_BUCKET)];
+        crypto::fill_random(&mut body);
+        Self { params, salt: crypto::generate_salt(), wrapped_key, body }
+    }
+
+    pub fn create(password: &[u8], params: Argon2Params, data_key: &Key<Aes256Gcm>, plaintext: &[u8]) -> Result<Self> {
+        let mut decoy = Self { params, salt: [0u8; SALT_LEN], wrapped_key: Vec::new(), body: Vec::new() };
+        decoy.rewrap(password, data_key)?;
+        decoy.seal_body(data_key, plaintext)?;
+        Ok(decoy)
+    }
+
+    // Always runs the KDF, whether or not this is filler
+    pub fn unwrap_key(&self, password: &[u8]) -> Result<Key<Aes256Gcm>> {
+        let kek = crypto::derive_key_with_params(password, &self.salt, &self.params)?;
+        let mut key_bytes = crypto::open(&kek, &self.wrapped_key)
+            .map_err(|_| WrongCredentials::Password)?;
+        let key = Key::<Aes256Gcm>::clone_from_slice(&key_bytes);
+        key_bytes.zeroize();
+        Ok(key)
+    }
+
+    // Wrap `data_key` under a new password (and salt), keeping the KDF parameters
+    pub fn rewrap(&mut self, password: &[u8], data_key: &Key<Aes256Gcm>) -> Result<()> {
+        let salt = crypto::generate_salt();
+        let kek = crypto::derive_key_with_params(password, &salt, &self.params)?;
+        self.wrapped_key = crypto::seal(&kek, data_key.as_slice())?;
+        self.salt = salt;
+        Ok(())
+    }
+
+    pub fn open_body(&self, data_key: &Key<Aes256Gcm>) -> Result<Vec<u8>> {
+        let mut padded = crypto::open_with(Cipher::Aes256Gcm, data_key, &self.body, &self.slot_bytes())?;
+        let plaintext = padded.get(4..)
+            .zip(padded.first_chunk::<4>().map(|len| u32::from_le_bytes(*len) as usize))
+            .and_then(|(notes, len)| notes.get(..len))
+            .map(<[u8]>::to_vec);
+        padded.zeroize();
+        plaintext.ok_or_else(|| anyhow!("Vault is corrupted"))
+    }
+
+    pub fn seal_body(&mut self, data_key: &Key<Aes256Gcm>, plaintext: &[u8]) -> Result<()> {
+        let padded_len = (4 + plaintext.len()).div_ceil(DECOY_BUCKET) * DECOY_BUCKET;
+        let mut padded = Vec::with_capacity(padded_len);
+        padded.extend_from_slice(&(plaintext.len() as u32).to_le_bytes());
+        padded.extend_from_slice(plaintext);
+        padded.resize(padded_len, 0);
+        let sealed = crypto::seal_with(Cipher::Aes256Gcm, data_key, &padded, &self.slot_bytes());
+        padded.zeroize();
+        self.body = sealed?;
+        Ok(())
+    }
+
+    fn slot_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DECOY_SLOT_LEN);
+        crypto::write_params(&self.params, &mut bytes);
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.wrapped_key);
+        bytes
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.body);
+        bytes.extend_from_slice(&self.slot_bytes());
+        bytes.extend_from_slice(&(self.body.len() as u32).to_le_bytes());
+    }
+
+    // Split the decoy area off the end of `bytes`, returning what precedes it
+    fn split(bytes: &[u8]) -> Result<(&[u8], Self)> {
+        if bytes.len() < DECOY_TRAILER_LEN {
+            return Err(anyhow!("Vault is truncated"));
+        }
+        let (rest, trailer) = bytes.split_at(bytes.len() - DECOY_TRAILER_LEN);
+        let (slot, length) = trailer.split_at(DECOY_SLOT_LEN);
+        let body_len = u32::from_le_bytes([length[0], length[1], length[2], length[3]]) as usize;
+        if body_len > rest.len() {
+            return Err(anyhow!("Vault is corrupted"));
+        }
+        let (rest, body) = rest.split_at(rest.len() - body_len);
+
+        let params = read_params(&slot[..PARAMS_LEN])?;
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&slot[PARAMS_LEN..PARAMS_LEN + SALT_LEN]);
+        let wrapped_key = slot[PARAMS_LEN + SALT_LEN..].to_vec();
+        Ok((rest, Self { params, salt, wrapped_key, body: body.to_vec() }))
+    }
+}
+
+// A vault from before envelope encryption: the password-derived key encrypts the notes
+pub struct DirectVault {
+    pub flags: u8,
+    pub params: Argon2Params,
+    pub encrypted: EncryptedData,
+}
+
+impl DirectVault {
+    pub fn decrypt(&self, password: &[u8], factors: &UnlockFactors) -> Result<Vec<u8>> {
+        let requires_keyfile = self.flags & FLAG_KEYFILE != 0;
+        match (requires_keyfile, factors.keyfile.is_some()) {
+            (true, false) => return Err(anyhow!("This vault requires its keyfile")),
+            (false, true) => return Err(anyhow!("This vault does not use a keyfile")),
+            _ => {},
+        }
+        let keyfile = if requires_keyfile { factors.keyfile.as_ref() } else { None };
+        let key = crypto::derive_key_with_secret(password, keyfile.map(Keyfile::as_bytes), &self.encrypted.header.salt, &self.params)?;
+        crypto::decrypt(&key, &self.encrypted, b"")
+            .map_err(|_| WrongCredentials::Password.into())
+    }
+}
+
+pub enum VaultFile {
+    Direct(DirectVault),
+    // `decoy` is None before version 5
+    Enveloped { header: VaultHeader, body: Vec<u8>, associated_data: Vec<u8>, decoy: Option<DecoyArea> },
+}
+
+impl VaultFile {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < MAGIC_LEN || &bytes[..MAGIC_LEN] != VAULT_MAGIC {
+            return Ok(VaultFile::Direct(DirectVault {
+                flags: 0,
+                params: Argon2Params::default(),
+                encrypted: EncryptedData::from_bytes(bytes)?,
+            }));
+        }
+
+        let rest = &bytes[MAGIC_LEN..];
+        let (&version, rest) = rest.split_first().ok_or_else(|| anyhow!("Vault header is truncated"))?;
+        match version {
+            DIRECT_VERSION => {
+                if rest.len() < 1 + PARAMS_LEN {
+                    return Err(anyhow!("Vault header is truncated"));
+                }
+                let flags = check_flags(rest[0])?;
+                if flags & FLAG_TOTP != 0 {
+                    return Err(anyhow!("Vault header is corrupted"));
+                }
+                let params = read_params(&rest[1..1 + PARAMS_LEN])?;
+                Ok(VaultFile::Direct(DirectVault {
+                    flags,
+                    params,
+                    encrypted: EncryptedData::from_bytes(&rest[1 + PARAMS_LEN..])?,
+                }))
+            },
+            AES_ONLY_VERSION | UNAUTHENTICATED_VERSION | NO_DECOY_VERSION | VAULT_VERSION => {
+                let (cipher, rest) = if version != AES_ONLY_VERSION {
+                    let (&id, rest) = rest.split_first().ok_or_else(|| anyhow!("Vault header is truncated"))?;
+                    let cipher = Cipher::from_id(id)
+                        .ok_or_else(|| anyhow!("Vault uses a cipher this version of Notas does not support"))?;
+                    (cipher, rest)
+                } else {
+                    (Cipher::Aes256Gcm, rest)
+                };
+                let (&count, mut rest) = rest.split_first().ok_or_else(|| anyhow!("Vault header is truncated"))?;
+                if count == 0 || count as usize > MAX_SLOTS {
+                    return Err(anyhow!("Vault header is corrupted"));
+                }
+                let mut slots = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let len = rest.get(1).map(|&flags| KeySlot::encoded_len(flags)).unwrap_or(SLOT_LEN);
+                    if rest.len() < len {
+                        return Err(anyhow!("Vault header is corrupted"));
+                    }
+                    slots.push(KeySlot::read(&rest[..len])?);
+                    rest = &rest[len..];
+                }
+                let associated_data = if version >= NO_DECOY_VERSION {
+                    bytes[..bytes.len() - rest.len()].to_vec()
+                } else {
+                    Vec::new()
+                };
+                let (rest, decoy) = if version == VAULT_VERSION {
+                    let (rest, decoy) = DecoyArea::split(rest)?;
+                    (rest, Some(decoy))
+                } else {
+                    (rest, None)
+                };
+                Ok(VaultFile::Enveloped { header: VaultHeader::new(cipher, slots), body: rest.to_vec(), associated_data, decoy })
+            },
+            _ => Err(anyhow!("Vault was written by a newer version of Notas (format {})", version)),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn requires_keyfile(&self) -> bool {
+        match self {
+            VaultFile::Direct(direct) => direct.flags & FLAG_KEYFILE != 0,
+            VaultFile::Enveloped { header, .. } => header.requires_keyfile(),
+        }
+    }
+
+    pub fn requires_totp(&self) -> bool {
+        match self {
+            VaultFile::Direct(_) => false,
+            VaultFile::Enveloped { header, .. } => header.requires_totp(),
+        }
+    }
+
+    // Serialize a version 5 vault
+    pub fn encode(header: &VaultHeader, data_key: &Key<Aes256Gcm>, plaintext: &[u8], decoy: &DecoyArea) -> Result<Vec<u8>> {
+        let mut bytes = header.to_bytes();
+        let body = crypto::seal_with(header.cipher, data_key, plaintext, &bytes)?;
+        bytes.extend_from_slice(&body);
+        decoy.write(&mut bytes);
+        Ok(bytes)
+    }
+}
+
+fn check_flags(flags: u8) -> Result<u8> {
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(anyhow!("Vault uses features this version of Notas does not support"));
+    }
+    Ok(flags)
+}
+
+fn read_params(bytes: &[u8]) -> Result<Argon2Params> {
+    bytes.first_chunk::<PARAMS_LEN>()
+        .and_then(crypto::read_params)
+        .ok_or_else(|| anyhow!("Vault uses unsupported key derivation parameters"))
+}
+
+// SHA-256 of a keyfile's contents. Any file works (a photo, a random blob on a USB
+// stick); only its exact bytes matter, so it must never be edited once in use.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct Keyfile([u8; 32]);
+
+impl Keyfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut file = fs::File::open(path).map_err(|e| anyhow!("Cannot read keyfile: {}", e))?;
+        let mut hasher = Sha256::new();
+        let mut buffer = [0u8; 8192];
+        let mut total = 0usize;
+        loop {
+            let read = file.read(&mut buffer).map_err(|e| anyhow!("Cannot read keyfile: {}", e))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            total += read;
+        }
+        buffer.zeroize();
+        if total == 0 {
+            return Err(anyhow!("Keyfile is empty"));
+        }
+        Ok(Self(hasher.finalize().into()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let digest: [u8; 32] = bytes.try_into()
+            .map_err(|_| anyhow!("Keyfile digest is corrupted"))?;
+        Ok(Self(digest))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// An unlock that failed because the password (or keyfile) or authenticator code was
+/// wrong, rather than because a factor is missing or the file can't be read. Only
+/// these count as failed attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WrongCredentials {
+    Password,
+    TotpCode,
+}
+
+impl fmt::Display for WrongCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WrongCredentials::Password => "Invalid password or corrupted data.",
+            WrongCredentials::TotpCode => "Authenticator code is incorrect",
+        })
+    }
+}
+
+impl std::error::Error for WrongCredentials {}
+
+// Everything besides the password that goes into unlocking a vault
+#[derive(Default, Clone)]
+pub struct UnlockFactors {
+    pub keyfile: Option<Keyfile>,
+    pub totp_code: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap KDF settings; the format is what's under test
+    const PARAMS: Argon2Params = Argon2Params { memory_cost: 8, time_cost: 1, parallelism: 1 };
+    const PASSWORD: &[u8] = b"correct horse";
+
+    fn vault_bytes() -> Vec<u8> {
+        let data_key = Key::<Aes256Gcm>::from_slice(&[3u8; KEY_LEN]).to_owned();
+        let slots = vec![
+            KeySlot::wrap(SlotKind::Password, PASSWORD, None, None, PARAMS, &data_key).unwrap(),
+            KeySlot::wrap(SlotKind::RecoveryCode, b"recovery code", None, None, PARAMS, &data_key).unwrap(),
+        ];
+        let header = VaultHeader::new(Cipher::XChaCha20Poly1305, slots);
+        VaultFile::encode(&header, &data_key, b"notes", &DecoyArea::filler(PARAMS)).unwrap()
+    }
+
+    fn header_len(bytes: &[u8]) -> usize {
+        match VaultFile::parse(bytes).unwrap() {
+            VaultFile::Enveloped { associated_data, .. } => associated_data.len(),
+            VaultFile::Direct(_) => panic!("not an enveloped vault"),
+        }
+    }
+
+    fn unlock(bytes: &[u8]) -> Result<Vec<u8>> {
+        match VaultFile::parse(bytes)? {
+            VaultFile::Enveloped { header, body, associated_data, .. } => {
+                let (data_key, _) = header.unwrap_key(SlotKind::Password, PASSWORD, &UnlockFactors::default())?;
+                header.open_body(&data_key, &body, &associated_data)
+            },
+            VaultFile::Direct(direct) => direct.decrypt(PASSWORD, &UnlockFactors::default()),
+        }
+    }
+
+    #[test]
+    fn unmodified_vault_unlocks() {
+        assert_eq!(unlock(&vault_bytes()).unwrap(), b"notes");
+    }
+
+    // Magic, version, cipher id, slot count, and every field of both key slots
+    #[test]
+    fn flipping_any_header_byte_fails_the_unlock() {
+        let bytes = vault_bytes();
+        let len = header_len(&bytes);
+        assert_eq!(len, MAGIC_LEN + 3 + 2 * SLOT_LEN);
+        for i in 0..len {
+            for bit in [0x01, 0x80] {
+                let mut tampered = bytes.clone();
+                tampered[i] ^= bit;
+                assert!(unlock(&tampered).is_err(), "byte {} ^ {:#04x} went unnoticed", i, bit);
+            }
+        }
+    }
+
+    #[test]
+    fn older_version_numbers_are_rejected() {
+        let bytes = vault_bytes();
+        for version in [DIRECT_VERSION, AES_ONLY_VERSION, UNAUTHENTICATED_VERSION, NO_DECOY_VERSION] {
+            let mut downgraded = bytes.clone();
+            downgraded[MAGIC_LEN] = version;
+            assert!(unlock(&downgraded).is_err(), "downgrade to version {} went unnoticed", version);
+        }
+    }
+
+    #[test]
+    fn used_decoy_is_the_size_of_filler() {
+        let data_key = Key::<Aes256Gcm>::from_slice(&[5u8; KEY_LEN]).to_owned();
+        let notes = vec![7u8; 172];
+        let decoy = DecoyArea::create(b"duress", PARAMS, &data_key, &notes).unwrap();
+        let (mut used, mut filler) = (Vec::new(), Vec::new());
+        decoy.write(&mut used);
+        DecoyArea::filler(PARAMS).write(&mut filler);
+        assert_eq!(used.len(), filler.len());
+        assert_eq!(used[used.len() - 4..], filler[filler.len() - 4..]);
+
+        let (rest, read_back) = DecoyArea::split(&used).unwrap();
+        assert!(rest.is_empty());
+        let key = read_back.unwrap_key(b"duress").unwrap();
+        assert_eq!(read_back.open_body(&key).unwrap(), notes);
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let mut bytes = vault_bytes();
+        bytes[MAGIC_LEN] = VAULT_VERSION + 1;
+        let error = VaultFile::parse(&bytes).err().unwrap();
+        assert!(error.to_string().contains("newer version"));
+    }
+}
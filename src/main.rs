@@ -3,18 +3,25 @@ mod core;
 use gtk4 as gtk;
 use gtk::{
     prelude::*,
+    gio,
     glib,
     Application, ApplicationWindow, Label, ListBoxRow,
 };
 use libadwaita as adw;
 use once_cell::sync::OnceCell;
 use std::sync::{Arc, Mutex};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
+use zeroize::Zeroize;
 
 use core::manager::CoreManager;
-use core::data::{AppSettings, AppTheme, EditorFont};
+use core::vault::{Keyfile, UnlockFactors};
+use core::totp::TotpSecret;
+use core::data::{AppSettings, AppTheme, Cipher, EditorFont, KeyCacheBackend, LockTriggers, PasswordPolicy};
+use core::archive::ExportScope;
+use core::import::{ImportAction, ImportPreview, ImportSource, ImportStatus};
+use core::quick_unlock::{MAX_TRIES, MIN_PIN_LEN};
 
 static CORE_MANAGER: OnceCell<Arc<Mutex<CoreManager>>> = OnceCell::new();
 static TOKIO_RUNTIME: OnceCell<tokio::runtime::Runtime> = OnceCell::new();
@@ -24,6 +31,12 @@ const APP_ID: &str = "com.jegly.Notas";
 thread_local! {
     static LAST_ACTIVITY: RefCell<Instant> = RefCell::new(Instant::now());
     static CLIPBOARD_TIMER: RefCell<Option<glib::SourceId>> = RefCell::new(None);
+    // Re-checks the auto-lock triggers and sets the timer for the next deadline; only
+    // set while the main window is open
+    static AUTO_LOCK_CHECK: RefCell<Option<Rc<dyn Fn()>>> = RefCell::new(None);
+    static AUTO_LOCK_TIMER: RefCell<Option<glib::SourceId>> = RefCell::new(None);
+    // What Notas last put on the clipboard, to tell whether it's still there
+    static CLIPBOARD_CONTENT: RefCell<Option<gtk::gdk::ContentProvider>> = RefCell::new(None);
     static CURRENT_THEME: RefCell<AppTheme> = RefCell::new(AppTheme::Dark);
     static CSS_PROVIDER: RefCell<Option<gtk::CssProvider>> = RefCell::new(None);
     static EDITOR_FONT: RefCell<EditorFont> = RefCell::new(EditorFont::default());
@@ -32,6 +45,9 @@ thread_local! {
 }
 
 fn main() -> glib::ExitCode {
+    // Before any secret is in memory: no core dumps, no ptrace from other processes
+    core::guarded::harden_process();
+
     let runtime = tokio::runtime::Runtime::new().expect("Failed to create tokio runtime");
     if TOKIO_RUNTIME.set(runtime).is_err() {
         eprintln!("Failed to set tokio runtime");
@@ -295,8 +311,17 @@ fn get_dark_css() -> String {
         
         .note-title {{ font-weight: 600; font-size: 0.9em; color: @text_color; }}
         .note-preview {{ font-size: 0.78em; color: @subtext_color; margin-top: 2px; }}
+        .privacy-screen {{ filter: blur(16px); }}
+        .secret-field {{ color: @subtext_color; }}
+        .secret-field button {{ padding: 0 4px; min-height: 0; font-size: 0.85em; }}
         .note-date {{ font-size: 0.7em; color: alpha(@subtext_color, 0.6); margin-top: 2px; }}
         .note-pinned {{ color: #a08050; }}
+        .note-locked {{ color: @subtext_color; }}
+        .strength-meter trough {{ min-height: 4px; }}
+        .strength-meter block.empty {{ background-color: @overlay_color; }}
+        .strength-meter block.filled.low {{ background-color: #a06060; }}
+        .strength-meter block.filled.high {{ background-color: #a09060; }}
+        .strength-meter block.filled.full {{ background-color: #60a060; }}
         
         .editor-area {{ 
             background: linear-gradient(160deg, 
@@ -643,7 +668,16 @@ fn get_light_css() -> String {
         
         .note-title {{ font-weight: 600; font-size: 0.9em; color: @text_color; }}
         .note-preview {{ font-size: 0.78em; color: @subtext_color; margin-top: 2px; }}
+        .privacy-screen {{ filter: blur(16px); }}
+        .secret-field {{ color: @subtext_color; }}
+        .secret-field button {{ padding: 0 4px; min-height: 0; font-size: 0.85em; }}
         .note-date {{ font-size: 0.7em; color: alpha(@subtext_color, 0.7); margin-top: 2px; }}
+        .note-locked {{ color: @subtext_color; }}
+        .strength-meter trough {{ min-height: 4px; }}
+        .strength-meter block.empty {{ background-color: @overlay_color; }}
+        .strength-meter block.filled.low {{ background-color: #a06060; }}
+        .strength-meter block.filled.high {{ background-color: #a09060; }}
+        .strength-meter block.filled.full {{ background-color: #60a060; }}
         
         .editor-area {{ background-color: @bg_color; padding: 16px; }}
         .title-entry {{ font-size: 1.3em; font-weight: bold; background-color: transparent; border: none; border-bottom: 1px solid @border_color; border-radius: 0; padding: 6px 4px; margin-bottom: 12px; color: @text_color; outline: none; }}
@@ -741,10 +775,19 @@ fn reset_activity_timer() {
     });
 }
 
+fn recheck_auto_lock() {
+    let check = AUTO_LOCK_CHECK.with(|check| check.borrow().clone());
+    if let Some(check) = check {
+        check();
+    }
+}
+
 fn build_ui(app: &Application) {
     reset_activity_timer();
     
-    if !CoreManager::is_unlocked() {
+    // Another Notas process may already have unlocked this vault in this login session
+    let _ = CORE_MANAGER.get().unwrap().lock().unwrap().unlock_from_key_cache();
+    if !CORE_MANAGER.get().unwrap().lock().unwrap().is_unlocked() {
         show_password_screen(app);
     } else {
         show_main_window(app);
@@ -803,6 +846,11 @@ fn create_traffic_light_buttons(window: &ApplicationWindow) -> gtk::Box {
 }
 
 fn show_password_screen(app: &Application) {
+    if CORE_MANAGER.get().unwrap().lock().unwrap().has_quick_unlock() {
+        show_quick_unlock_screen(app);
+        return;
+    }
+
     let window = ApplicationWindow::builder()
         .application(app)
         .title("Notas")
@@ -838,43 +886,283 @@ fn show_password_screen(app: &Application) {
     let title = Label::new(Some("Notas"));
     title.add_css_class("lock-title");
 
-    let subtitle = Label::new(Some("Enter your master password"));
+    let (requires_keyfile, requires_totp, is_new_vault, has_recovery_code, quick_unlock_minutes) = {
+        let manager = CORE_MANAGER.get().unwrap().lock().unwrap();
+        (manager.requires_keyfile(), manager.requires_totp(), !manager.vault_exists(), manager.has_recovery_code(),
+            manager.get_settings().quick_unlock_minutes)
+    };
+    let subtitle = Label::new(Some(match (is_new_vault, requires_keyfile, requires_totp) {
+        (true, _, _) => "Choose a master password for your new vault",
+        (false, true, true) => "Enter your master password, authenticator code and keyfile",
+        (false, true, false) => "Enter your master password and choose your keyfile",
+        (false, false, true) => "Enter your master password and authenticator code",
+        (false, false, false) => "Enter your master password",
+    }));
     subtitle.add_css_class("lock-subtitle");
 
+    // Vault picker, shown once there's more than one vault; picking one switches the
+    // lock screen to it
+    let (vault_names, vault_paths, current_vault) = {
+        let manager = CORE_MANAGER.get().unwrap().lock().unwrap();
+        let vaults = manager.vaults();
+        (
+            vaults.iter().map(|entry| entry.name.clone()).collect::<Vec<_>>(),
+            vaults.iter().map(|entry| entry.path.clone()).collect::<Vec<_>>(),
+            vaults.iter().position(|entry| &entry.path == manager.get_data_path()).unwrap_or(0) as u32,
+        )
+    };
+    let vault_dropdown = gtk::DropDown::from_strings(&vault_names.iter().map(String::as_str).collect::<Vec<_>>());
+    vault_dropdown.set_selected(current_vault);
+    vault_dropdown.set_margin_bottom(12);
+    vault_dropdown.set_visible(vault_paths.len() > 1);
+    if let Some(path) = vault_paths.get(current_vault as usize) {
+        vault_dropdown.set_tooltip_text(Some(&path.display().to_string()));
+    }
+
+    let window_for_vaults = window.clone();
+    let app_for_vaults = app.clone();
+    vault_dropdown.connect_selected_notify(move |dropdown| {
+        let Some(path) = vault_paths.get(dropdown.selected() as usize) else { return };
+        let result = CORE_MANAGER.get().unwrap().lock().unwrap().switch_vault(path);
+        if result.is_ok() {
+            window_for_vaults.close();
+            show_password_screen(&app_for_vaults);
+        }
+    });
+
     let password_entry = gtk::PasswordEntry::new();
     password_entry.set_placeholder_text(Some("Password"));
     password_entry.add_css_class("password-entry");
     password_entry.set_show_peek_icon(true);
 
+    // A new vault asks for the password twice and shows how guessable it is
+    let confirm_entry = gtk::PasswordEntry::new();
+    confirm_entry.set_placeholder_text(Some("Confirm Password"));
+    confirm_entry.add_css_class("password-entry");
+    confirm_entry.set_show_peek_icon(true);
+    confirm_entry.set_margin_top(8);
+    confirm_entry.set_visible(is_new_vault);
+
+    let (strength_bar, strength_label) = build_strength_meter();
+    strength_bar.set_margin_top(8);
+    strength_bar.set_visible(is_new_vault);
+    strength_label.set_visible(is_new_vault);
+    let strength_bar_clone = strength_bar.clone();
+    let strength_label_clone = strength_label.clone();
+    password_entry.connect_changed(move |entry| {
+        if is_new_vault {
+            update_strength_meter(&strength_bar_clone, &strength_label_clone, entry.text().as_str());
+        }
+    });
+
+    // Authenticator code, only asked for when the vault has two-factor codes turned on
+    let totp_entry = gtk::Entry::new();
+    totp_entry.set_placeholder_text(Some("Authenticator Code"));
+    totp_entry.add_css_class("password-entry");
+    totp_entry.set_input_purpose(gtk::InputPurpose::Digits);
+    totp_entry.set_max_length(7);
+    totp_entry.set_margin_top(8);
+    totp_entry.set_visible(requires_totp);
+
+    // PIN that can reopen the vault for a while after it locks; only offered when
+    // quick unlock is turned on, and never required
+    let pin_entry = gtk::Entry::new();
+    pin_entry.set_placeholder_text(Some("Quick Unlock PIN (optional)"));
+    pin_entry.add_css_class("password-entry");
+    pin_entry.set_visibility(false);
+    pin_entry.set_input_purpose(gtk::InputPurpose::Pin);
+    pin_entry.set_margin_top(8);
+    pin_entry.set_tooltip_text(Some(&format!("Reopen Notas with this PIN for {} min after it locks", quick_unlock_minutes)));
+    pin_entry.set_visible(quick_unlock_minutes > 0);
+
+    // Optional keyfile - also used when creating a new vault
+    let keyfile_path: Rc<RefCell<Option<std::path::PathBuf>>> = Rc::new(RefCell::new(None));
+    let keyfile_row = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    keyfile_row.set_margin_top(8);
+    let keyfile_button = gtk::Button::with_label("Use Keyfile...");
+    keyfile_button.add_css_class("secondary-button");
+    keyfile_button.set_hexpand(true);
+    let keyfile_clear = gtk::Button::with_label("✕");
+    keyfile_clear.add_css_class("icon-button");
+    keyfile_clear.set_tooltip_text(Some("Don't use a keyfile"));
+    keyfile_clear.set_visible(false);
+    keyfile_row.append(&keyfile_button);
+    keyfile_row.append(&keyfile_clear);
+
+    let window_for_keyfile = window.clone();
+    let keyfile_path_clone = keyfile_path.clone();
+    let keyfile_clear_clone = keyfile_clear.clone();
+    keyfile_button.connect_clicked(move |btn| {
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Choose Keyfile"),
+            Some(&window_for_keyfile),
+            gtk::FileChooserAction::Open,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Choose", gtk::ResponseType::Accept)],
+        );
+        let keyfile_path = keyfile_path_clone.clone();
+        let keyfile_clear = keyfile_clear_clone.clone();
+        let btn = btn.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                    btn.set_label(&format!("Keyfile: {}", name));
+                    keyfile_clear.set_visible(true);
+                    *keyfile_path.borrow_mut() = Some(path);
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    let keyfile_button_clone = keyfile_button.clone();
+    let keyfile_path_clone = keyfile_path.clone();
+    keyfile_clear.connect_clicked(move |btn| {
+        *keyfile_path_clone.borrow_mut() = None;
+        keyfile_button_clone.set_label("Use Keyfile...");
+        btn.set_visible(false);
+    });
+
     let status_label = Arc::new(Label::new(None));
     status_label.set_margin_top(12);
 
     let unlock_button = gtk::Button::with_label("Unlock");
     unlock_button.add_css_class("unlock-button");
 
+    // Back-off wait and erase warning after failed attempts
+    let attempts_label = Label::new(None);
+    attempts_label.add_css_class("note-date");
+    attempts_label.set_margin_top(8);
+    attempts_label.set_justify(gtk::Justification::Center);
+    let counting_down = Rc::new(Cell::new(false));
+    refresh_attempt_status(&attempts_label, &unlock_button, counting_down.clone());
+
+    let forgot_button = gtk::Button::with_label("Forgot password?");
+    forgot_button.add_css_class("flat");
+    forgot_button.add_css_class("note-date");
+    forgot_button.set_margin_top(8);
+    forgot_button.set_visible(has_recovery_code);
+
+    let vault_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    vault_buttons.set_halign(gtk::Align::Center);
+    let new_vault_button = gtk::Button::with_label("New Vault");
+    new_vault_button.add_css_class("flat");
+    new_vault_button.add_css_class("note-date");
+    let open_vault_button = gtk::Button::with_label("Open Vault File");
+    open_vault_button.add_css_class("flat");
+    open_vault_button.add_css_class("note-date");
+    vault_buttons.append(&new_vault_button);
+    vault_buttons.append(&open_vault_button);
+
+    let window_for_vaults = window.clone();
+    let app_for_vaults = app.clone();
+    new_vault_button.connect_clicked(move |_| {
+        show_new_vault_dialog(&window_for_vaults, &app_for_vaults);
+    });
+
+    let window_for_vaults = window.clone();
+    let app_for_vaults = app.clone();
+    let status_label_clone = status_label.clone();
+    open_vault_button.connect_clicked(move |_| {
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Open Vault File"),
+            Some(&window_for_vaults),
+            gtk::FileChooserAction::Open,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Open", gtk::ResponseType::Accept)],
+        );
+        let window = window_for_vaults.clone();
+        let app = app_for_vaults.clone();
+        let status_label = status_label_clone.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    let result = CORE_MANAGER.get().unwrap().lock().unwrap().add_vault_file(&path);
+                    match result {
+                        Ok(_) => {
+                            window.close();
+                            show_password_screen(&app);
+                        },
+                        Err(e) => status_label.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    let window_for_forgot = window.clone();
+    let app_for_forgot = app.clone();
+    let keyfile_path_clone = keyfile_path.clone();
+    forgot_button.connect_clicked(move |_| {
+        show_forgot_password_dialog(&window_for_forgot, &app_for_forgot, keyfile_path_clone.clone());
+    });
+
     let window_clone = window.clone();
     let app_clone = app.clone();
     let status_label_clone = status_label.clone();
     let password_entry_clone = password_entry.clone();
+    let totp_entry_clone = totp_entry.clone();
+    let confirm_entry_clone = confirm_entry.clone();
+    let pin_entry_clone = pin_entry.clone();
+    let attempts_label_clone = attempts_label.clone();
+    let unlock_button_clone = unlock_button.clone();
 
     let do_unlock = move || {
+        // Enter in the password field still reaches here while waiting
+        if !unlock_button_clone.is_sensitive() {
+            return;
+        }
+
         let password = password_entry_clone.text().to_string();
         if password.is_empty() {
             status_label_clone.set_markup("<span foreground='#a06060'>Password cannot be empty</span>");
             return;
         }
+        if is_new_vault && password != confirm_entry_clone.text().as_str() {
+            status_label_clone.set_markup("<span foreground='#a06060'>Passwords don't match</span>");
+            return;
+        }
+
+        let keyfile = match keyfile_path.borrow().as_deref().map(Keyfile::load).transpose() {
+            Ok(keyfile) => keyfile,
+            Err(e) => {
+                status_label_clone.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                return;
+            }
+        };
+
+        let totp_code = requires_totp.then(|| totp_entry_clone.text().to_string());
+
+        let mut pin = pin_entry_clone.text().to_string();
+        if !pin.is_empty() && pin.chars().count() < MIN_PIN_LEN {
+            status_label_clone.set_markup(&format!("<span foreground='#a06060'>PIN must be at least {} characters</span>", MIN_PIN_LEN));
+            pin.zeroize();
+            return;
+        }
 
         let manager_rc = CORE_MANAGER.get().unwrap().clone();
         let master_password = core::data::MasterPassword::from(password.as_str());
 
-        let result = manager_rc.lock().unwrap().unlock(master_password);
+        let result = manager_rc.lock().unwrap().unlock(master_password, UnlockFactors { keyfile, totp_code });
+        if result.is_ok() && !pin.is_empty() {
+            let _ = manager_rc.lock().unwrap().enable_quick_unlock(&pin);
+        }
+        pin.zeroize();
         match result {
             Ok(_) => {
                 window_clone.close();
                 show_main_window(&app_clone);
+                if is_new_vault {
+                    if let Some(main_window) = app_clone.active_window() {
+                        show_recovery_offer_dialog(&main_window, manager_rc.clone());
+                    }
+                }
             },
             Err(e) => {
                 status_label_clone.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                refresh_attempt_status(&attempts_label_clone, &unlock_button_clone, counting_down.clone());
             }
         };
     };
@@ -884,21 +1172,323 @@ fn show_password_screen(app: &Application) {
         do_unlock_clone();
     });
 
+    let do_unlock_clone = do_unlock.clone();
+    totp_entry.connect_activate(move |_| {
+        do_unlock_clone();
+    });
+
+    let do_unlock_clone = do_unlock.clone();
+    confirm_entry.connect_activate(move |_| {
+        do_unlock_clone();
+    });
+
+    let do_unlock_clone = do_unlock.clone();
+    pin_entry.connect_activate(move |_| {
+        do_unlock_clone();
+    });
+
+    let totp_entry_clone = totp_entry.clone();
+    let confirm_entry_clone = confirm_entry.clone();
     password_entry.connect_activate(move |_| {
+        // Move on to the confirmation or the code before trying to unlock
+        if is_new_vault && confirm_entry_clone.text().is_empty() {
+            confirm_entry_clone.grab_focus();
+            return;
+        }
+        if requires_totp && totp_entry_clone.text().is_empty() {
+            totp_entry_clone.grab_focus();
+            return;
+        }
         do_unlock();
     });
 
     main_box.append(&title);
     main_box.append(&subtitle);
+    main_box.append(&vault_dropdown);
     main_box.append(&password_entry);
+    main_box.append(&confirm_entry);
+    main_box.append(&strength_bar);
+    main_box.append(&strength_label);
+    main_box.append(&totp_entry);
+    main_box.append(&keyfile_row);
+    main_box.append(&pin_entry);
     main_box.append(status_label.as_ref());
     main_box.append(&unlock_button);
+    main_box.append(&attempts_label);
+    main_box.append(&forgot_button);
+    main_box.append(&vault_buttons);
 
     window.set_child(Some(&main_box));
     window.present();
     password_entry.grab_focus();
 }
 
+// Name a new vault and optionally choose where it goes; the lock screen then asks for
+// its password and creates it on the first unlock
+// Lock screen while a quick unlock PIN is set up: the PIN reopens the vault, and after
+// too many wrong PINs (or on request) the full password screen takes over
+fn show_quick_unlock_screen(app: &Application) {
+    let window = ApplicationWindow::builder()
+        .application(app)
+        .title("Notas")
+        .default_width(420)
+        .default_height(300)
+        .build();
+
+    let header = gtk::HeaderBar::new();
+    header.set_show_title_buttons(false);
+    header.add_css_class("custom-headerbar");
+    let traffic_buttons = create_traffic_light_buttons(&window);
+    header.pack_start(&traffic_buttons);
+    let header_title = Label::new(Some("Notas"));
+    header_title.add_css_class("headerbar-title");
+    header.set_title_widget(Some(&header_title));
+    window.set_titlebar(Some(&header));
+    window.add_css_class("lock-screen");
+
+    let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    main_box.set_valign(gtk::Align::Center);
+    main_box.set_halign(gtk::Align::Center);
+    main_box.set_margin_top(20);
+    main_box.set_margin_bottom(40);
+    main_box.set_margin_start(40);
+    main_box.set_margin_end(40);
+
+    let title = Label::new(Some("Notas"));
+    title.add_css_class("lock-title");
+    let subtitle = Label::new(Some("Enter your PIN"));
+    subtitle.add_css_class("lock-subtitle");
+
+    let pin_entry = gtk::Entry::new();
+    pin_entry.set_placeholder_text(Some("PIN"));
+    pin_entry.add_css_class("password-entry");
+    pin_entry.set_visibility(false);
+    pin_entry.set_input_purpose(gtk::InputPurpose::Pin);
+
+    let status_label = Label::new(None);
+    status_label.set_margin_top(12);
+
+    let unlock_button = gtk::Button::with_label("Unlock");
+    unlock_button.add_css_class("unlock-button");
+
+    let password_button = gtk::Button::with_label("Use password instead");
+    password_button.add_css_class("flat");
+    password_button.add_css_class("note-date");
+    password_button.set_margin_top(8);
+
+    let window_clone = window.clone();
+    let app_clone = app.clone();
+    password_button.connect_clicked(move |_| {
+        CORE_MANAGER.get().unwrap().lock().unwrap().disable_quick_unlock();
+        window_clone.close();
+        show_password_screen(&app_clone);
+    });
+
+    let do_unlock = glib::clone!(@strong window, @strong app, @strong pin_entry, @strong status_label,
+        @strong unlock_button => move || {
+        let mut pin = pin_entry.text().to_string();
+        if pin.is_empty() {
+            return;
+        }
+        let (result, still_available) = {
+            let mut manager = CORE_MANAGER.get().unwrap().lock().unwrap();
+            let result = manager.quick_unlock(&pin);
+            (result, manager.has_quick_unlock())
+        };
+        pin.zeroize();
+        pin_entry.set_text("");
+        match result {
+            Ok(_) => {
+                window.close();
+                show_main_window(&app);
+            },
+            Err(e) => {
+                status_label.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                if !still_available {
+                    // Only the password can open the vault now
+                    pin_entry.set_sensitive(false);
+                    unlock_button.set_visible(false);
+                }
+            },
+        }
+    });
+
+    let do_unlock_clone = do_unlock.clone();
+    unlock_button.connect_clicked(move |_| {
+        do_unlock_clone();
+    });
+    pin_entry.connect_activate(move |_| {
+        do_unlock();
+    });
+
+    main_box.append(&title);
+    main_box.append(&subtitle);
+    main_box.append(&pin_entry);
+    main_box.append(&status_label);
+    main_box.append(&unlock_button);
+    main_box.append(&password_button);
+
+    window.set_child(Some(&main_box));
+    window.present();
+    pin_entry.grab_focus();
+}
+
+fn show_new_vault_dialog(window: &ApplicationWindow, app: &Application) {
+    let (dialog, vbox) = build_dialog_window(window, "New Vault", 360);
+
+    let info = Label::new(Some("Each vault has its own password and notes. The current vault stays locked."));
+    info.set_wrap(true);
+    info.set_xalign(0.0);
+
+    let name_entry = gtk::Entry::new();
+    name_entry.set_placeholder_text(Some("Vault Name"));
+
+    // Stored next to the default vault unless another location is chosen
+    let location: Rc<RefCell<Option<std::path::PathBuf>>> = Rc::new(RefCell::new(None));
+    let location_button = gtk::Button::with_label("Default Location");
+    location_button.add_css_class("secondary-button");
+
+    let status = Label::new(None);
+    status.set_halign(gtk::Align::Start);
+    status.set_wrap(true);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
+    let create_button = gtk::Button::with_label("Create");
+    create_button.add_css_class("action-button");
+    button_box.append(&cancel_button);
+    button_box.append(&create_button);
+
+    vbox.append(&info);
+    vbox.append(&name_entry);
+    vbox.append(&location_button);
+    vbox.append(&status);
+    vbox.append(&button_box);
+
+    let dialog_clone = dialog.clone();
+    let location_clone = location.clone();
+    let name_entry_clone = name_entry.clone();
+    location_button.connect_clicked(move |btn| {
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Vault Location"),
+            Some(&dialog_clone),
+            gtk::FileChooserAction::Save,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Choose", gtk::ResponseType::Accept)],
+        );
+        let name = name_entry_clone.text();
+        file_chooser.set_current_name(&format!("{}.dat", if name.is_empty() { "vault" } else { name.as_str() }));
+        let location = location_clone.clone();
+        let btn = btn.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    btn.set_label(&path.display().to_string());
+                    *location.borrow_mut() = Some(path);
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| dialog_clone.close());
+
+    let dialog_clone = dialog.clone();
+    let window = window.clone();
+    let app = app.clone();
+    let name_entry_clone = name_entry.clone();
+    let do_create = move || {
+        let name = name_entry_clone.text().to_string();
+        let path = location.borrow().clone();
+        let result = CORE_MANAGER.get().unwrap().lock().unwrap().create_vault(&name, path);
+        match result {
+            Ok(_) => {
+                dialog_clone.close();
+                window.close();
+                show_password_screen(&app);
+            },
+            Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    };
+    let do_create = Rc::new(do_create);
+    let do_create_clone = do_create.clone();
+    create_button.connect_clicked(move |_| do_create_clone());
+    name_entry.connect_activate(move |_| do_create());
+
+    dialog.present();
+}
+
+// Strength bar with a one-line verdict, for screens that set a master password
+fn build_strength_meter() -> (gtk::LevelBar, Label) {
+    let bar = gtk::LevelBar::for_interval(0.0, 5.0);
+    bar.set_mode(gtk::LevelBarMode::Discrete);
+    bar.add_css_class("strength-meter");
+    // Block colours: red up to weak, amber up to strong, green for very strong
+    bar.add_offset_value("low", 2.0);
+    bar.add_offset_value("high", 4.0);
+    bar.add_offset_value("full", 5.0);
+
+    let label = Label::new(None);
+    label.add_css_class("note-date");
+    label.set_halign(gtk::Align::Start);
+    label.set_wrap(true);
+    label.set_max_width_chars(40);
+    (bar, label)
+}
+
+fn update_strength_meter(bar: &gtk::LevelBar, label: &Label, password: &str) {
+    if password.is_empty() {
+        bar.set_value(0.0);
+        label.set_text("");
+        return;
+    }
+    let strength = core::strength::estimate(password);
+    bar.set_value(strength.score as f64 + 1.0);
+    match strength.warning.or(strength.suggestions.first().copied()) {
+        Some(hint) => label.set_text(&format!("{} — {}", strength.label(), hint)),
+        None => label.set_text(&format!("{} — about {} to guess offline", strength.label(), strength.crack_time())),
+    }
+}
+
+// Show the failed-attempt state on the lock screen, keeping the unlock button disabled
+// and counting down while a back-off wait is running
+fn refresh_attempt_status(label: &Label, unlock_button: &gtk::Button, counting_down: Rc<Cell<bool>>) {
+    let (record, wipe_after) = {
+        let manager = CORE_MANAGER.get().unwrap().lock().unwrap();
+        (manager.failed_attempts(), manager.get_settings().wipe_after_failures)
+    };
+    let wait = record.retry_in(core::totp::unix_now());
+
+    let mut lines = Vec::new();
+    if record.tampered {
+        lines.push("The failed attempt record was changed or removed".to_string());
+    }
+    if wait > 0 {
+        lines.push(format!("Too many failed attempts. Try again in {} s", wait));
+    }
+    if wipe_after > 0 && record.failures > 0 {
+        let left = wipe_after.saturating_sub(record.failures);
+        lines.push(format!("{} attempt{} left before the vault is erased", left, if left == 1 { "" } else { "s" }));
+    }
+    label.set_markup(&format!("<span foreground='#a06060'>{}</span>", lines.join("\n")));
+    label.set_visible(!lines.is_empty());
+    unlock_button.set_sensitive(wait == 0);
+
+    // One tick at a time; each refresh schedules the next while the wait lasts
+    if wait > 0 && !counting_down.replace(true) {
+        let label = label.clone();
+        let unlock_button = unlock_button.clone();
+        glib::timeout_add_seconds_local(1, move || {
+            counting_down.set(false);
+            refresh_attempt_status(&label, &unlock_button, counting_down.clone());
+            glib::ControlFlow::Break
+        });
+    }
+}
+
 fn show_main_window(app: &Application) {
     let manager_rc = CORE_MANAGER.get().unwrap().clone();
 
@@ -918,8 +1508,15 @@ fn show_main_window(app: &Application) {
     let traffic_buttons = create_traffic_light_buttons(&window);
     header.pack_start(&traffic_buttons);
     
-    // Title in center
-    let header_title = Label::new(Some("Notas"));
+    // Title in center, with the vault's name once there's more than one
+    let vault_name = {
+        let manager = manager_rc.lock().unwrap();
+        manager.current_vault().filter(|_| manager.vaults().len() > 1).map(|entry| entry.name.clone())
+    };
+    let header_title = Label::new(Some(&match vault_name {
+        Some(name) => format!("Notas — {}", name),
+        None => "Notas".to_string(),
+    }));
     header_title.add_css_class("headerbar-title");
     header.set_title_widget(Some(&header_title));
     
@@ -931,6 +1528,16 @@ fn show_main_window(app: &Application) {
     });
     window.add_controller(motion_controller);
 
+    // Typing in the editor or an entry is consumed there and never bubbles up to the
+    // window's shortcut controller, so activity is noted on the way down
+    let activity_controller = gtk::EventControllerKey::new();
+    activity_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+    activity_controller.connect_key_pressed(|_, _, _, _| {
+        reset_activity_timer();
+        glib::Propagation::Proceed
+    });
+    window.add_controller(activity_controller);
+
     let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
     paned.set_position(220);
     paned.set_wide_handle(false);
@@ -1065,8 +1672,20 @@ fn show_main_window(app: &Application) {
     status_label.set_hexpand(true);
     status_label.set_halign(gtk::Align::Start);
 
+    // Tell the owner about failed attempts made while the vault was locked
+    if let Some(report) = manager_rc.lock().unwrap().take_unlock_report() {
+        if report.tampered {
+            status_label.set_markup("<span foreground='#a06060'>The failed attempt record was changed while locked</span>");
+        } else {
+            status_label.set_markup(&format!(
+                "<span foreground='#a06060'>{} failed unlock attempt{} since you last unlocked</span>",
+                report.failures, if report.failures == 1 { "" } else { "s" }));
+        }
+    }
+
     let copy_button = Arc::new(gtk::Button::with_label("Copy"));
     copy_button.add_css_class("status-button");
+    copy_button.set_tooltip_text(Some("Copy the selection, or the whole note (Ctrl+Shift+L copies the current line)"));
     copy_button.set_sensitive(false);
 
     let save_button = Arc::new(gtk::Button::with_label("Save"));
@@ -1077,10 +1696,28 @@ fn show_main_window(app: &Application) {
     delete_button.add_css_class("status-button");
     delete_button.set_sensitive(false);
 
+    // Share is available whenever a note is open, same as Copy
+    let share_button = gtk::Button::with_label("Share");
+    share_button.add_css_class("status-button");
+    share_button.set_tooltip_text(Some("Share encrypted"));
+    copy_button.bind_property("sensitive", &share_button, "sensitive")
+        .sync_create()
+        .build();
+
+    // Lock applies to any selected note, including one that is still locked
+    let note_lock_button = gtk::Button::with_label("Lock");
+    note_lock_button.add_css_class("status-button");
+    note_lock_button.set_tooltip_text(Some("Lock this note with its own passphrase"));
+    delete_button.bind_property("sensitive", &note_lock_button, "sensitive")
+        .sync_create()
+        .build();
+
     status_bar.append(&title_toggle_label);
     status_bar.append(&title_toggle);
     status_bar.append(status_label.as_ref());
     status_bar.append(copy_button.as_ref());
+    status_bar.append(&share_button);
+    status_bar.append(&note_lock_button);
     status_bar.append(save_button.as_ref());
     status_bar.append(delete_button.as_ref());
     
@@ -1135,18 +1772,48 @@ fn show_main_window(app: &Application) {
             }
             row_ids.lock().unwrap().clear();
 
-            let notes = manager_rc.lock().unwrap().get_notes();
             let search = search_text.lock().unwrap().to_lowercase();
-            
-            for note in notes {
-                if !search.is_empty() {
-                    let title_lower = note.title.to_lowercase();
-                    let content_lower = note.content.to_lowercase();
-                    if !title_lower.contains(&search) && !content_lower.contains(&search) {
-                        continue;
+
+            // Collect what the rows show while the manager is locked, without copying
+            // note contents out of guarded memory
+            struct NoteRow {
+                id: u64,
+                title: String,
+                pinned: bool,
+                locked: bool,
+                preview: String,
+                updated_at: String,
+            }
+            let rows: Vec<NoteRow> = {
+                let manager = manager_rc.lock().unwrap();
+                manager.get_notes().iter().filter_map(|note| {
+                    // Locked notes don't take part in search until they are unlocked
+                    if !search.is_empty() && note.is_locked() && !manager.is_note_open(note.id) {
+                        return None;
                     }
-                }
-                
+                    // Secret fields are neither searched nor previewed
+                    let content = core::secrets::mask_secrets(&note.content);
+                    if !search.is_empty() && !note.title.to_lowercase().contains(&search)
+                        && !content.to_lowercase().contains(&search) {
+                        return None;
+                    }
+                    let preview = if note.is_locked() {
+                        "Locked".to_string()
+                    } else {
+                        content.lines().next().unwrap_or("").chars().take(40).collect::<String>()
+                    };
+                    Some(NoteRow {
+                        id: note.id,
+                        title: note.title.clone(),
+                        pinned: note.pinned,
+                        locked: note.is_locked(),
+                        preview,
+                        updated_at: note.updated_at.format("%b %d, %Y").to_string(),
+                    })
+                }).collect()
+            };
+
+            for note in rows {
                 let row = ListBoxRow::new();
                 let row_box = gtk::Box::new(gtk::Orientation::Vertical, 1);
                 row_box.set_margin_top(2);
@@ -1167,13 +1834,19 @@ fn show_main_window(app: &Application) {
                 title_label.set_hexpand(true);
                 title_box.append(&title_label);
 
-                let preview = note.content.lines().next().unwrap_or("").chars().take(40).collect::<String>();
-                let preview_label = Label::new(Some(&preview));
+                if note.locked {
+                    let lock_icon = Label::new(Some("⚿"));
+                    lock_icon.add_css_class("note-locked");
+                    lock_icon.set_tooltip_text(Some("Locked"));
+                    title_box.append(&lock_icon);
+                }
+
+                let preview_label = Label::new(Some(&note.preview));
                 preview_label.set_halign(gtk::Align::Start);
                 preview_label.add_css_class("note-preview");
                 preview_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
 
-                let date_label = Label::new(Some(&note.updated_at.format("%b %d, %Y").to_string()));
+                let date_label = Label::new(Some(&note.updated_at));
                 date_label.set_halign(gtk::Align::Start);
                 date_label.add_css_class("note-date");
 
@@ -1214,13 +1887,9 @@ fn show_main_window(app: &Application) {
         let _ = manager_clone.lock().unwrap().update_settings(settings);
     });
 
-    let window_clone = window.clone();
     let app_clone = app.clone();
     lock_button.connect_clicked(move |_| {
-        let manager_rc = CORE_MANAGER.get().unwrap().clone();
-        manager_rc.lock().unwrap().lock();
-        window_clone.close();
-        show_password_screen(&app_clone);
+        lock_app(&app_clone);
     });
 
     let window_clone = window.clone();
@@ -1303,15 +1972,35 @@ fn show_main_window(app: &Application) {
         auto_create_for_content();
     });
 
+    // Only a `}}` or a closing fence can complete a secret field. The buffer can't be
+    // changed during an insertion, so masking waits until it's done.
+    let mask_pending = Rc::new(Cell::new(false));
+    content_buffer.connect_insert_text(glib::clone!(@strong content_view, @strong status_label => move |_, _, text| {
+        if (text.contains('}') || text.contains('`')) && !mask_pending.replace(true) {
+            glib::idle_add_local_once(glib::clone!(@strong mask_pending, @strong content_view,
+                @strong status_label => move || {
+                mask_pending.set(false);
+                mask_secret_fields(&content_view, status_label.clone(), true);
+            }));
+        }
+    }));
+
     note_list_box.connect_row_selected(glib::clone!(@strong manager_rc, @strong title_entry, 
         @strong content_buffer, @strong delete_button, @strong save_button, @strong copy_button, 
         @strong active_note_id, @strong status_label, @strong row_ids, @strong skip_next_load,
-        @strong suppress_auto_create => move |_, row_opt| {
+        @strong suppress_auto_create, @strong content_view, @strong note_lock_button,
+        @strong window => move |_, row_opt| {
         if let Some(row) = row_opt {
             let idx = row.index();
             if idx >= 0 {
                 let id_opt = row_ids.lock().unwrap().get(idx as usize).copied();
                 if let Some(id) = id_opt {
+                    // Switching notes re-locks whichever locked note was open
+                    if *active_note_id.lock().unwrap() != Some(id) {
+                        manager_rc.lock().unwrap().relock_note();
+                    }
+                    content_view.set_editable(true);
+                    note_lock_button.set_label("Lock");
                     let skip = {
                         let mut guard = skip_next_load.lock().unwrap();
                         let val = *guard;
@@ -1325,11 +2014,54 @@ fn show_main_window(app: &Application) {
                         copy_button.set_sensitive(true);
                         status_label.set_text("New note — enter a title and save");
                     } else {
-                        let note_opt = manager_rc.lock().unwrap().get_notes().into_iter().find(|n| n.id == id);
-                        if let Some(note) = note_opt {
+                        let note_opt = manager_rc.lock().unwrap().get_notes().iter().find(|n| n.id == id).cloned();
+                        if let Some(note) = note_opt.as_ref().filter(|n| n.is_locked()) {
+                            // Show the title only; the content needs the note passphrase
+                            *suppress_auto_create.lock().unwrap() = true;
+                            title_entry.set_text(&note.title);
+                            content_buffer.set_text("");
+                            *suppress_auto_create.lock().unwrap() = false;
+                            *active_note_id.lock().unwrap() = Some(id);
+                            content_view.set_editable(false);
+                            note_lock_button.set_label("Remove Lock");
+                            delete_button.set_sensitive(true);
+                            save_button.set_sensitive(false);
+                            copy_button.set_sensitive(false);
+                            status_label.set_text("Locked note");
+
+                            if manager_rc.lock().unwrap().is_note_open(id) {
+                                return;
+                            }
+                            let title = note.title.clone();
+                            let active_note_id = active_note_id.clone();
+                            let content_buffer = content_buffer.clone();
+                            let content_view = content_view.clone();
+                            let save_button = save_button.clone();
+                            let copy_button = copy_button.clone();
+                            let status_label = status_label.clone();
+                            let suppress_auto_create = suppress_auto_create.clone();
+                            show_note_lock_dialog(&window, manager_rc.clone(), id, NoteLockAction::Open, move |content| {
+                                // Ignore a late result if another note was selected meanwhile
+                                if *active_note_id.lock().unwrap() != Some(id) {
+                                    return;
+                                }
+                                if let Some(mut content) = content {
+                                    *suppress_auto_create.lock().unwrap() = true;
+                                    content_buffer.set_text(&content);
+                                    mask_secret_fields(&content_view, status_label.clone(), false);
+                                    *suppress_auto_create.lock().unwrap() = false;
+                                    content.zeroize();
+                                }
+                                content_view.set_editable(true);
+                                save_button.set_sensitive(true);
+                                copy_button.set_sensitive(true);
+                                status_label.set_text(&format!("Editing: {} (unlocked)", title));
+                            });
+                        } else if let Some(note) = note_opt {
                             *suppress_auto_create.lock().unwrap() = true;
                             title_entry.set_text(&note.title);
                             content_buffer.set_text(&note.content);
+                            mask_secret_fields(&content_view, status_label.clone(), false);
                             *suppress_auto_create.lock().unwrap() = false;
                             *active_note_id.lock().unwrap() = Some(id);
                             delete_button.set_sensitive(true);
@@ -1351,54 +2083,132 @@ fn show_main_window(app: &Application) {
     let status_label_clone = status_label.clone();
     let manager_clone = manager_rc.clone();
     copy_button.connect_clicked(move |_| {
-        let content = content_buffer_clone.text(
-            &content_buffer_clone.start_iter(), 
-            &content_buffer_clone.end_iter(), 
-            false
-        ).to_string();
-        
-        if let Some(display) = gtk::gdk::Display::default() {
-            let clipboard = display.clipboard();
-            clipboard.set_text(&content);
-            status_label_clone.set_text("Copied to clipboard");
-            
-            let timeout = manager_clone.lock().unwrap().get_settings().clipboard_timeout;
-            
-            if timeout > 0 {
-                CLIPBOARD_TIMER.with(|timer_cell| {
-                    if let Some(old_id) = timer_cell.borrow_mut().take() {
-                        old_id.remove();
-                    }
-                });
-                
-                let status_for_timer = status_label_clone.clone();
-                
-                let timer_id = glib::timeout_add_seconds_local(timeout as u32, move || {
-                    if let Some(disp) = gtk::gdk::Display::default() {
-                        disp.clipboard().set_text("");
-                    }
-                    status_for_timer.set_text("Clipboard cleared");
-                    
-                    CLIPBOARD_TIMER.with(|timer_cell| {
-                        *timer_cell.borrow_mut() = None;
-                    });
-                    
-                    glib::ControlFlow::Break
-                });
-                
-                CLIPBOARD_TIMER.with(|timer_cell| {
-                    *timer_cell.borrow_mut() = Some(timer_id);
-                });
-            }
+        let (start, end) = content_buffer_clone.selection_bounds()
+            .unwrap_or_else(|| content_buffer_clone.bounds());
+        let mut content = content_buffer_clone.text(&start, &end, true).to_string();
+        
+        let timeout = manager_clone.lock().unwrap().get_settings().clipboard_timeout;
+        copy_to_clipboard(&content, status_label_clone.clone(), timeout);
+        content.zeroize();
+    });
+
+    // Copying from the editor through the auto-clearing clipboard: the selection, or the
+    // line under the cursor (handy for a password kept on a line of its own). Both are
+    // also in the editor's right-click menu.
+    let copy_actions = gio::SimpleActionGroup::new();
+    let copy_selection_action = gio::SimpleAction::new("copy-selection", None);
+    copy_selection_action.connect_activate(glib::clone!(@strong content_buffer, @strong status_label,
+        @strong manager_rc => move |_, _| {
+        if let Some((start, end)) = content_buffer.selection_bounds() {
+            let mut text = content_buffer.text(&start, &end, true).to_string();
+            let timeout = manager_rc.lock().unwrap().get_settings().clipboard_timeout;
+            copy_to_clipboard(&text, status_label.clone(), timeout);
+            text.zeroize();
+        }
+    }));
+    let copy_line_action = gio::SimpleAction::new("copy-line", None);
+    copy_line_action.connect_activate(glib::clone!(@strong content_buffer, @strong status_label,
+        @strong manager_rc => move |_, _| {
+        let mut start = content_buffer.iter_at_mark(&content_buffer.get_insert());
+        start.set_line_offset(0);
+        let mut end = start.clone();
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let mut line = content_buffer.text(&start, &end, true).to_string();
+        if !line.trim().is_empty() {
+            let timeout = manager_rc.lock().unwrap().get_settings().clipboard_timeout;
+            copy_to_clipboard(line.trim(), status_label.clone(), timeout);
+        }
+        line.zeroize();
+    }));
+    copy_actions.add_action(&copy_selection_action);
+    copy_actions.add_action(&copy_line_action);
+    content_view.insert_action_group("note", Some(&copy_actions));
+
+    let copy_menu = gio::Menu::new();
+    copy_menu.append(Some("Copy and Clear Later"), Some("note.copy-selection"));
+    copy_menu.append(Some("Copy Line and Clear Later"), Some("note.copy-line"));
+    content_view.set_extra_menu(Some(&copy_menu));
+
+    let copy_shortcuts = gtk::ShortcutController::new();
+    for (trigger, action) in [("<Control><Shift>c", "note.copy-selection"), ("<Control><Shift>l", "note.copy-line")] {
+        copy_shortcuts.add_shortcut(gtk::Shortcut::new(
+            gtk::ShortcutTrigger::parse_string(trigger),
+            Some(gtk::NamedAction::new(action)),
+        ));
+    }
+    content_view.add_controller(copy_shortcuts);
+
+    let window_clone = window.clone();
+    let manager_clone = manager_rc.clone();
+    let status_label_clone = status_label.clone();
+    let active_note_id_clone = active_note_id.clone();
+    share_button.connect_clicked(move |_| {
+        if let Some(id) = *active_note_id_clone.lock().unwrap() {
+            show_share_note_dialog(&window_clone, manager_clone.clone(), status_label_clone.clone(), id);
         }
     });
 
+    let refresh_clone = refresh_note_list.clone();
+    note_lock_button.connect_clicked(glib::clone!(@strong manager_rc, @strong window,
+        @strong active_note_id, @strong content_buffer, @strong content_view, @strong save_button,
+        @strong copy_button, @strong status_label, @strong suppress_auto_create => move |button| {
+        let Some(id) = *active_note_id.lock().unwrap() else { return };
+        let (locked, open) = {
+            let manager = manager_rc.lock().unwrap();
+            let locked = manager.get_notes().iter().any(|n| n.id == id && n.is_locked());
+            (locked, manager.is_note_open(id))
+        };
+
+        let button = button.clone();
+        let content_buffer = content_buffer.clone();
+        let content_view = content_view.clone();
+        let save_button = save_button.clone();
+        let copy_button = copy_button.clone();
+        let status_label = status_label.clone();
+        let suppress_auto_create = suppress_auto_create.clone();
+        let manager_for_done = manager_rc.clone();
+        let refresh = refresh_clone.clone();
+        if locked {
+            show_note_lock_dialog(&window, manager_rc.clone(), id, NoteLockAction::Remove, move |_| {
+                // An open note already shows its content (possibly with unsaved edits)
+                if !open {
+                    let content = manager_for_done.lock().unwrap().get_notes().iter()
+                        .find(|n| n.id == id)
+                        .map(|n| n.content.clone())
+                        .unwrap_or_default();
+                    *suppress_auto_create.lock().unwrap() = true;
+                    content_buffer.set_text(&content);
+                    mask_secret_fields(&content_view, status_label.clone(), false);
+                    *suppress_auto_create.lock().unwrap() = false;
+                }
+                content_view.set_editable(true);
+                save_button.set_sensitive(true);
+                copy_button.set_sensitive(true);
+                button.set_label("Lock");
+                status_label.set_text("Note lock removed");
+                refresh();
+            });
+        } else {
+            show_note_lock_dialog(&window, manager_rc.clone(), id, NoteLockAction::Lock, move |_| {
+                button.set_label("Remove Lock");
+                status_label.set_text("Note locked — it locks again when you switch notes");
+                refresh();
+            });
+        }
+    }));
+
     let refresh_clone = refresh_note_list.clone();
     new_note_button.connect_clicked(glib::clone!(@strong manager_rc, @strong title_entry, 
         @strong content_buffer, @strong active_note_id, @strong delete_button, @strong save_button, 
         @strong copy_button, @strong note_list_box, @strong status_label, @strong skip_next_load,
-        @strong suppress_auto_create => move |_| {
+        @strong suppress_auto_create, @strong content_view, @strong note_lock_button => move |_| {
         
+        manager_rc.lock().unwrap().relock_note();
+        content_view.set_editable(true);
+        note_lock_button.set_label("Lock");
+
         // Suppress connect_changed during programmatic clear so no phantom note is created
         *suppress_auto_create.lock().unwrap() = true;
         title_entry.set_text("");
@@ -1462,10 +2272,11 @@ fn show_main_window(app: &Application) {
         let id_opt = *active_note_id.lock().unwrap();
         if let Some(id) = id_opt {
             let title = title_entry.text().to_string();
+            // Masked secret fields are hidden text, and still part of the note
             let content = content_buffer.text(
                 &content_buffer.start_iter(), 
                 &content_buffer.end_iter(), 
-                false
+                true
             ).to_string();
 
             let manager_clone = manager_rc.clone();
@@ -1510,6 +2321,8 @@ fn show_main_window(app: &Application) {
         let status_label = status_label.clone();
         let refresh_clone = refresh_clone.clone();
         let suppress_auto_create = suppress_auto_create.clone();
+        let content_view = content_view.clone();
+        let note_lock_button = note_lock_button.clone();
         
         move || {
             let id_opt = *active_note_id.lock().unwrap();
@@ -1524,6 +2337,8 @@ fn show_main_window(app: &Application) {
                 let copy_clone = copy_button.clone();
                 let refresh = refresh_clone.clone();
                 let suppress_clone = suppress_auto_create.clone();
+                let content_view = content_view.clone();
+                let note_lock_button = note_lock_button.clone();
 
                 status_label.set_text("Deleting...");
                 delete_button.set_sensitive(false);
@@ -1553,6 +2368,8 @@ fn show_main_window(app: &Application) {
                                 delete_clone.set_sensitive(false);
                                 save_clone.set_sensitive(false);
                                 copy_clone.set_sensitive(false);
+                                content_view.set_editable(true);
+                                note_lock_button.set_label("Lock");
                                 refresh();
                             },
                             Ok(Err(e)) => { 
@@ -1593,136 +2410,450 @@ fn show_main_window(app: &Application) {
     window.add_controller(key_controller);
 
     export_button.connect_clicked(glib::clone!(@strong manager_rc, @strong status_label, 
-        @strong window => move |_| {
-        let file_chooser = gtk::FileChooserDialog::new(
-            Some("Export Notes"), 
-            Some(&window), 
-            gtk::FileChooserAction::Save,
-            &[("Cancel", gtk::ResponseType::Cancel), ("Export", gtk::ResponseType::Accept)],
-        );
-        file_chooser.set_current_name("notes_export.dat");
-        
-        let manager_clone = manager_rc.clone();
-        let status_clone = status_label.clone();
-        
-        file_chooser.connect_response(move |dialog, response| {
-            if response == gtk::ResponseType::Accept {
-                if let Some(path) = dialog.file().and_then(|f| f.path()) {
-                    let manager_for_task = manager_clone.clone();
-                    let status_for_ui = status_clone.clone();
-                    let path_clone = path.clone();
-                    
-                    status_clone.set_text("Exporting...");
-                    
-                    let (sender, receiver) = async_channel::unbounded();
-                    let runtime = TOKIO_RUNTIME.get().unwrap();
-                    
-                    glib::spawn_future_local(async move {
-                        let _guard = runtime.enter();
-                        let result = tokio::task::spawn_blocking(move || {
-                            manager_for_task.lock().unwrap().export_all_encrypted(&path_clone)
-                        }).await;
-                        let _ = sender.send((result, path)).await;
-                    });
-                    
-                    glib::spawn_future_local(async move {
-                        if let Ok((result, path)) = receiver.recv().await {
-                            match result {
-                                Ok(Ok(_)) => status_for_ui.set_text(&format!("Exported: {}", path.display())),
-                                Ok(Err(e)) => status_for_ui.set_text(&format!("Error: {}", e)),
-                                Err(e) => status_for_ui.set_text(&format!("Error: {}", e)),
-                            }
-                        }
-                    });
-                }
-            }
-            dialog.close();
-        });
-        file_chooser.show();
+        @strong window, @strong active_note_id => move |_| {
+        let active = *active_note_id.lock().unwrap();
+        show_export_dialog(&window, manager_rc.clone(), status_label.clone(), active);
     }));
 
     let refresh_clone = refresh_note_list.clone();
     import_button.connect_clicked(glib::clone!(@strong manager_rc, @strong status_label, 
         @strong window => move |_| {
-        let file_chooser = gtk::FileChooserDialog::new(
-            Some("Import Notes"), 
-            Some(&window), 
-            gtk::FileChooserAction::Open,
-            &[("Cancel", gtk::ResponseType::Cancel), ("Import", gtk::ResponseType::Accept)],
-        );
-        
-        let manager_clone = manager_rc.clone();
-        let status_clone = status_label.clone();
-        let window_clone = window.clone();
-        let refresh = refresh_clone.clone();
-        
-        file_chooser.connect_response(move |dialog, response| {
-            if response == gtk::ResponseType::Accept {
-                if let Some(path) = dialog.file().and_then(|f| f.path()) {
-                    show_import_password_dialog(
-                        &window_clone, 
-                        manager_clone.clone(), 
-                        status_clone.clone(), 
-                        refresh.clone(), 
-                        path
-                    );
-                }
-            }
-            dialog.close();
-        });
-        file_chooser.show();
+        show_import_source_dialog(&window, manager_rc.clone(), status_label.clone(), refresh_clone.clone());
     }));
 
     let window_weak = window.downgrade();
     let app_weak = app.downgrade();
     let manager_for_timer = manager_rc.clone();
-    
-    glib::timeout_add_seconds_local(30, move || {
-        let timeout = manager_for_timer.lock()
-            .map(|m| m.get_settings().auto_lock_timeout)
-            .unwrap_or(0);
-        
-        if timeout == 0 { 
-            return glib::ControlFlow::Continue; 
+    let paned_for_timer = paned.clone();
+    // When no Notas window last had focus, and when the main window was last minimized
+    let unfocused_since: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+    let minimized_since: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+
+    // The privacy screen goes up as soon as focus leaves for another app. A dialog of
+    // ours taking focus doesn't count; the focus check below covers leaving from a dialog.
+    let app_for_focus = app.clone();
+    window.connect_is_active_notify(glib::clone!(@strong manager_rc, @strong paned, @weak app_for_focus => move |window| {
+        let privacy = manager_rc.lock().unwrap().get_settings().lock_triggers.privacy_screen;
+        let dialog_open = app_for_focus.windows().iter()
+            .any(|w| w != window.upcast_ref::<gtk::Window>() && w.is_visible());
+        if window.is_active() {
+            paned.remove_css_class("privacy-screen");
+        } else if privacy && !dialog_open {
+            paned.add_css_class("privacy-screen");
         }
-        
-        let elapsed = LAST_ACTIVITY.with(|l| l.borrow().elapsed());
-        
-        if elapsed >= Duration::from_secs(timeout) {
-            if let (Some(w), Some(a)) = (window_weak.upgrade(), app_weak.upgrade()) {
-                if let Some(mgr) = CORE_MANAGER.get() {
-                    mgr.clone().lock().unwrap().lock();
-                }
-                w.close();
-                show_password_screen(&a);
-                return glib::ControlFlow::Break;
+    }));
+
+    // Rather than polling, one timer waits for the earliest lock deadline. Activity only
+    // moves the idle deadline later, so when the timer finds it hasn't been reached it
+    // waits again; focus, minimizing and settings changes re-check straight away.
+    let check_auto_lock: Rc<dyn Fn()> = Rc::new(move || {
+        if let Some(id) = AUTO_LOCK_TIMER.with(|timer| timer.borrow_mut().take()) {
+            id.remove();
+        }
+        let (Some(window), Some(app)) = (window_weak.upgrade(), app_weak.upgrade()) else {
+            return;
+        };
+        let (timeout, triggers) = match manager_for_timer.lock() {
+            Ok(m) => (m.get_settings().auto_lock_timeout, m.get_settings().lock_triggers),
+            Err(_) => return,
+        };
+
+        let focused = app.windows().iter().any(|w| w.is_active());
+        // Wayland compositors may not report minimizing; then only focus loss applies
+        let minimized = window.surface().downcast::<gtk::gdk::Toplevel>()
+            .is_ok_and(|toplevel| toplevel.state().contains(gtk::gdk::ToplevelState::MINIMIZED));
+        for (since, active) in [(&unfocused_since, !focused), (&minimized_since, minimized)] {
+            match (since.get(), active) {
+                (None, true) => since.set(Some(Instant::now())),
+                (Some(_), false) => since.set(None),
+                _ => {}
             }
         }
-        glib::ControlFlow::Continue
+
+        if triggers.privacy_screen && !focused {
+            paned_for_timer.add_css_class("privacy-screen");
+        } else if focused {
+            paned_for_timer.remove_css_class("privacy-screen");
+        }
+
+        let last_activity = LAST_ACTIVITY.with(|l| *l.borrow());
+        let remaining = [
+            (timeout, Some(last_activity)),
+            (triggers.unfocused_after, unfocused_since.get()),
+            (triggers.minimized_after, minimized_since.get()),
+        ].into_iter()
+            .filter(|&(seconds, _)| seconds > 0)
+            .filter_map(|(seconds, since)| Some(Duration::from_secs(seconds).saturating_sub(since?.elapsed())))
+            .min();
+        match remaining {
+            Some(remaining) if remaining.is_zero() => lock_app(&app),
+            Some(remaining) => {
+                let id = glib::timeout_add_local_once(remaining, || {
+                    // Already fired, so there's no source left to remove
+                    AUTO_LOCK_TIMER.with(|timer| timer.borrow_mut().take());
+                    recheck_auto_lock();
+                });
+                AUTO_LOCK_TIMER.with(|timer| *timer.borrow_mut() = Some(id));
+            },
+            None => {},
+        }
+    });
+    AUTO_LOCK_CHECK.with(|check| *check.borrow_mut() = Some(check_auto_lock));
+
+    // Focus may pass between our own windows, so look once it has settled
+    window.connect_is_active_notify(|_| {
+        glib::idle_add_local_once(recheck_auto_lock);
+    });
+    let window_added = app.connect_window_added(|_, added| {
+        added.connect_is_active_notify(|_| {
+            glib::idle_add_local_once(recheck_auto_lock);
+        });
+    });
+    window.connect_realize(|window| {
+        window.surface().connect_notify_local(Some("state"), |_, _| recheck_auto_lock());
+    });
+
+    let subscriptions = watch_session_signals(app);
+    let app_for_destroy = app.clone();
+    let window_added = RefCell::new(Some(window_added));
+    window.connect_destroy(move |_| {
+        for (connection, id) in subscriptions.borrow_mut().drain(..) {
+            connection.signal_unsubscribe(id);
+        }
+        if let Some(id) = window_added.take() {
+            app_for_destroy.disconnect(id);
+        }
+        AUTO_LOCK_CHECK.with(|check| check.borrow_mut().take());
+        if let Some(id) = AUTO_LOCK_TIMER.with(|timer| timer.borrow_mut().take()) {
+            id.remove();
+        }
     });
 
     window.present();
+    recheck_auto_lock();
 }
 
-fn show_preferences_dialog(
-    parent: &ApplicationWindow,
-    manager_rc: Arc<Mutex<CoreManager>>,
-    status_label: Arc<Label>,
-    title_entry_widget: Arc<gtk::Entry>,
-    title_toggle_widget: gtk::Switch,
-) {
-    let settings = manager_rc.lock().unwrap().get_settings().clone();
-    let db_path = manager_rc.lock().unwrap().get_data_path().display().to_string();
-    
+type SignalSubscriptions = Rc<RefCell<Vec<(gio::DBusConnection, gio::SignalSubscriptionId)>>>;
+type SessionSignalHandler = Rc<dyn Fn(&str, &str, &glib::Variant)>;
+
+// Locks as soon as the desktop session locks, the machine suspends or the screensaver
+// starts, as allowed by the lock triggers in settings. gio honours
+// DBUS_SYSTEM_BUS_ADDRESS and DBUS_SESSION_BUS_ADDRESS, so a private dbus-daemon can
+// stand in for logind and the screensaver. A missing bus (e.g. no logind) is skipped.
+fn watch_session_signals(app: &Application) -> SignalSubscriptions {
+    let subscriptions = Rc::new(RefCell::new(Vec::new()));
+
+    let on_signal: SessionSignalHandler = {
+        let app_weak = app.downgrade();
+        Rc::new(move |interface: &str, member: &str, parameters: &glib::Variant| {
+            let triggers = match CORE_MANAGER.get() {
+                Some(manager) => manager.lock().unwrap().get_settings().lock_triggers,
+                None => return,
+            };
+            // Both signals carry `true` on the way in (going to sleep, screen blanked)
+            let starting = parameters.get::<(bool,)>().map(|(active,)| active).unwrap_or(false);
+            let lock = match member {
+                "Lock" => triggers.session_lock,
+                "PrepareForSleep" => triggers.suspend && starting,
+                "ActiveChanged" if interface.ends_with("ScreenSaver") => triggers.screensaver && starting,
+                _ => false,
+            };
+            let unlocked = CORE_MANAGER.get().is_some_and(|manager| manager.lock().unwrap().is_unlocked());
+            if let (true, true, Some(app)) = (lock, unlocked, app_weak.upgrade()) {
+                lock_app(&app);
+            }
+        })
+    };
+
+    if let Ok(system) = gio::bus_get_sync(gio::BusType::System, gio::Cancellable::NONE) {
+        watch_login_signals(&system, on_signal.clone(), &subscriptions);
+    }
+
+    if let Ok(session) = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+        // GNOME still uses its own name; KDE, Xfce and others the freedesktop one
+        for interface in ["org.freedesktop.ScreenSaver", "org.gnome.ScreenSaver"] {
+            let on_signal = on_signal.clone();
+            let id = session.signal_subscribe(
+                None,
+                Some(interface),
+                Some("ActiveChanged"),
+                None,
+                None,
+                gio::DBusSignalFlags::NONE,
+                move |_, _, _, interface, member, parameters| on_signal(interface, member, parameters),
+            );
+            subscriptions.borrow_mut().push((session.clone(), id));
+        }
+    }
+
+    subscriptions
+}
+
+// logind's PrepareForSleep, and Lock for this session only: `loginctl lock-sessions`
+// signals every session. The session is looked up without blocking the main loop; when
+// logind can't name it, session locks aren't followed at all rather than from any session.
+fn watch_login_signals(system: &gio::DBusConnection, on_signal: SessionSignalHandler, subscriptions: &SignalSubscriptions) {
+    let subscribe = |connection: &gio::DBusConnection, interface: &str, member: &str, path: &str, on_signal: SessionSignalHandler| {
+        connection.signal_subscribe(
+            Some("org.freedesktop.login1"),
+            Some(interface),
+            Some(member),
+            Some(path),
+            None,
+            gio::DBusSignalFlags::NONE,
+            move |_, _, _, interface, member, parameters| on_signal(interface, member, parameters),
+        )
+    };
+
+    let id = subscribe(system, "org.freedesktop.login1.Manager", "PrepareForSleep", "/org/freedesktop/login1", on_signal.clone());
+    subscriptions.borrow_mut().push((system.clone(), id));
+
+    // Dropped with the window, which may close before logind answers
+    let pending = Rc::downgrade(subscriptions);
+    let connection = system.clone();
+    system.call(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+        "GetSession",
+        Some(&("auto",).to_variant()),
+        Some(glib::VariantTy::new("(o)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        1000,
+        gio::Cancellable::NONE,
+        move |reply| {
+            let Some(session_path) = reply.ok().and_then(|reply| reply.child_value(0).str().map(str::to_string)) else {
+                return;
+            };
+            let Some(subscriptions) = pending.upgrade() else { return };
+            let id = subscribe(&connection, "org.freedesktop.login1.Session", "Lock", &session_path, on_signal);
+            subscriptions.borrow_mut().push((connection, id));
+        },
+    );
+}
+
+// Locks the vault and returns to the lock screen. Closing a window doesn't free the text
+// its widgets hold (GTK may keep the widgets and their undo history alive a while
+// longer), so every open window is emptied first.
+fn lock_app(app: &Application) {
+    if let Some(manager) = CORE_MANAGER.get() {
+        let mut manager = manager.lock().unwrap();
+        // Locking one Notas locks them all
+        let _ = manager.forget_cached_key();
+        manager.lock();
+    }
+    clear_owned_clipboard();
+
+    let windows = app.windows();
+    for window in &windows {
+        clear_widget_text(window.upcast_ref());
+    }
+    for window in &windows {
+        window.close();
+    }
+    show_password_screen(app);
+}
+
+// Empties every text-holding widget under `widget`: entries, text views (with their undo
+// history), labels, and the rows of list boxes. Runs after the manager is locked, so the
+// change handlers this triggers can't save anything.
+fn clear_widget_text(widget: &gtk::Widget) {
+    let mut child = widget.first_child();
+    while let Some(current) = child {
+        child = current.next_sibling();
+        clear_widget_text(&current);
+    }
+
+    if let Some(editable) = widget.downcast_ref::<gtk::Editable>() {
+        editable.set_enable_undo(false);
+        editable.set_text("");
+    } else if let Some(text_view) = widget.downcast_ref::<gtk::TextView>() {
+        let buffer = text_view.buffer();
+        // Turning undo off drops the history, which holds earlier versions of the text
+        buffer.set_enable_undo(false);
+        buffer.set_text("");
+    } else if let Some(label) = widget.downcast_ref::<Label>() {
+        label.set_text("");
+    } else if let Some(list_box) = widget.downcast_ref::<gtk::ListBox>() {
+        while let Some(row) = list_box.row_at_index(0) {
+            list_box.remove(&row);
+        }
+    }
+}
+
+// Hides the value of each secret field (see core::secrets) in the editor behind a row
+// of dots, with buttons to show it in place or copy it through the auto-clearing
+// clipboard. The value is only tagged invisible, so saving writes it back unchanged; the
+// editor has to be read with hidden text included. Runs when a note is loaded, and again
+// whenever a field is closed by typing or pasting its `}}` or closing fence (`typing`),
+// which leaves out fenced blocks still waiting for theirs. A value typed into a field
+// that's already closed stays visible until the note is reopened. Masking drops the
+// undo history, so undo can't bring a masked value back into view either.
+fn mask_secret_fields(view: &gtk::TextView, status_label: Arc<Label>, typing: bool) {
+    let buffer = view.buffer();
+    // With the child anchors of fields already masked (as U+FFFC), so character offsets
+    // match the buffer's
+    let mut text = buffer.slice(&buffer.start_iter(), &buffer.end_iter(), true).to_string();
+    // The controls of a masked field are anchored right after its value
+    let spans: Vec<_> = core::secrets::find_secrets(&text).into_iter()
+        .filter(|span| !text[span.value.clone()].ends_with('\u{FFFC}'))
+        .filter(|span| !typing || text[span.field.clone()].ends_with("}}") || text[span.field.clone()].ends_with("```"))
+        .collect();
+    if spans.is_empty() {
+        text.zeroize();
+        return;
+    }
+    let tag = buffer.tag_table().lookup("secret-hidden").unwrap_or_else(|| {
+        let tag = gtk::TextTag::builder().name("secret-hidden").invisible(true).build();
+        buffer.tag_table().add(&tag);
+        tag
+    });
+
+    buffer.begin_irreversible_action();
+    // Last field first, so the anchors inserted don't shift the fields still to do
+    for span in spans.iter().rev() {
+        let start_offset = text[..span.value.start].chars().count() as i32;
+        let end_offset = start_offset + text[span.value.clone()].chars().count() as i32;
+        buffer.apply_tag(&tag, &buffer.iter_at_offset(start_offset), &buffer.iter_at_offset(end_offset));
+        // Both stay put when text is typed at them, keeping the controls after the value
+        let value_start = buffer.create_mark(None, &buffer.iter_at_offset(start_offset), true);
+        let value_end = buffer.create_mark(None, &buffer.iter_at_offset(end_offset), true);
+        let anchor = buffer.create_child_anchor(&mut buffer.iter_at_offset(end_offset));
+
+        let controls = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+        controls.add_css_class("secret-field");
+        let dots = Label::new(Some(core::secrets::MASK));
+        let show_button = gtk::ToggleButton::with_label("Show");
+        show_button.add_css_class("flat");
+        let copy_button = gtk::Button::with_label("Copy");
+        copy_button.add_css_class("flat");
+        controls.append(&dots);
+        controls.append(&show_button);
+        controls.append(&copy_button);
+
+        show_button.connect_toggled(glib::clone!(@weak buffer, @strong tag, @strong value_start,
+            @strong value_end, @weak dots => move |button| {
+            let (start, end) = (buffer.iter_at_mark(&value_start), buffer.iter_at_mark(&value_end));
+            if button.is_active() {
+                buffer.remove_tag(&tag, &start, &end);
+                button.set_label("Hide");
+            } else {
+                buffer.apply_tag(&tag, &start, &end);
+                button.set_label("Show");
+            }
+            dots.set_visible(!button.is_active());
+        }));
+        copy_button.connect_clicked(glib::clone!(@weak buffer, @strong value_start, @strong value_end,
+            @strong status_label => move |_| {
+            let mut secret = buffer.text(&buffer.iter_at_mark(&value_start), &buffer.iter_at_mark(&value_end), true).to_string();
+            let timeout = CORE_MANAGER.get()
+                .map(|manager| manager.lock().unwrap().get_settings().clipboard_timeout)
+                .unwrap_or(0);
+            copy_to_clipboard(&secret, status_label.clone(), timeout);
+            secret.zeroize();
+        }));
+        view.add_child_at_anchor(&controls, &anchor);
+    }
+    buffer.end_irreversible_action();
+    text.zeroize();
+}
+
+// Clears the clipboard and primary selection if Notas put their contents there, and
+// cancels the pending clear timer
+fn clear_owned_clipboard() {
+    CLIPBOARD_TIMER.with(|timer_cell| {
+        if let Some(id) = timer_cell.borrow_mut().take() {
+            id.remove();
+        }
+    });
+    CLIPBOARD_CONTENT.with(|content| *content.borrow_mut() = None);
+    if let Some(display) = gtk::gdk::Display::default() {
+        for clipboard in [display.clipboard(), display.primary_clipboard()] {
+            if clipboard.is_local() {
+                clipboard.set_content(None::<&gtk::gdk::ContentProvider>).ok();
+            }
+        }
+    }
+}
+
+// Whether the clipboard still holds what copy_to_clipboard last put there, rather than
+// something copied since, in Notas or elsewhere
+fn clipboard_holds_ours() -> bool {
+    let Some(display) = gtk::gdk::Display::default() else {
+        return false;
+    };
+    let current = display.clipboard().content();
+    CLIPBOARD_CONTENT.with(|content| content.borrow().is_some() && *content.borrow() == current)
+}
+
+// Puts `text` on the clipboard, marked so clipboard managers (Klipper, and the ones that
+// follow its convention) don't keep it in their history, and clears it after `timeout`
+// seconds (0 = never) unless something else has been copied by then. The countdown
+// shows in the status bar.
+fn copy_to_clipboard(text: &str, status_label: Arc<Label>, timeout: u64) {
+    let Some(display) = gtk::gdk::Display::default() else {
+        return;
+    };
+    CLIPBOARD_TIMER.with(|timer_cell| {
+        if let Some(old_id) = timer_cell.borrow_mut().take() {
+            old_id.remove();
+        }
+    });
+
+    let content = gtk::gdk::ContentProvider::new_union(&[
+        gtk::gdk::ContentProvider::for_value(&text.to_value()),
+        gtk::gdk::ContentProvider::for_bytes("x-kde-passwordManagerHint", &glib::Bytes::from_static(b"secret")),
+    ]);
+    if let Err(e) = display.clipboard().set_content(Some(&content)) {
+        status_label.set_text(&format!("Could not copy: {}", e));
+        return;
+    }
+    CLIPBOARD_CONTENT.with(|ours| *ours.borrow_mut() = Some(content));
+
+    if timeout == 0 {
+        status_label.set_text("Copied to clipboard");
+        return;
+    }
+    status_label.set_text(&format!("Copied, clipboard clears in {}s", timeout));
+
+    let remaining = Cell::new(timeout);
+    let status_for_timer = status_label.clone();
+    let timer_id = glib::timeout_add_seconds_local(1, move || {
+        if !clipboard_holds_ours() {
+            // Replaced since: leave the user's clipboard alone
+            status_for_timer.set_text("Clipboard changed, not cleared");
+        } else if remaining.get() > 1 {
+            remaining.set(remaining.get() - 1);
+            status_for_timer.set_text(&format!("Copied, clipboard clears in {}s", remaining.get()));
+            return glib::ControlFlow::Continue;
+        } else {
+            if let Some(display) = gtk::gdk::Display::default() {
+                display.clipboard().set_content(None::<&gtk::gdk::ContentProvider>).ok();
+            }
+            status_for_timer.set_text("Clipboard cleared");
+        }
+
+        CLIPBOARD_CONTENT.with(|ours| *ours.borrow_mut() = None);
+        CLIPBOARD_TIMER.with(|timer_cell| {
+            *timer_cell.borrow_mut() = None;
+        });
+        glib::ControlFlow::Break
+    });
+
+    CLIPBOARD_TIMER.with(|timer_cell| {
+        *timer_cell.borrow_mut() = Some(timer_id);
+    });
+}
+
+// Small modal window with the app's custom header bar and a close button
+fn build_dialog_window(parent: &impl IsA<gtk::Window>, title: &str, width: i32) -> (gtk::Window, gtk::Box) {
     let dialog = gtk::Window::builder()
-        .title("Preferences")
+        .title(title)
         .modal(true)
         .transient_for(parent)
-        .default_width(420)
-        .default_height(560)
+        .default_width(width)
         .build();
     
-    // Custom header for dialog too
     let header = gtk::HeaderBar::new();
     header.set_show_title_buttons(false);
     header.add_css_class("custom-headerbar");
@@ -1738,194 +2869,2553 @@ fn show_preferences_dialog(
     btn_box.append(&close_btn);
     header.pack_start(&btn_box);
     
-    let header_title = Label::new(Some("Preferences"));
+    let header_title = Label::new(Some(title));
     header_title.add_css_class("headerbar-title");
     header.set_title_widget(Some(&header_title));
     
     dialog.set_titlebar(Some(&header));
 
-    let scrolled = gtk::ScrolledWindow::builder()
-        .hscrollbar_policy(gtk::PolicyType::Never)
-        .build();
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    vbox.set_margin_top(18);
+    vbox.set_margin_bottom(18);
+    vbox.set_margin_start(18);
+    vbox.set_margin_end(18);
+    dialog.set_child(Some(&vbox));
 
-    let main_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
-    main_box.set_margin_top(18);
-    main_box.set_margin_bottom(18);
-    main_box.set_margin_start(18);
-    main_box.set_margin_end(18);
+    (dialog, vbox)
+}
 
-    // Editor group
-    let editor_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
-    editor_group.add_css_class("preferences-group");
-    
-    let editor_title = Label::new(Some("EDITOR"));
-    editor_title.add_css_class("preferences-title");
-    editor_title.set_halign(gtk::Align::Start);
-    
-    // Font family dropdown
-    let font_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let font_label = Label::new(Some("Font"));
-    font_label.set_hexpand(true);
-    font_label.set_halign(gtk::Align::Start);
-    
-    let font_dropdown = gtk::DropDown::from_strings(
-        &EditorFont::all_fonts().iter().map(|f| f.display_name()).collect::<Vec<_>>()
-    );
-    font_dropdown.set_selected(settings.editor_font.to_index());
+fn show_share_note_dialog(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    note_id: u64,
+) {
+    let (dialog, vbox) = build_dialog_window(parent, "Share Encrypted", 380);
+
+    let label = Label::new(Some("One-off passphrase (send it separately):"));
+    label.set_halign(gtk::Align::Start);
+
+    let passphrase_entry = gtk::PasswordEntry::new();
+    passphrase_entry.set_placeholder_text(Some("Passphrase"));
+    passphrase_entry.set_show_peek_icon(true);
+
+    let confirm_entry = gtk::PasswordEntry::new();
+    confirm_entry.set_placeholder_text(Some("Confirm Passphrase"));
+    confirm_entry.set_show_peek_icon(true);
+
+    let share_status = Rc::new(Label::new(None));
+    share_status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+
+    let copy_text_button = gtk::Button::with_label("Copy as Text");
+    copy_text_button.add_css_class("secondary-button");
     
-    font_row.append(&font_label);
+    let save_file_button = gtk::Button::with_label("Save File...");
+    save_file_button.add_css_class("action-button");
+
+    button_box.append(&copy_text_button);
+    button_box.append(&save_file_button);
+
+    vbox.append(&label);
+    vbox.append(&passphrase_entry);
+    vbox.append(&confirm_entry);
+    vbox.append(share_status.as_ref());
+    vbox.append(&button_box);
+
+    // Validates the passphrase fields and seals the note on a worker thread
+    let seal = {
+        let passphrase_entry = passphrase_entry.clone();
+        let share_status = share_status.clone();
+        let manager_rc = manager_rc.clone();
+        move |armored: bool, on_sealed: Box<dyn Fn(Vec<u8>)>| {
+            let passphrase = passphrase_entry.text().to_string();
+            if passphrase.is_empty() {
+                share_status.set_markup("<span foreground='#a06060'>Passphrase required</span>");
+                return;
+            }
+            if passphrase != confirm_entry.text().as_str() {
+                share_status.set_markup("<span foreground='#a06060'>Passphrases don't match</span>");
+                return;
+            }
+            share_status.set_text("Encrypting...");
+
+            let manager_for_task = manager_rc.clone();
+            let share_status = share_status.clone();
+            let (sender, receiver) = async_channel::unbounded();
+            let runtime = TOKIO_RUNTIME.get().unwrap();
+            
+            glib::spawn_future_local(async move {
+                let _guard = runtime.enter();
+                let result = tokio::task::spawn_blocking(move || {
+                    let pw = core::data::MasterPassword::from(passphrase.as_str());
+                    manager_for_task.lock().unwrap().share_note(note_id, pw, armored)
+                }).await;
+                let _ = sender.send(result).await;
+            });
+            
+            glib::spawn_future_local(async move {
+                if let Ok(result) = receiver.recv().await {
+                    match result {
+                        Ok(Ok(sealed)) => on_sealed(sealed),
+                        Ok(Err(e)) => share_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                        Err(e) => share_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                }
+            });
+        }
+    };
+    let seal = Rc::new(seal);
+
+    let seal_clone = seal.clone();
+    let dialog_clone = dialog.clone();
+    let status_clone = status_label.clone();
+    let manager_clone = manager_rc.clone();
+    copy_text_button.connect_clicked(move |_| {
+        let dialog_close = dialog_clone.clone();
+        let status_for_copy = status_clone.clone();
+        let timeout = manager_clone.lock().unwrap().get_settings().clipboard_timeout;
+        seal_clone(true, Box::new(move |sealed| {
+            copy_to_clipboard(&String::from_utf8_lossy(&sealed), status_for_copy.clone(), timeout);
+            status_for_copy.set_text("Encrypted note copied as text");
+            dialog_close.close();
+        }));
+    });
+
+    let dialog_clone = dialog.clone();
+    let parent = parent.clone();
+    save_file_button.connect_clicked(move |_| {
+        let dialog_close = dialog_clone.clone();
+        let parent = parent.clone();
+        let status_for_save = status_label.clone();
+        seal(false, Box::new(move |sealed| {
+            dialog_close.close();
+            let file_chooser = gtk::FileChooserDialog::new(
+                Some("Save Shared Note"), 
+                Some(&parent), 
+                gtk::FileChooserAction::Save,
+                &[("Cancel", gtk::ResponseType::Cancel), ("Save", gtk::ResponseType::Accept)],
+            );
+            file_chooser.set_current_name("shared_note.notas");
+            let status_for_save = status_for_save.clone();
+            file_chooser.connect_response(move |dialog, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                        match std::fs::write(&path, &sealed) {
+                            Ok(_) => status_for_save.set_text(&format!("Shared note saved: {}", path.display())),
+                            Err(e) => status_for_save.set_text(&format!("Error: {}", e)),
+                        }
+                    }
+                }
+                dialog.close();
+            });
+            file_chooser.show();
+        }));
+    });
+
+    dialog.present();
+    passphrase_entry.grab_focus();
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum NoteLockAction {
+    Lock,
+    Open,
+    Remove,
+}
+
+// Asks for a note passphrase and locks, opens or unlocks the note on a worker thread.
+// `on_done` gets the decrypted content when opening.
+fn show_note_lock_dialog<F>(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    note_id: u64,
+    action: NoteLockAction,
+    on_done: F,
+) where F: Fn(Option<String>) + 'static {
+    let (title, prompt, button_label) = match action {
+        NoteLockAction::Lock => ("Lock Note", "Passphrase for this note (the vault password won't open it):", "Lock"),
+        NoteLockAction::Open => ("Locked Note", "Enter the passphrase for this note:", "Unlock"),
+        NoteLockAction::Remove => ("Remove Lock", "Enter the passphrase to remove this note's lock:", "Remove Lock"),
+    };
+    let (dialog, vbox) = build_dialog_window(parent, title, 360);
+
+    let label = Label::new(Some(prompt));
+    label.set_halign(gtk::Align::Start);
+    label.set_wrap(true);
+
+    let passphrase_entry = gtk::PasswordEntry::new();
+    passphrase_entry.set_placeholder_text(Some("Passphrase"));
+    passphrase_entry.set_show_peek_icon(true);
+
+    let confirm_entry = gtk::PasswordEntry::new();
+    confirm_entry.set_placeholder_text(Some("Confirm Passphrase"));
+    confirm_entry.set_show_peek_icon(true);
+    confirm_entry.set_visible(action == NoteLockAction::Lock);
+
+    let lock_status = Rc::new(Label::new(None));
+    lock_status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
+
+    let confirm_button = gtk::Button::with_label(button_label);
+    confirm_button.add_css_class("action-button");
+
+    button_box.append(&cancel_button);
+    button_box.append(&confirm_button);
+
+    vbox.append(&label);
+    vbox.append(&passphrase_entry);
+    vbox.append(&confirm_entry);
+    vbox.append(lock_status.as_ref());
+    vbox.append(&button_box);
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| dialog_clone.close());
+
+    let submit = {
+        let passphrase_entry = passphrase_entry.clone();
+        let confirm_entry = confirm_entry.clone();
+        let confirm_button = confirm_button.clone();
+        let dialog = dialog.clone();
+        let on_done = Rc::new(on_done);
+        move || {
+            let passphrase = passphrase_entry.text().to_string();
+            if passphrase.is_empty() {
+                lock_status.set_markup("<span foreground='#a06060'>Passphrase required</span>");
+                return;
+            }
+            if action == NoteLockAction::Lock && passphrase != confirm_entry.text().as_str() {
+                lock_status.set_markup("<span foreground='#a06060'>Passphrases don't match</span>");
+                return;
+            }
+            lock_status.set_text(if action == NoteLockAction::Lock { "Encrypting..." } else { "Decrypting..." });
+            confirm_button.set_sensitive(false);
+
+            let manager_for_task = manager_rc.clone();
+            let (sender, receiver) = async_channel::unbounded();
+            let runtime = TOKIO_RUNTIME.get().unwrap();
+
+            glib::spawn_future_local(async move {
+                let _guard = runtime.enter();
+                let result = tokio::task::spawn_blocking(move || {
+                    let pw = core::data::MasterPassword::from(passphrase.as_str());
+                    let mut manager = manager_for_task.lock().unwrap();
+                    match action {
+                        NoteLockAction::Lock => manager.lock_note(note_id, pw).map(|_| None),
+                        NoteLockAction::Open => manager.unlock_note(note_id, pw).map(Some),
+                        NoteLockAction::Remove => manager.remove_note_lock(note_id, pw).map(|_| None),
+                    }
+                }).await;
+                let _ = sender.send(result).await;
+            });
+
+            let lock_status = lock_status.clone();
+            let confirm_button = confirm_button.clone();
+            let dialog = dialog.clone();
+            let on_done = on_done.clone();
+            glib::spawn_future_local(async move {
+                if let Ok(result) = receiver.recv().await {
+                    match result {
+                        Ok(Ok(content)) => {
+                            dialog.close();
+                            on_done(content);
+                        },
+                        Ok(Err(e)) => {
+                            lock_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                            confirm_button.set_sensitive(true);
+                        },
+                        Err(e) => {
+                            lock_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                            confirm_button.set_sensitive(true);
+                        },
+                    }
+                }
+            });
+        }
+    };
+    let submit = Rc::new(submit);
+
+    let submit_clone = submit.clone();
+    confirm_button.connect_clicked(move |_| submit_clone());
+    let submit_clone = submit.clone();
+    passphrase_entry.connect_activate(move |_| submit_clone());
+    confirm_entry.connect_activate(move |_| submit());
+
+    dialog.present();
+    passphrase_entry.grab_focus();
+}
+
+// Opens a shared note from a file or pasted armor; it can be viewed or added to the vault
+fn show_open_shared_note_dialog<F>(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    refresh_list: F,
+) where F: Fn() + 'static + Clone {
+    let (dialog, vbox) = build_dialog_window(parent, "Open Shared Note", 440);
+
+    let label = Label::new(Some("Paste the shared note text, or choose a file:"));
+    label.set_halign(gtk::Align::Start);
+
+    let text_buffer = gtk::TextBuffer::new(None);
+    let text_view = gtk::TextView::builder()
+        .buffer(&text_buffer)
+        .monospace(true)
+        .wrap_mode(gtk::WrapMode::Char)
+        .build();
+    text_view.add_css_class("content-view");
+    let text_scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&text_view)
+        .min_content_height(140)
+        .build();
+
+    // Raw bytes of a chosen file take precedence over pasted text
+    let file_bytes: Rc<RefCell<Option<Vec<u8>>>> = Rc::new(RefCell::new(None));
+
+    let choose_file_button = gtk::Button::with_label("Choose File...");
+    choose_file_button.add_css_class("secondary-button");
+    choose_file_button.set_halign(gtk::Align::Start);
+
+    let passphrase_entry = gtk::PasswordEntry::new();
+    passphrase_entry.set_placeholder_text(Some("Passphrase"));
+    passphrase_entry.set_show_peek_icon(true);
+
+    let open_status = Rc::new(Label::new(None));
+    open_status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+
+    let view_button = gtk::Button::with_label("View");
+    view_button.add_css_class("secondary-button");
+    
+    let add_button = gtk::Button::with_label("Add to Vault");
+    add_button.add_css_class("action-button");
+
+    button_box.append(&view_button);
+    button_box.append(&add_button);
+
+    vbox.append(&label);
+    vbox.append(&text_scrolled);
+    vbox.append(&choose_file_button);
+    vbox.append(&passphrase_entry);
+    vbox.append(open_status.as_ref());
+    vbox.append(&button_box);
+
+    let dialog_clone = dialog.clone();
+    let file_bytes_clone = file_bytes.clone();
+    let open_status_clone = open_status.clone();
+    let text_buffer_clone = text_buffer.clone();
+    choose_file_button.connect_clicked(move |_| {
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Open Shared Note"), 
+            Some(&dialog_clone), 
+            gtk::FileChooserAction::Open,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Open", gtk::ResponseType::Accept)],
+        );
+        let file_bytes = file_bytes_clone.clone();
+        let open_status = open_status_clone.clone();
+        let text_buffer = text_buffer_clone.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    match std::fs::read(&path) {
+                        Ok(bytes) => {
+                            *file_bytes.borrow_mut() = Some(bytes);
+                            text_buffer.set_text("");
+                            open_status.set_text(&format!("File: {}", path.display()));
+                        },
+                        Err(e) => open_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    // Decrypts on a worker thread, then hands the note to `on_opened`
+    let open = {
+        let passphrase_entry = passphrase_entry.clone();
+        let open_status = open_status.clone();
+        move |on_opened: Box<dyn Fn(core::data::Note)>| {
+            let passphrase = passphrase_entry.text().to_string();
+            if passphrase.is_empty() {
+                open_status.set_markup("<span foreground='#a06060'>Passphrase required</span>");
+                return;
+            }
+            let data = match file_bytes.borrow().clone() {
+                Some(bytes) => bytes,
+                None => text_buffer.text(&text_buffer.start_iter(), &text_buffer.end_iter(), false).to_string().into_bytes(),
+            };
+            if data.is_empty() {
+                open_status.set_markup("<span foreground='#a06060'>Paste the text or choose a file</span>");
+                return;
+            }
+            open_status.set_text("Decrypting...");
+
+            let open_status = open_status.clone();
+            let (sender, receiver) = async_channel::unbounded();
+            let runtime = TOKIO_RUNTIME.get().unwrap();
+            
+            glib::spawn_future_local(async move {
+                let _guard = runtime.enter();
+                let result = tokio::task::spawn_blocking(move || {
+                    let pw = core::data::MasterPassword::from(passphrase.as_str());
+                    CoreManager::open_shared_note(&data, pw)
+                }).await;
+                let _ = sender.send(result).await;
+            });
+            
+            glib::spawn_future_local(async move {
+                if let Ok(result) = receiver.recv().await {
+                    match result {
+                        Ok(Ok(note)) => on_opened(note),
+                        Ok(Err(e)) => open_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                        Err(e) => open_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                }
+            });
+        }
+    };
+    let open = Rc::new(open);
+
+    let open_clone = open.clone();
+    let dialog_clone = dialog.clone();
+    let open_status_clone = open_status.clone();
+    view_button.connect_clicked(move |_| {
+        let dialog_for_view = dialog_clone.clone();
+        let open_status = open_status_clone.clone();
+        open_clone(Box::new(move |note| {
+            open_status.set_text("");
+            show_shared_note_viewer(&dialog_for_view, note);
+        }));
+    });
+
+    let dialog_clone = dialog.clone();
+    add_button.connect_clicked(move |_| {
+        let dialog_close = dialog_clone.clone();
+        let manager_rc = manager_rc.clone();
+        let status_label = status_label.clone();
+        let refresh = refresh_list.clone();
+        open(Box::new(move |note| {
+            let title = note.title.clone();
+            match manager_rc.lock().unwrap().add_shared_note(note) {
+                Ok(_) => {
+                    status_label.set_text(&format!("Added shared note: {}", title));
+                    refresh();
+                    dialog_close.close();
+                },
+                Err(e) => status_label.set_text(&format!("Error: {}", e)),
+            }
+        }));
+    });
+
+    dialog.present();
+}
+
+// Read-only view of a shared note that has not been added to the vault
+fn show_shared_note_viewer(parent: &gtk::Window, mut note: core::data::Note) {
+    let (dialog, vbox) = build_dialog_window(parent, "Shared Note", 520);
+    dialog.set_default_size(520, 440);
+
+    let title = Label::new(Some(&note.title));
+    title.add_css_class("note-title");
+    title.set_halign(gtk::Align::Start);
+    title.set_wrap(true);
+    title.set_selectable(true);
+
+    let date = Label::new(Some(&format!("Last edited {}", note.updated_at.format("%b %d, %Y %H:%M"))));
+    date.add_css_class("note-date");
+    date.set_halign(gtk::Align::Start);
+
+    let buffer = gtk::TextBuffer::new(None);
+    buffer.set_text(&note.content);
+    let content_view = gtk::TextView::builder()
+        .buffer(&buffer)
+        .editable(false)
+        .cursor_visible(false)
+        .wrap_mode(gtk::WrapMode::Word)
+        .left_margin(10)
+        .right_margin(10)
+        .top_margin(10)
+        .bottom_margin(10)
+        .build();
+    content_view.add_css_class("content-view");
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&content_view)
+        .vexpand(true)
+        .build();
+
+    vbox.append(&title);
+    vbox.append(&date);
+    vbox.append(&scrolled);
+
+    // Only the widgets hold the plaintext now; clear them when the viewer closes
+    note.zeroize();
+    dialog.connect_close_request(move |_| {
+        buffer.set_text("");
+        glib::Propagation::Proceed
+    });
+
+    dialog.present();
+}
+
+// Shows a freshly generated recovery code once, with copy and save-as-sheet options
+fn show_recovery_code_dialog(parent: &impl IsA<gtk::Window>, code: String, vault_path: String, clipboard_timeout: u64) {
+    let (dialog, vbox) = build_dialog_window(parent, "Recovery Code", 420);
+
+    let info = Label::new(Some("Write this code down or save it somewhere safe and offline. It will not be shown again."));
+    info.set_wrap(true);
+    info.set_xalign(0.0);
+
+    let code_label = Label::new(Some(&code));
+    code_label.add_css_class("note-title");
+    code_label.set_selectable(true);
+    code_label.set_margin_top(6);
+    code_label.set_margin_bottom(6);
+
+    let warning = Label::new(Some("Anyone with this code can open your vault."));
+    warning.add_css_class("note-date");
+    warning.set_xalign(0.0);
+
+    let status = Arc::new(Label::new(None));
+    status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    let copy_button = gtk::Button::with_label("Copy");
+    copy_button.add_css_class("secondary-button");
+    let save_button = gtk::Button::with_label("Save Sheet...");
+    save_button.add_css_class("secondary-button");
+    let done_button = gtk::Button::with_label("Done");
+    done_button.add_css_class("action-button");
+    button_box.append(&copy_button);
+    button_box.append(&save_button);
+    button_box.append(&done_button);
+
+    vbox.append(&info);
+    vbox.append(&code_label);
+    vbox.append(&warning);
+    vbox.append(status.as_ref());
+    vbox.append(&button_box);
+
+    let code = Rc::new(RefCell::new(code));
+
+    let code_clone = code.clone();
+    let status_clone = status.clone();
+    copy_button.connect_clicked(move |_| {
+        copy_to_clipboard(&code_clone.borrow(), status_clone.clone(), clipboard_timeout);
+    });
+
+    let dialog_clone = dialog.clone();
+    let code_clone = code.clone();
+    save_button.connect_clicked(move |_| {
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Save Recovery Sheet"),
+            Some(&dialog_clone),
+            gtk::FileChooserAction::Save,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Save", gtk::ResponseType::Accept)],
+        );
+        file_chooser.set_current_name("notas-recovery-code.txt");
+
+        let code = code_clone.clone();
+        let vault_path = vault_path.clone();
+        let status = status.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    let mut sheet = core::recovery::sheet(&code.borrow(), &vault_path);
+                    // Owner-only permissions: the sheet unlocks the vault
+                    let result = {
+                        use std::io::Write;
+                        use std::os::unix::fs::OpenOptionsExt;
+                        std::fs::OpenOptions::new()
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .mode(0o600)
+                            .open(&path)
+                            .and_then(|mut file| file.write_all(sheet.as_bytes()))
+                    };
+                    sheet.zeroize();
+                    match result {
+                        Ok(_) => status.set_text(&format!("Saved: {}", path.display())),
+                        Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    let dialog_clone = dialog.clone();
+    done_button.connect_clicked(move |_| dialog_clone.close());
+
+    dialog.connect_close_request(move |_| {
+        code.borrow_mut().zeroize();
+        code_label.set_text("");
+        glib::Propagation::Proceed
+    });
+
+    dialog.present();
+}
+
+// Offered right after a new vault is created
+fn show_recovery_offer_dialog(parent: &impl IsA<gtk::Window>, manager_rc: Arc<Mutex<CoreManager>>) {
+    let (dialog, vbox) = build_dialog_window(parent, "Recovery Code", 380);
+
+    let info = Label::new(Some("Create a recovery code? It lets you regain access to your notes if you forget your master password. You can also do this later in Preferences."));
+    info.set_wrap(true);
+    info.set_xalign(0.0);
+
+    let status = Label::new(None);
+    status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    let later_button = gtk::Button::with_label("Not Now");
+    later_button.add_css_class("secondary-button");
+    let create_button = gtk::Button::with_label("Create Code");
+    create_button.add_css_class("action-button");
+    button_box.append(&later_button);
+    button_box.append(&create_button);
+
+    vbox.append(&info);
+    vbox.append(&status);
+    vbox.append(&button_box);
+
+    let dialog_clone = dialog.clone();
+    later_button.connect_clicked(move |_| dialog_clone.close());
+
+    let dialog_clone = dialog.clone();
+    create_button.connect_clicked(move |_| {
+        let mut manager = manager_rc.lock().unwrap();
+        match manager.generate_recovery_code() {
+            Ok(code) => {
+                let vault_path = manager.get_data_path().display().to_string();
+                let clipboard_timeout = manager.get_settings().clipboard_timeout;
+                drop(manager);
+                if let Some(parent) = dialog_clone.transient_for() {
+                    show_recovery_code_dialog(&parent, code, vault_path, clipboard_timeout);
+                }
+                dialog_clone.close();
+            },
+            Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    });
+
+    dialog.present();
+}
+
+// "Forgot password?" on the unlock screen: recovery code plus a new master password
+fn show_forgot_password_dialog(window: &ApplicationWindow, app: &Application, keyfile_path: Rc<RefCell<Option<std::path::PathBuf>>>) {
+    let (dialog, vbox) = build_dialog_window(window, "Forgot Password", 360);
+
+    let requires_totp = CORE_MANAGER.get().unwrap().lock().unwrap().requires_totp();
+    let info = Label::new(Some(if requires_totp {
+        "Enter your recovery code and choose a new master password. Two-factor codes will be turned off; you can set them up again in Preferences."
+    } else {
+        "Enter your recovery code and choose a new master password."
+    }));
+    info.set_wrap(true);
+    info.set_xalign(0.0);
+
+    let code_entry = gtk::Entry::new();
+    code_entry.set_placeholder_text(Some("XXXXX-XXXXX-XXXXX-XXXXX-XXXXX"));
+
+    let new_password_entry = gtk::PasswordEntry::new();
+    new_password_entry.set_placeholder_text(Some("New Password"));
+    new_password_entry.set_show_peek_icon(true);
+
+    let confirm_entry = gtk::PasswordEntry::new();
+    confirm_entry.set_placeholder_text(Some("Confirm New Password"));
+    confirm_entry.set_show_peek_icon(true);
+
+    let (strength_bar, strength_label) = build_strength_meter();
+    new_password_entry.connect_changed(glib::clone!(@strong strength_bar, @strong strength_label => move |entry| {
+        update_strength_meter(&strength_bar, &strength_label, entry.text().as_str());
+    }));
+
+    let status = Label::new(None);
+    status.set_halign(gtk::Align::Start);
+    status.set_wrap(true);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
+    let reset_button = gtk::Button::with_label("Reset Password");
+    reset_button.add_css_class("action-button");
+    button_box.append(&cancel_button);
+    button_box.append(&reset_button);
+
+    vbox.append(&info);
+    vbox.append(&code_entry);
+    vbox.append(&new_password_entry);
+    vbox.append(&strength_bar);
+    vbox.append(&strength_label);
+    vbox.append(&confirm_entry);
+    vbox.append(&status);
+    vbox.append(&button_box);
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| dialog_clone.close());
+
+    let dialog_clone = dialog.clone();
+    let window = window.clone();
+    let app = app.clone();
+    reset_button.connect_clicked(move |_| {
+        let code = code_entry.text().to_string();
+        let new_password = new_password_entry.text().to_string();
+        if code.is_empty() || new_password.is_empty() {
+            status.set_markup("<span foreground='#a06060'>All fields required</span>");
+            return;
+        }
+        if new_password != confirm_entry.text().as_str() {
+            status.set_markup("<span foreground='#a06060'>Passwords don't match</span>");
+            return;
+        }
+        let policy = CORE_MANAGER.get().unwrap().lock().unwrap().get_settings().password_policy;
+        if let Err(e) = policy.check(&new_password) {
+            status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+            return;
+        }
+        // A keyfile chosen on the unlock screen stays required with the new password
+        let keyfile = match keyfile_path.borrow().as_deref().map(Keyfile::load).transpose() {
+            Ok(keyfile) => keyfile,
+            Err(e) => {
+                status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                return;
+            }
+        };
+
+        let manager_rc = CORE_MANAGER.get().unwrap().clone();
+        let result = manager_rc.lock().unwrap().recover(
+            &code,
+            core::data::MasterPassword::from(new_password.as_str()),
+            UnlockFactors { keyfile, totp_code: None },
+        );
+        match result {
+            Ok(_) => {
+                code_entry.set_text("");
+                new_password_entry.set_text("");
+                confirm_entry.set_text("");
+                dialog_clone.close();
+                window.close();
+                show_main_window(&app);
+            },
+            Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    });
+
+    dialog.present();
+}
+
+fn show_preferences_dialog(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    title_entry_widget: Arc<gtk::Entry>,
+    title_toggle_widget: gtk::Switch,
+) {
+    let settings = manager_rc.lock().unwrap().get_settings().clone();
+    let db_path = manager_rc.lock().unwrap().get_data_path().display().to_string();
+    
+    let dialog = gtk::Window::builder()
+        .title("Preferences")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(420)
+        .default_height(560)
+        .build();
+    
+    // Custom header for dialog too
+    let header = gtk::HeaderBar::new();
+    header.set_show_title_buttons(false);
+    header.add_css_class("custom-headerbar");
+    
+    let dialog_for_header = dialog.clone();
+    let close_btn = gtk::Button::new();
+    close_btn.add_css_class("traffic-btn");
+    close_btn.add_css_class("traffic-close");
+    close_btn.connect_clicked(move |_| dialog_for_header.close());
+    
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    btn_box.set_margin_start(4);
+    btn_box.append(&close_btn);
+    header.pack_start(&btn_box);
+    
+    let header_title = Label::new(Some("Preferences"));
+    header_title.add_css_class("headerbar-title");
+    header.set_title_widget(Some(&header_title));
+    
+    dialog.set_titlebar(Some(&header));
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .build();
+
+    let main_box = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    main_box.set_margin_top(18);
+    main_box.set_margin_bottom(18);
+    main_box.set_margin_start(18);
+    main_box.set_margin_end(18);
+
+    // Editor group
+    let editor_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    editor_group.add_css_class("preferences-group");
+    
+    let editor_title = Label::new(Some("EDITOR"));
+    editor_title.add_css_class("preferences-title");
+    editor_title.set_halign(gtk::Align::Start);
+    
+    // Font family dropdown
+    let font_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let font_label = Label::new(Some("Font"));
+    font_label.set_hexpand(true);
+    font_label.set_halign(gtk::Align::Start);
+    
+    let font_dropdown = gtk::DropDown::from_strings(
+        &EditorFont::all_fonts().iter().map(|f| f.display_name()).collect::<Vec<_>>()
+    );
+    font_dropdown.set_selected(settings.editor_font.to_index());
+    
+    font_row.append(&font_label);
     font_row.append(&font_dropdown);
     
-    // Font size
-    let size_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let size_label = Label::new(Some("Font size"));
-    size_label.set_hexpand(true);
-    size_label.set_halign(gtk::Align::Start);
-    let size_spin = gtk::SpinButton::with_range(6.0, 24.0, 1.0);
-    size_spin.set_value(settings.editor_font_size as f64);
-    size_row.append(&size_label);
-    size_row.append(&size_spin);
+    // Font size
+    let size_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let size_label = Label::new(Some("Font size"));
+    size_label.set_hexpand(true);
+    size_label.set_halign(gtk::Align::Start);
+    let size_spin = gtk::SpinButton::with_range(6.0, 24.0, 1.0);
+    size_spin.set_value(settings.editor_font_size as f64);
+    size_row.append(&size_label);
+    size_row.append(&size_spin);
+    
+    // Show title toggle
+    let title_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let title_label = Label::new(Some("Show note title"));
+    title_label.set_hexpand(true);
+    title_label.set_halign(gtk::Align::Start);
+    let title_switch = gtk::Switch::new();
+    title_switch.set_active(settings.show_note_title);
+    title_row.append(&title_label);
+    title_row.append(&title_switch);
+    
+    editor_group.append(&editor_title);
+    editor_group.append(&font_row);
+    editor_group.append(&size_row);
+    editor_group.append(&title_row);
+
+    // Security group
+    let security_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    security_group.add_css_class("preferences-group");
+    
+    let security_title = Label::new(Some("SECURITY"));
+    security_title.add_css_class("preferences-title");
+    security_title.set_halign(gtk::Align::Start);
+    
+    let auto_lock_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let auto_lock_label = Label::new(Some("Auto-lock (sec, 0=off)"));
+    auto_lock_label.set_hexpand(true);
+    auto_lock_label.set_halign(gtk::Align::Start);
+    let auto_lock_spin = gtk::SpinButton::with_range(0.0, 3600.0, 30.0);
+    auto_lock_spin.set_value(settings.auto_lock_timeout as f64);
+    auto_lock_row.append(&auto_lock_label);
+    auto_lock_row.append(&auto_lock_spin);
+    
+    let clipboard_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let clipboard_label = Label::new(Some("Clipboard clear (sec, 0=off)"));
+    clipboard_label.set_hexpand(true);
+    clipboard_label.set_halign(gtk::Align::Start);
+    let clipboard_spin = gtk::SpinButton::with_range(0.0, 300.0, 5.0);
+    clipboard_spin.set_value(settings.clipboard_timeout as f64);
+    clipboard_row.append(&clipboard_label);
+    clipboard_row.append(&clipboard_spin);
+    
+    let wipe_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let wipe_label = Label::new(Some("Erase vault after failed unlocks (0=off)"));
+    wipe_label.set_hexpand(true);
+    wipe_label.set_halign(gtk::Align::Start);
+    let wipe_spin = gtk::SpinButton::with_range(0.0, 100.0, 1.0);
+    wipe_spin.set_value(settings.wipe_after_failures as f64);
+    wipe_spin.set_tooltip_text(Some("Keep a backup: an erased vault can't be recovered"));
+    wipe_row.append(&wipe_label);
+    wipe_row.append(&wipe_spin);
+
+    // Desktop events that lock right away
+    let lock_switch_row = |text: &str, active: bool| {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        let label = Label::new(Some(text));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        let switch = gtk::Switch::new();
+        switch.set_active(active);
+        row.append(&label);
+        row.append(&switch);
+        (row, switch)
+    };
+    let (session_lock_row, session_lock_switch) = lock_switch_row("Lock with the desktop session", settings.lock_triggers.session_lock);
+    let (suspend_row, suspend_switch) = lock_switch_row("Lock on suspend", settings.lock_triggers.suspend);
+    let (screensaver_row, screensaver_switch) = lock_switch_row("Lock when the screensaver starts", settings.lock_triggers.screensaver);
+    let lock_spin_row = |text: &str, value: u64| {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        let label = Label::new(Some(text));
+        label.set_hexpand(true);
+        label.set_halign(gtk::Align::Start);
+        let spin = gtk::SpinButton::with_range(0.0, 3600.0, 10.0);
+        spin.set_value(value as f64);
+        row.append(&label);
+        row.append(&spin);
+        (row, spin)
+    };
+    let (unfocused_row, unfocused_spin) = lock_spin_row("Lock when unfocused (sec, 0=off)", settings.lock_triggers.unfocused_after);
+    let (minimized_row, minimized_spin) = lock_spin_row("Lock when minimized (sec, 0=off)", settings.lock_triggers.minimized_after);
+    let (privacy_row, privacy_switch) = lock_switch_row("Blur notes when unfocused", settings.lock_triggers.privacy_screen);
+
+    // The PIN itself is chosen on the lock screen, at the next password unlock
+    let quick_unlock_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let quick_unlock_label = Label::new(Some("Quick unlock PIN valid for (min, 0=off)"));
+    quick_unlock_label.set_hexpand(true);
+    quick_unlock_label.set_halign(gtk::Align::Start);
+    let quick_unlock_spin = gtk::SpinButton::with_range(0.0, 1440.0, 15.0);
+    quick_unlock_spin.set_value(settings.quick_unlock_minutes as f64);
+    quick_unlock_spin.set_tooltip_text(Some(&format!(
+        "After a password unlock, a PIN reopens Notas for this long. {} wrong PINs require the password.",
+        MAX_TRIES,
+    )));
+    quick_unlock_row.append(&quick_unlock_label);
+    quick_unlock_row.append(&quick_unlock_spin);
+    
+    // Leave the unlocked key with the login session for other Notas processes
+    let key_cache_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let key_cache_label = Label::new(Some("Share unlock with this login session"));
+    key_cache_label.set_hexpand(true);
+    key_cache_label.set_halign(gtk::Align::Start);
+    let key_cache_dropdown = gtk::DropDown::from_strings(
+        &KeyCacheBackend::all_backends().iter().map(|b| b.display_name()).collect::<Vec<_>>()
+    );
+    key_cache_dropdown.set_selected(settings.key_cache.to_index());
+    key_cache_dropdown.set_tooltip_text(Some("Secret Service needs secret-tool (libsecret-tools). Locking Notas removes the shared key."));
+    key_cache_row.append(&key_cache_label);
+    key_cache_row.append(&key_cache_dropdown);
+    
+    let key_cache_minutes_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let key_cache_minutes_label = Label::new(Some("Shared unlock lasts (min)"));
+    key_cache_minutes_label.set_hexpand(true);
+    key_cache_minutes_label.set_halign(gtk::Align::Start);
+    let key_cache_minutes_spin = gtk::SpinButton::with_range(1.0, 1440.0, 15.0);
+    key_cache_minutes_spin.set_value(settings.key_cache_minutes as f64);
+    key_cache_minutes_row.append(&key_cache_minutes_label);
+    key_cache_minutes_row.append(&key_cache_minutes_spin);
+    
+    // Minimum for new master passwords
+    let min_length_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let min_length_label = Label::new(Some("Min password length"));
+    min_length_label.set_hexpand(true);
+    min_length_label.set_halign(gtk::Align::Start);
+    let min_length_spin = gtk::SpinButton::with_range(1.0, 64.0, 1.0);
+    min_length_spin.set_value(settings.password_policy.min_length as f64);
+    min_length_row.append(&min_length_label);
+    min_length_row.append(&min_length_spin);
+    
+    let min_strength_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let min_strength_label = Label::new(Some("Min password strength"));
+    min_strength_label.set_hexpand(true);
+    min_strength_label.set_halign(gtk::Align::Start);
+    let min_strength_dropdown = gtk::DropDown::from_strings(
+        &(0..=4).map(core::strength::score_label).collect::<Vec<_>>()
+    );
+    min_strength_dropdown.set_selected(settings.password_policy.min_score as u32);
+    min_strength_dropdown.set_tooltip_text(Some("Applies to new and changed master passwords"));
+    min_strength_row.append(&min_strength_label);
+    min_strength_row.append(&min_strength_dropdown);
+    
+    // Cipher of the open vault; changing it re-encrypts the notes on save
+    let cipher_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let cipher_label = Label::new(Some("Cipher"));
+    cipher_label.set_hexpand(true);
+    cipher_label.set_halign(gtk::Align::Start);
+    let cipher_dropdown = gtk::DropDown::from_strings(
+        &Cipher::all_ciphers().iter().map(|c| c.display_name()).collect::<Vec<_>>()
+    );
+    let vault_cipher = manager_rc.lock().unwrap().vault_cipher().unwrap_or(settings.cipher);
+    cipher_dropdown.set_selected(vault_cipher.to_index());
+    cipher_dropdown.set_tooltip_text(Some("XChaCha20-Poly1305 is faster on CPUs without AES instructions"));
+    cipher_row.append(&cipher_label);
+    cipher_row.append(&cipher_dropdown);
+    
+    security_group.append(&security_title);
+    security_group.append(&auto_lock_row);
+    security_group.append(&clipboard_row);
+    security_group.append(&wipe_row);
+    security_group.append(&session_lock_row);
+    security_group.append(&suspend_row);
+    security_group.append(&screensaver_row);
+    security_group.append(&unfocused_row);
+    security_group.append(&minimized_row);
+    security_group.append(&privacy_row);
+    security_group.append(&quick_unlock_row);
+    security_group.append(&key_cache_row);
+    security_group.append(&key_cache_minutes_row);
+    security_group.append(&min_length_row);
+    security_group.append(&min_strength_row);
+    security_group.append(&cipher_row);
+
+    // Storage group
+    let storage_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    storage_group.add_css_class("preferences-group");
+    
+    let storage_title = Label::new(Some("STORAGE"));
+    storage_title.add_css_class("preferences-title");
+    storage_title.set_halign(gtk::Align::Start);
+    
+    let path_label = Label::new(Some("Database location:"));
+    path_label.set_halign(gtk::Align::Start);
+    
+    let path_entry = gtk::Entry::new();
+    path_entry.set_text(&db_path);
+    path_entry.set_hexpand(true);
+    
+    storage_group.append(&storage_title);
+    storage_group.append(&path_label);
+    storage_group.append(&path_entry);
+
+    // Known vaults: rename (Enter) or remove from the picker, leaving the file alone
+    let vaults_label = Label::new(Some("Vaults:"));
+    vaults_label.set_halign(gtk::Align::Start);
+    vaults_label.set_margin_top(8);
+    storage_group.append(&vaults_label);
+    let (vaults, current_path) = {
+        let manager = manager_rc.lock().unwrap();
+        (manager.vaults().to_vec(), manager.get_data_path().clone())
+    };
+    for vault in vaults {
+        let vault_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+        let name_entry = gtk::Entry::new();
+        name_entry.set_text(&vault.name);
+        name_entry.set_hexpand(true);
+        name_entry.set_tooltip_text(Some(&vault.path.display().to_string()));
+        let opened_label = Label::new(Some(&match vault.last_opened {
+            Some(time) => time.format("%b %d, %Y").to_string(),
+            None => "never opened".to_string(),
+        }));
+        opened_label.add_css_class("note-date");
+        let remove_button = gtk::Button::with_label("Remove");
+        remove_button.add_css_class("secondary-button");
+        remove_button.set_sensitive(vault.path != current_path);
+        vault_row.append(&name_entry);
+        vault_row.append(&opened_label);
+        vault_row.append(&remove_button);
+        storage_group.append(&vault_row);
+
+        let manager_clone = manager_rc.clone();
+        let path = vault.path.clone();
+        let status_label_clone = status_label.clone();
+        name_entry.connect_activate(move |entry| {
+            match manager_clone.lock().unwrap().rename_vault(&path, entry.text().as_str()) {
+                Ok(_) => status_label_clone.set_text("Vault renamed"),
+                Err(e) => status_label_clone.set_text(&format!("Error: {}", e)),
+            }
+        });
+
+        let manager_clone = manager_rc.clone();
+        let path = vault.path.clone();
+        let status_label_clone = status_label.clone();
+        remove_button.connect_clicked(glib::clone!(@weak vault_row => move |_| {
+            match manager_clone.lock().unwrap().forget_vault(&path) {
+                Ok(_) => {
+                    vault_row.set_visible(false);
+                    status_label_clone.set_text("Vault removed from the list");
+                },
+                Err(e) => status_label_clone.set_text(&format!("Error: {}", e)),
+            }
+        }));
+    }
+
+    // Sharing group - the public key others can encrypt exports to
+    let sharing_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    sharing_group.add_css_class("preferences-group");
+    
+    let sharing_title = Label::new(Some("SHARING"));
+    sharing_title.add_css_class("preferences-title");
+    sharing_title.set_halign(gtk::Align::Start);
+    
+    let public_key_label = Label::new(Some("Give your public key to people who want to send you notes."));
+    public_key_label.set_halign(gtk::Align::Start);
+    public_key_label.set_wrap(true);
+    public_key_label.set_xalign(0.0);
+    
+    let public_key_value = Label::new(None);
+    public_key_value.set_selectable(true);
+    public_key_value.set_wrap(true);
+    public_key_value.set_wrap_mode(gtk::pango::WrapMode::Char);
+    public_key_value.set_xalign(0.0);
+    public_key_value.add_css_class("note-date");
+    public_key_value.set_visible(false);
+    
+    let public_key_button = gtk::Button::with_label("Show Public Key");
+    public_key_button.add_css_class("secondary-button");
+    
+    sharing_group.append(&sharing_title);
+    sharing_group.append(&public_key_label);
+    sharing_group.append(&public_key_value);
+    sharing_group.append(&public_key_button);
+
+    let manager_clone = manager_rc.clone();
+    let status_clone = status_label.clone();
+    let clipboard_timeout = settings.clipboard_timeout;
+    public_key_button.connect_clicked(move |btn| {
+        // First click reveals the key (creating it if needed), later clicks copy it
+        if public_key_value.is_visible() {
+            copy_to_clipboard(&public_key_value.text(), status_clone.clone(), clipboard_timeout);
+            return;
+        }
+        match manager_clone.lock().unwrap().public_key() {
+            Ok(key) => {
+                public_key_value.set_text(&key);
+                public_key_value.set_visible(true);
+                btn.set_label("Copy Public Key");
+            },
+            Err(e) => public_key_label.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    });
+
+    // Keyfile group
+    let keyfile_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    keyfile_group.add_css_class("preferences-group");
+    
+    let keyfile_title = Label::new(Some("KEYFILE"));
+    keyfile_title.add_css_class("preferences-title");
+    keyfile_title.set_halign(gtk::Align::Start);
+    
+    let uses_keyfile = manager_rc.lock().unwrap().requires_keyfile();
+    let keyfile_info = Label::new(None);
+    keyfile_info.set_halign(gtk::Align::Start);
+    keyfile_info.set_wrap(true);
+    keyfile_info.set_xalign(0.0);
+    
+    let keyfile_password_entry = gtk::PasswordEntry::new();
+    keyfile_password_entry.set_placeholder_text(Some("Current Password"));
+    keyfile_password_entry.set_show_peek_icon(true);
+    
+    let keyfile_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let set_keyfile_button = gtk::Button::new();
+    set_keyfile_button.add_css_class("secondary-button");
+    let remove_keyfile_button = gtk::Button::with_label("Remove Keyfile");
+    remove_keyfile_button.add_css_class("secondary-button");
+    keyfile_buttons.append(&set_keyfile_button);
+    keyfile_buttons.append(&remove_keyfile_button);
+    
+    let keyfile_status = Rc::new(Label::new(None));
+    keyfile_status.set_halign(gtk::Align::Start);
+    
+    // Reflect whether the vault currently needs a keyfile
+    let update_keyfile_ui = {
+        let keyfile_info = keyfile_info.clone();
+        let set_keyfile_button = set_keyfile_button.clone();
+        let remove_keyfile_button = remove_keyfile_button.clone();
+        move |in_use: bool| {
+            keyfile_info.set_text(if in_use {
+                "Unlocking requires your password and your keyfile."
+            } else {
+                "Require a file (e.g. on a USB stick) in addition to your password."
+            });
+            set_keyfile_button.set_label(if in_use { "Change Keyfile..." } else { "Set Keyfile..." });
+            remove_keyfile_button.set_visible(in_use);
+        }
+    };
+    update_keyfile_ui(uses_keyfile);
+    
+    keyfile_group.append(&keyfile_title);
+    keyfile_group.append(&keyfile_info);
+    keyfile_group.append(&keyfile_password_entry);
+    keyfile_group.append(&keyfile_buttons);
+    keyfile_group.append(keyfile_status.as_ref());
+
+    let dialog_for_keyfile = dialog.clone();
+    let manager_clone = manager_rc.clone();
+    let password_clone = keyfile_password_entry.clone();
+    let keyfile_status_clone = keyfile_status.clone();
+    let update_keyfile_ui_clone = update_keyfile_ui.clone();
+    set_keyfile_button.connect_clicked(move |_| {
+        let password = password_clone.text().to_string();
+        if password.is_empty() {
+            keyfile_status_clone.set_markup("<span foreground='#a06060'>Current password required</span>");
+            return;
+        }
+        
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Choose Keyfile"),
+            Some(&dialog_for_keyfile),
+            gtk::FileChooserAction::Open,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Choose", gtk::ResponseType::Accept)],
+        );
+        let manager_clone = manager_clone.clone();
+        let password_entry = password_clone.clone();
+        let keyfile_status = keyfile_status_clone.clone();
+        let update_keyfile_ui = update_keyfile_ui_clone.clone();
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    change_keyfile(
+                        manager_clone.clone(),
+                        password_entry.clone(),
+                        Some(path),
+                        keyfile_status.clone(),
+                        update_keyfile_ui.clone(),
+                    );
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    let manager_clone = manager_rc.clone();
+    let password_clone = keyfile_password_entry.clone();
+    let keyfile_status_clone = keyfile_status.clone();
+    remove_keyfile_button.connect_clicked(move |_| {
+        if password_clone.text().is_empty() {
+            keyfile_status_clone.set_markup("<span foreground='#a06060'>Current password required</span>");
+            return;
+        }
+        change_keyfile(
+            manager_clone.clone(),
+            password_clone.clone(),
+            None,
+            keyfile_status_clone.clone(),
+            update_keyfile_ui.clone(),
+        );
+    });
+
+    // Two-factor group
+    let totp_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    totp_group.add_css_class("preferences-group");
+    
+    let totp_title = Label::new(Some("TWO-FACTOR CODES"));
+    totp_title.add_css_class("preferences-title");
+    totp_title.set_halign(gtk::Align::Start);
+    
+    let totp_info = Label::new(None);
+    totp_info.set_halign(gtk::Align::Start);
+    totp_info.set_wrap(true);
+    totp_info.set_xalign(0.0);
+    
+    let totp_password_entry = gtk::PasswordEntry::new();
+    totp_password_entry.set_placeholder_text(Some("Current Password"));
+    totp_password_entry.set_show_peek_icon(true);
+    
+    let totp_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let setup_totp_button = gtk::Button::new();
+    setup_totp_button.add_css_class("secondary-button");
+    let disable_totp_button = gtk::Button::with_label("Turn Off");
+    disable_totp_button.add_css_class("secondary-button");
+    totp_buttons.append(&setup_totp_button);
+    totp_buttons.append(&disable_totp_button);
+    
+    let totp_status = Rc::new(Label::new(None));
+    totp_status.set_halign(gtk::Align::Start);
+    totp_status.set_wrap(true);
+    
+    let update_totp_ui = {
+        let totp_info = totp_info.clone();
+        let setup_totp_button = setup_totp_button.clone();
+        let disable_totp_button = disable_totp_button.clone();
+        move |enabled: bool| {
+            totp_info.set_text(if enabled {
+                "Unlocking requires a code from your authenticator app."
+            } else {
+                "Require a code from an authenticator app on your phone in addition to your password."
+            });
+            setup_totp_button.set_label(if enabled { "Use New Device..." } else { "Set Up..." });
+            disable_totp_button.set_visible(enabled);
+        }
+    };
+    update_totp_ui(manager_rc.lock().unwrap().requires_totp());
+    
+    totp_group.append(&totp_title);
+    totp_group.append(&totp_info);
+    totp_group.append(&totp_password_entry);
+    totp_group.append(&totp_buttons);
+    totp_group.append(totp_status.as_ref());
+
+    let dialog_for_totp = dialog.clone();
+    let manager_clone = manager_rc.clone();
+    let password_clone = totp_password_entry.clone();
+    let totp_status_clone = totp_status.clone();
+    let update_totp_ui_clone = update_totp_ui.clone();
+    setup_totp_button.connect_clicked(move |_| {
+        if password_clone.text().is_empty() {
+            totp_status_clone.set_markup("<span foreground='#a06060'>Current password required</span>");
+            return;
+        }
+        totp_status_clone.set_text("");
+        show_totp_setup_dialog(
+            &dialog_for_totp,
+            manager_clone.clone(),
+            password_clone.clone(),
+            totp_status_clone.clone(),
+            update_totp_ui_clone.clone(),
+        );
+    });
+
+    let manager_clone = manager_rc.clone();
+    let password_clone = totp_password_entry.clone();
+    let totp_status_clone = totp_status.clone();
+    disable_totp_button.connect_clicked(move |_| {
+        if password_clone.text().is_empty() {
+            totp_status_clone.set_markup("<span foreground='#a06060'>Current password required</span>");
+            return;
+        }
+        change_two_factor(
+            manager_clone.clone(),
+            password_clone.clone(),
+            None,
+            totp_status_clone.clone(),
+            update_totp_ui.clone(),
+        );
+    });
+
+    // Recovery group
+    let recovery_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    recovery_group.add_css_class("preferences-group");
+    
+    let recovery_title = Label::new(Some("RECOVERY CODE"));
+    recovery_title.add_css_class("preferences-title");
+    recovery_title.set_halign(gtk::Align::Start);
+    
+    let recovery_info = Rc::new(Label::new(None));
+    recovery_info.set_halign(gtk::Align::Start);
+    recovery_info.set_wrap(true);
+    recovery_info.set_xalign(0.0);
+    
+    let recovery_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let generate_recovery_button = gtk::Button::with_label("Generate New Code");
+    generate_recovery_button.add_css_class("secondary-button");
+    let revoke_recovery_button = gtk::Button::with_label("Revoke");
+    revoke_recovery_button.add_css_class("secondary-button");
+    recovery_buttons.append(&generate_recovery_button);
+    recovery_buttons.append(&revoke_recovery_button);
+    
+    let update_recovery_ui = {
+        let recovery_info = recovery_info.clone();
+        let revoke_recovery_button = revoke_recovery_button.clone();
+        move |has_code: bool| {
+            recovery_info.set_text(if has_code {
+                "A recovery code can unlock this vault. Generating a new one revokes it."
+            } else {
+                "No recovery code. If you forget your password, your notes are lost."
+            });
+            revoke_recovery_button.set_visible(has_code);
+        }
+    };
+    update_recovery_ui(manager_rc.lock().unwrap().has_recovery_code());
+    
+    recovery_group.append(&recovery_title);
+    recovery_group.append(recovery_info.as_ref());
+    recovery_group.append(&recovery_buttons);
+
+    let dialog_for_recovery = dialog.clone();
+    let manager_clone = manager_rc.clone();
+    let recovery_info_clone = recovery_info.clone();
+    let update_recovery_ui_clone = update_recovery_ui.clone();
+    generate_recovery_button.connect_clicked(move |_| {
+        let mut manager = manager_clone.lock().unwrap();
+        match manager.generate_recovery_code() {
+            Ok(code) => {
+                let vault_path = manager.get_data_path().display().to_string();
+                drop(manager);
+                update_recovery_ui_clone(true);
+                show_recovery_code_dialog(&dialog_for_recovery, code, vault_path, clipboard_timeout);
+            },
+            Err(e) => recovery_info_clone.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    });
+
+    let manager_clone = manager_rc.clone();
+    revoke_recovery_button.connect_clicked(move |_| {
+        match manager_clone.lock().unwrap().revoke_recovery_code() {
+            Ok(_) => update_recovery_ui(false),
+            Err(e) => recovery_info.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+        }
+    });
+
+    // Duress password group. Whether one is set can't be read from the file, so the
+    // group looks the same either way.
+    let duress_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    duress_group.add_css_class("preferences-group");
+
+    let duress_title = Label::new(Some("DURESS PASSWORD"));
+    duress_title.add_css_class("preferences-title");
+    duress_title.set_halign(gtk::Align::Start);
+
+    let duress_info = Label::new(Some("A second password that opens a separate decoy vault instead of your notes. Nothing in the vault file shows whether one is set."));
+    duress_info.set_halign(gtk::Align::Start);
+    duress_info.set_wrap(true);
+    duress_info.set_xalign(0.0);
+
+    let duress_current_entry = gtk::PasswordEntry::new();
+    duress_current_entry.set_placeholder_text(Some("Current Password"));
+    duress_current_entry.set_show_peek_icon(true);
+
+    let duress_entry = gtk::PasswordEntry::new();
+    duress_entry.set_placeholder_text(Some("Duress Password"));
+    duress_entry.set_show_peek_icon(true);
+
+    let duress_confirm_entry = gtk::PasswordEntry::new();
+    duress_confirm_entry.set_placeholder_text(Some("Confirm Duress Password"));
+    duress_confirm_entry.set_show_peek_icon(true);
+
+    let duress_buttons = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let set_duress_button = gtk::Button::with_label("Set Duress Password");
+    set_duress_button.add_css_class("secondary-button");
+    let remove_duress_button = gtk::Button::with_label("Remove");
+    remove_duress_button.add_css_class("secondary-button");
+    remove_duress_button.set_tooltip_text(Some("Remove any duress password and its decoy notes"));
+    duress_buttons.append(&set_duress_button);
+    duress_buttons.append(&remove_duress_button);
+
+    let duress_status = Rc::new(Label::new(None));
+    duress_status.set_halign(gtk::Align::Start);
+
+    duress_group.append(&duress_title);
+    duress_group.append(&duress_info);
+    duress_group.append(&duress_current_entry);
+    duress_group.append(&duress_entry);
+    duress_group.append(&duress_confirm_entry);
+    duress_group.append(&duress_buttons);
+    duress_group.append(duress_status.as_ref());
+
+    // Set (Some) or remove (None) the duress password on a worker thread
+    let change_duress = {
+        let manager_rc = manager_rc.clone();
+        let duress_current_entry = duress_current_entry.clone();
+        let duress_entry = duress_entry.clone();
+        let duress_confirm_entry = duress_confirm_entry.clone();
+        let duress_buttons = duress_buttons.clone();
+        let duress_status = duress_status.clone();
+        move |duress: Option<String>| {
+            let current = duress_current_entry.text().to_string();
+            if current.is_empty() {
+                duress_status.set_markup("<span foreground='#a06060'>Current password required</span>");
+                return;
+            }
+            duress_buttons.set_sensitive(false);
+            duress_status.set_text("Saving...");
+
+            let manager_for_task = manager_rc.clone();
+            let (sender, receiver) = async_channel::unbounded();
+            let runtime = TOKIO_RUNTIME.get().unwrap();
+            let setting = duress.is_some();
+
+            glib::spawn_future_local(async move {
+                let _guard = runtime.enter();
+                let result = tokio::task::spawn_blocking(move || {
+                    let password = core::data::MasterPassword::from(current.as_str());
+                    let mut manager = manager_for_task.lock().unwrap();
+                    match duress {
+                        Some(duress) => manager.set_duress_password(password, core::data::MasterPassword::from(duress.as_str())),
+                        None => manager.remove_duress_password(password),
+                    }
+                }).await;
+                let _ = sender.send(result).await;
+            });
+
+            let duress_status = duress_status.clone();
+            let duress_buttons = duress_buttons.clone();
+            let entries = [duress_current_entry.clone(), duress_entry.clone(), duress_confirm_entry.clone()];
+            glib::spawn_future_local(async move {
+                if let Ok(result) = receiver.recv().await {
+                    match result {
+                        Ok(Ok(_)) => {
+                            duress_status.set_markup(if setting {
+                                "<span foreground='#60a060'>Duress password set. Its vault starts empty.</span>"
+                            } else {
+                                "<span foreground='#60a060'>Duress password removed</span>"
+                            });
+                            for entry in &entries {
+                                entry.set_text("");
+                            }
+                        },
+                        Ok(Err(e)) => duress_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                        Err(e) => duress_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    }
+                    duress_buttons.set_sensitive(true);
+                }
+            });
+        }
+    };
+    let change_duress = Rc::new(change_duress);
+
+    let change_duress_clone = change_duress.clone();
+    let duress_entry_clone = duress_entry.clone();
+    let duress_status_clone = duress_status.clone();
+    set_duress_button.connect_clicked(move |_| {
+        let duress = duress_entry_clone.text().to_string();
+        if duress.is_empty() {
+            duress_status_clone.set_markup("<span foreground='#a06060'>Duress password required</span>");
+            return;
+        }
+        if duress != duress_confirm_entry.text().as_str() {
+            duress_status_clone.set_markup("<span foreground='#a06060'>Passwords don't match</span>");
+            return;
+        }
+        change_duress_clone(Some(duress));
+    });
+
+    remove_duress_button.connect_clicked(move |_| change_duress(None));
+
+    // Password group
+    let password_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    password_group.add_css_class("preferences-group");
+    
+    let password_title = Label::new(Some("CHANGE PASSWORD"));
+    password_title.add_css_class("preferences-title");
+    password_title.set_halign(gtk::Align::Start);
     
-    // Show title toggle
-    let title_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let title_label = Label::new(Some("Show note title"));
-    title_label.set_hexpand(true);
-    title_label.set_halign(gtk::Align::Start);
-    let title_switch = gtk::Switch::new();
-    title_switch.set_active(settings.show_note_title);
-    title_row.append(&title_label);
-    title_row.append(&title_switch);
+    let current_password_entry = gtk::PasswordEntry::new();
+    current_password_entry.set_placeholder_text(Some("Current Password"));
+    current_password_entry.set_show_peek_icon(true);
+    
+    let new_password_entry = gtk::PasswordEntry::new();
+    new_password_entry.set_placeholder_text(Some("New Password"));
+    new_password_entry.set_show_peek_icon(true);
+    
+    let confirm_password_entry = gtk::PasswordEntry::new();
+    confirm_password_entry.set_placeholder_text(Some("Confirm New Password"));
+    confirm_password_entry.set_show_peek_icon(true);
+    
+    let (strength_bar, strength_label) = build_strength_meter();
+    new_password_entry.connect_changed(glib::clone!(@strong strength_bar, @strong strength_label => move |entry| {
+        update_strength_meter(&strength_bar, &strength_label, entry.text().as_str());
+    }));
+    
+    let change_password_button = gtk::Button::with_label("Change Password");
+    change_password_button.add_css_class("secondary-button");
+    
+    let password_status = Rc::new(Label::new(None));
+    password_status.set_halign(gtk::Align::Start);
+    
+    password_group.append(&password_title);
+    password_group.append(&current_password_entry);
+    password_group.append(&new_password_entry);
+    password_group.append(&strength_bar);
+    password_group.append(&strength_label);
+    password_group.append(&confirm_password_entry);
+    password_group.append(&change_password_button);
+    password_group.append(password_status.as_ref());
+
+    let manager_clone = manager_rc.clone();
+    let current_clone = current_password_entry.clone();
+    let new_clone = new_password_entry.clone();
+    let confirm_clone = confirm_password_entry.clone();
+    let password_status_clone = password_status.clone();
+    
+    change_password_button.connect_clicked(move |btn| {
+        let current = current_clone.text().to_string();
+        let new_pass = new_clone.text().to_string();
+        let confirm = confirm_clone.text().to_string();
+        
+        if current.is_empty() || new_pass.is_empty() || confirm.is_empty() {
+            password_status_clone.set_markup("<span foreground='#a06060'>All fields required</span>");
+            return;
+        }
+        if new_pass != confirm {
+            password_status_clone.set_markup("<span foreground='#a06060'>Passwords don't match</span>");
+            return;
+        }
+        let policy = manager_clone.lock().unwrap().get_settings().password_policy;
+        if let Err(e) = policy.check(&new_pass) {
+            password_status_clone.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+            return;
+        }
+        
+        btn.set_sensitive(false);
+        password_status_clone.set_text("Changing...");
+        
+        let manager_for_task = manager_clone.clone();
+        let password_status_for_ui = password_status_clone.clone();
+        let current_entry = current_clone.clone();
+        let new_entry = new_clone.clone();
+        let confirm_entry = confirm_clone.clone();
+        let btn_ui = btn.clone();
+        
+        let (sender, receiver) = async_channel::unbounded();
+        let runtime = TOKIO_RUNTIME.get().unwrap();
+        
+        glib::spawn_future_local(async move {
+            let _guard = runtime.enter();
+            let result = tokio::task::spawn_blocking(move || {
+                let old = core::data::MasterPassword::from(current.as_str());
+                let new = core::data::MasterPassword::from(new_pass.as_str());
+                manager_for_task.lock().unwrap().change_password(old, new)
+            }).await;
+            let _ = sender.send(result).await;
+        });
+        
+        glib::spawn_future_local(async move {
+            if let Ok(result) = receiver.recv().await {
+                match result {
+                    Ok(Ok(_)) => {
+                        password_status_for_ui.set_markup("<span foreground='#60a060'>Password changed</span>");
+                        current_entry.set_text("");
+                        new_entry.set_text("");
+                        confirm_entry.set_text("");
+                    },
+                    Ok(Err(e)) => password_status_for_ui.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    Err(e) => password_status_for_ui.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                }
+                btn_ui.set_sensitive(true);
+            }
+        });
+    });
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    button_box.set_margin_top(8);
+    
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
+    
+    let save_button = gtk::Button::with_label("Save");
+    save_button.add_css_class("action-button");
+    
+    button_box.append(&cancel_button);
+    button_box.append(&save_button);
+
+    main_box.append(&editor_group);
+    main_box.append(&security_group);
+    main_box.append(&storage_group);
+    main_box.append(&sharing_group);
+    main_box.append(&keyfile_group);
+    main_box.append(&totp_group);
+    main_box.append(&recovery_group);
+    main_box.append(&duress_group);
+    main_box.append(&password_group);
+    main_box.append(&button_box);
+
+    scrolled.set_child(Some(&main_box));
+    dialog.set_child(Some(&scrolled));
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| { dialog_clone.close(); });
+
+    let dialog_clone = dialog.clone();
+    let manager_clone = manager_rc.clone();
+    let path_entry_clone = path_entry.clone();
+    let font_dropdown_clone = font_dropdown.clone();
+    let size_spin_clone = size_spin.clone();
+    let title_switch_clone = title_switch.clone();
+    let title_entry_clone = title_entry_widget.clone();
+    let title_toggle_clone = title_toggle_widget.clone();
+    
+    save_button.connect_clicked(move |_| {
+        let current = manager_clone.lock().unwrap().get_settings().clone();
+        let default_path = manager_clone.lock().unwrap().get_data_path().display().to_string();
+        let path_str = path_entry_clone.text().to_string();
+        
+        let selected_font = EditorFont::from_index(font_dropdown_clone.selected());
+        let font_size = size_spin_clone.value() as u32;
+        let show_title = title_switch_clone.is_active();
+        
+        // Update thread-local settings
+        EDITOR_FONT.with(|f| *f.borrow_mut() = selected_font.clone());
+        EDITOR_FONT_SIZE.with(|s| *s.borrow_mut() = font_size);
+        SHOW_NOTE_TITLE.with(|s| *s.borrow_mut() = show_title);
+        
+        // Update title visibility immediately and keep status bar toggle in sync
+        title_entry_clone.set_visible(show_title);
+        title_toggle_clone.set_active(show_title);
+        
+        let new_settings = AppSettings {
+            auto_lock_timeout: auto_lock_spin.value() as u64,
+            clipboard_timeout: clipboard_spin.value() as u64,
+            custom_db_path: if path_str != default_path { 
+                Some(std::path::PathBuf::from(path_str)) 
+            } else { 
+                // Unchanged: keep the vault in use where it is
+                current.custom_db_path.clone() 
+            },
+            argon2_params: current.argon2_params,
+            theme: CURRENT_THEME.with(|t| t.borrow().clone()),
+            editor_font: selected_font,
+            editor_font_size: font_size,
+            show_note_title: show_title,
+            cipher: Cipher::from_index(cipher_dropdown.selected()),
+            wipe_after_failures: wipe_spin.value() as u32,
+            password_policy: PasswordPolicy {
+                min_length: min_length_spin.value() as usize,
+                min_score: min_strength_dropdown.selected() as u8,
+            },
+            vaults: current.vaults.clone(),
+            lock_triggers: LockTriggers {
+                session_lock: session_lock_switch.is_active(),
+                suspend: suspend_switch.is_active(),
+                screensaver: screensaver_switch.is_active(),
+                unfocused_after: unfocused_spin.value() as u64,
+                minimized_after: minimized_spin.value() as u64,
+                privacy_screen: privacy_switch.is_active(),
+            },
+            quick_unlock_minutes: quick_unlock_spin.value() as u64,
+            key_cache: KeyCacheBackend::from_index(key_cache_dropdown.selected()),
+            key_cache_minutes: key_cache_minutes_spin.value() as u64,
+        };
+        
+        match manager_clone.lock().unwrap().update_settings(new_settings) {
+            Ok(_) => { 
+                status_label.set_text("Settings saved");
+                reload_css();
+                recheck_auto_lock();
+                dialog_clone.close(); 
+            },
+            Err(e) => status_label.set_text(&format!("Error: {}", e)),
+        }
+    });
+
+    dialog.present();
+}
+
+// Re-encrypts the vault with a new keyfile (or none) after re-checking the password
+fn change_keyfile<F>(
+    manager_rc: Arc<Mutex<CoreManager>>,
+    password_entry: gtk::PasswordEntry,
+    keyfile_path: Option<std::path::PathBuf>,
+    status: Rc<Label>,
+    on_changed: F,
+) where F: Fn(bool) + 'static {
+    let password = password_entry.text().to_string();
+    let adding = keyfile_path.is_some();
+    status.set_text("Re-encrypting...");
+    
+    let (sender, receiver) = async_channel::unbounded();
+    let runtime = TOKIO_RUNTIME.get().unwrap();
+    
+    glib::spawn_future_local(async move {
+        let _guard = runtime.enter();
+        let result = tokio::task::spawn_blocking(move || {
+            let keyfile = keyfile_path.as_deref().map(Keyfile::load).transpose()?;
+            let password = core::data::MasterPassword::from(password.as_str());
+            manager_rc.lock().unwrap().set_keyfile(password, keyfile)
+        }).await;
+        let _ = sender.send(result).await;
+    });
+    
+    glib::spawn_future_local(async move {
+        if let Ok(result) = receiver.recv().await {
+            match result {
+                Ok(Ok(_)) => {
+                    status.set_markup(if adding {
+                        "<span foreground='#60a060'>Keyfile set. Keep a backup copy of it.</span>"
+                    } else {
+                        "<span foreground='#60a060'>Keyfile removed</span>"
+                    });
+                    password_entry.set_text("");
+                    on_changed(adding);
+                },
+                Ok(Err(e)) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+            }
+        }
+    });
+}
+
+// Enrolls a fresh TOTP secret: shows it as a QR code and as text, then asks for the
+// first code so a failed scan can't lock the user out
+fn show_totp_setup_dialog<F>(
+    parent: &impl IsA<gtk::Window>,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    password_entry: gtk::PasswordEntry,
+    status: Rc<Label>,
+    on_changed: F,
+) where F: Fn(bool) + Clone + 'static {
+    let (dialog, vbox) = build_dialog_window(parent, "Set Up Two-Factor Codes", 380);
+
+    let secret = TotpSecret::generate();
+    let account = glib::user_name().to_string_lossy().to_string();
+    let mut uri = secret.otpauth_uri(&account);
+
+    let info = Label::new(Some("Scan this code with an authenticator app, or enter the key below by hand."));
+    info.set_wrap(true);
+    info.set_xalign(0.0);
+
+    // QR modules are drawn with cairo; the quiet zone is part of the white background
+    let modules = qrcode::QrCode::new(uri.as_bytes()).ok().map(|qr| {
+        let width = qr.width();
+        let dark: Vec<bool> = qr.to_colors().iter().map(|c| *c == qrcode::Color::Dark).collect();
+        (width, dark)
+    });
+    uri.zeroize();
+
+    let qr_area = gtk::DrawingArea::new();
+    qr_area.set_content_width(200);
+    qr_area.set_content_height(200);
+    qr_area.set_halign(gtk::Align::Center);
+    qr_area.set_visible(modules.is_some());
+    let modules = Rc::new(RefCell::new(modules));
+    let modules_clone = modules.clone();
+    qr_area.set_draw_func(move |_, cr, width, height| {
+        let modules = modules_clone.borrow();
+        let Some((count, ref dark)) = *modules else { return };
+        let size = width.min(height) as f64;
+        let module = size / (count + 8) as f64;
+        let origin_x = (width as f64 - size) / 2.0 + module * 4.0;
+        let origin_y = (height as f64 - size) / 2.0 + module * 4.0;
+
+        cr.set_source_rgb(1.0, 1.0, 1.0);
+        cr.rectangle((width as f64 - size) / 2.0, (height as f64 - size) / 2.0, size, size);
+        let _ = cr.fill();
+        cr.set_source_rgb(0.0, 0.0, 0.0);
+        for (i, is_dark) in dark.iter().enumerate() {
+            if *is_dark {
+                let (x, y) = ((i % count) as f64, (i / count) as f64);
+                cr.rectangle(origin_x + x * module, origin_y + y * module, module, module);
+            }
+        }
+        let _ = cr.fill();
+    });
+
+    // Groups of four, as most apps show manually entered keys
+    let mut key_text = secret.to_base32()
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let key_label = Label::new(Some(&key_text));
+    key_text.zeroize();
+    key_label.add_css_class("note-title");
+    key_label.set_selectable(true);
+    key_label.set_wrap(true);
+
+    let code_entry = gtk::Entry::new();
+    code_entry.set_placeholder_text(Some("Code from the app"));
+    code_entry.set_input_purpose(gtk::InputPurpose::Digits);
+    code_entry.set_max_length(7);
+
+    let dialog_status = Rc::new(Label::new(None));
+    dialog_status.set_halign(gtk::Align::Start);
+    dialog_status.set_wrap(true);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
+    let enable_button = gtk::Button::with_label("Turn On");
+    enable_button.add_css_class("action-button");
+    button_box.append(&cancel_button);
+    button_box.append(&enable_button);
+
+    vbox.append(&info);
+    vbox.append(&qr_area);
+    vbox.append(&key_label);
+    vbox.append(&code_entry);
+    vbox.append(dialog_status.as_ref());
+    vbox.append(&button_box);
+
+    // Forget the secret and its rendering however the dialog is closed
+    let modules_clone = modules.clone();
+    let key_label_clone = key_label.clone();
+    dialog.connect_close_request(move |_| {
+        if let Some((_, ref mut dark)) = *modules_clone.borrow_mut() {
+            dark.zeroize();
+        }
+        key_label_clone.set_text("");
+        glib::Propagation::Proceed
+    });
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| dialog_clone.close());
+
+    let dialog_clone = dialog.clone();
+    let code_entry_clone = code_entry.clone();
+    let do_enable = move || {
+        let code = code_entry_clone.text().to_string();
+        if code.trim().is_empty() {
+            dialog_status.set_markup("<span foreground='#a06060'>Enter the code shown in the app</span>");
+            return;
+        }
+        let on_changed = on_changed.clone();
+        let dialog = dialog_clone.clone();
+        let dialog_status = dialog_status.clone();
+        let status = status.clone();
+        change_two_factor(
+            manager_rc.clone(),
+            password_entry.clone(),
+            Some((secret.clone(), code)),
+            dialog_status.clone(),
+            move |enabled| {
+                on_changed(enabled);
+                status.set_markup("<span foreground='#60a060'>Two-factor codes turned on</span>");
+                dialog.close();
+            },
+        );
+    };
+
+    let do_enable_clone = do_enable.clone();
+    enable_button.connect_clicked(move |_| do_enable_clone());
+    code_entry.connect_activate(move |_| do_enable());
+
+    dialog.present();
+}
+
+// Turns two-factor codes on (with the new secret and its first code) or off, after
+// re-checking the password
+fn change_two_factor<F>(
+    manager_rc: Arc<Mutex<CoreManager>>,
+    password_entry: gtk::PasswordEntry,
+    enrollment: Option<(TotpSecret, String)>,
+    status: Rc<Label>,
+    on_changed: F,
+) where F: Fn(bool) + 'static {
+    let password = password_entry.text().to_string();
+    let enabling = enrollment.is_some();
+    status.set_text("Re-encrypting...");
     
-    editor_group.append(&editor_title);
-    editor_group.append(&font_row);
-    editor_group.append(&size_row);
-    editor_group.append(&title_row);
+    let (sender, receiver) = async_channel::unbounded();
+    let runtime = TOKIO_RUNTIME.get().unwrap();
+    
+    glib::spawn_future_local(async move {
+        let _guard = runtime.enter();
+        let result = tokio::task::spawn_blocking(move || {
+            let password = core::data::MasterPassword::from(password.as_str());
+            let mut manager = manager_rc.lock().unwrap();
+            match enrollment {
+                Some((secret, code)) => manager.enable_totp(password, secret, &code),
+                None => manager.disable_totp(password),
+            }
+        }).await;
+        let _ = sender.send(result).await;
+    });
+    
+    glib::spawn_future_local(async move {
+        if let Ok(result) = receiver.recv().await {
+            match result {
+                Ok(Ok(_)) => {
+                    status.set_markup(if enabling {
+                        "<span foreground='#60a060'>Two-factor codes turned on</span>"
+                    } else {
+                        "<span foreground='#60a060'>Two-factor codes turned off</span>"
+                    });
+                    password_entry.set_text("");
+                    on_changed(enabling);
+                },
+                Ok(Err(e)) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                Err(e) => status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+            }
+        }
+    });
+}
 
-    // Security group
-    let security_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
-    security_group.add_css_class("preferences-group");
+// Asks what to export and how to protect it (passphrase or recipient public keys),
+// then where to save it
+fn show_export_dialog(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    active_note_id: Option<u64>,
+) {
+    let dialog = gtk::Window::builder()
+        .title("Export")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(340)
+        .build();
     
-    let security_title = Label::new(Some("SECURITY"));
-    security_title.add_css_class("preferences-title");
-    security_title.set_halign(gtk::Align::Start);
+    // Custom header
+    let header = gtk::HeaderBar::new();
+    header.set_show_title_buttons(false);
+    header.add_css_class("custom-headerbar");
     
-    let auto_lock_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let auto_lock_label = Label::new(Some("Auto-lock (sec, 0=off)"));
-    auto_lock_label.set_hexpand(true);
-    auto_lock_label.set_halign(gtk::Align::Start);
-    let auto_lock_spin = gtk::SpinButton::with_range(0.0, 3600.0, 30.0);
-    auto_lock_spin.set_value(settings.auto_lock_timeout as f64);
-    auto_lock_row.append(&auto_lock_label);
-    auto_lock_row.append(&auto_lock_spin);
+    let dialog_for_header = dialog.clone();
+    let close_btn = gtk::Button::new();
+    close_btn.add_css_class("traffic-btn");
+    close_btn.add_css_class("traffic-close");
+    close_btn.connect_clicked(move |_| dialog_for_header.close());
     
-    let clipboard_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    let clipboard_label = Label::new(Some("Clipboard clear (sec, 0=off)"));
-    clipboard_label.set_hexpand(true);
-    clipboard_label.set_halign(gtk::Align::Start);
-    let clipboard_spin = gtk::SpinButton::with_range(0.0, 300.0, 5.0);
-    clipboard_spin.set_value(settings.clipboard_timeout as f64);
-    clipboard_row.append(&clipboard_label);
-    clipboard_row.append(&clipboard_spin);
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    btn_box.set_margin_start(4);
+    btn_box.append(&close_btn);
+    header.pack_start(&btn_box);
     
-    security_group.append(&security_title);
-    security_group.append(&auto_lock_row);
-    security_group.append(&clipboard_row);
+    let header_title = Label::new(Some("Export"));
+    header_title.add_css_class("headerbar-title");
+    header.set_title_widget(Some(&header_title));
+    
+    dialog.set_titlebar(Some(&header));
 
-    // Storage group
-    let storage_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
-    storage_group.add_css_class("preferences-group");
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    vbox.set_margin_top(18);
+    vbox.set_margin_bottom(18);
+    vbox.set_margin_start(18);
+    vbox.set_margin_end(18);
+
+    // Build the scope choices: everything, the open note, each folder and each tag
+    let (folders, tags) = {
+        let manager = manager_rc.lock().unwrap();
+        (manager.get_folders(), manager.get_tags())
+    };
+    let mut scopes = vec![("All notes".to_string(), ExportScope::All)];
+    if let Some(id) = active_note_id {
+        scopes.push(("Current note".to_string(), ExportScope::Notes(vec![id])));
+    }
+    for folder in folders {
+        scopes.push((format!("Folder: {}", folder), ExportScope::Folder(folder)));
+    }
+    for tag in tags {
+        scopes.push((format!("Tag: #{}", tag), ExportScope::Tag(tag)));
+    }
+
+    let scope_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let scope_label = Label::new(Some("Notes"));
+    scope_label.set_hexpand(true);
+    scope_label.set_halign(gtk::Align::Start);
+    let scope_names: Vec<&str> = scopes.iter().map(|(name, _)| name.as_str()).collect();
+    let scope_dropdown = gtk::DropDown::from_strings(&scope_names);
+    scope_row.append(&scope_label);
+    scope_row.append(&scope_dropdown);
+
+    // Either a passphrase, or age public keys so nothing secret has to be exchanged
+    let method_row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    let method_label = Label::new(Some("Encrypt with"));
+    method_label.set_hexpand(true);
+    method_label.set_halign(gtk::Align::Start);
+    let method_dropdown = gtk::DropDown::from_strings(&["Passphrase", "Public keys (age)"]);
+    method_row.append(&method_label);
+    method_row.append(&method_dropdown);
+
+    let passphrase_box = gtk::Box::new(gtk::Orientation::Vertical, 12);
+
+    let label = Label::new(Some("Passphrase for the export file:"));
+    label.set_halign(gtk::Align::Start);
+
+    let passphrase_entry = gtk::PasswordEntry::new();
+    passphrase_entry.set_placeholder_text(Some("Passphrase"));
+    passphrase_entry.set_show_peek_icon(true);
+
+    let confirm_entry = gtk::PasswordEntry::new();
+    confirm_entry.set_placeholder_text(Some("Confirm Passphrase"));
+    confirm_entry.set_show_peek_icon(true);
+
+    passphrase_box.append(&label);
+    passphrase_box.append(&passphrase_entry);
+    passphrase_box.append(&confirm_entry);
+
+    let recipients_box = gtk::Box::new(gtk::Orientation::Vertical, 12);
+    recipients_box.set_visible(false);
+
+    let recipients_label = Label::new(Some("Recipient public keys (age1..., one per line):"));
+    recipients_label.set_halign(gtk::Align::Start);
+
+    let recipients_view = gtk::TextView::new();
+    recipients_view.set_wrap_mode(gtk::WrapMode::Char);
+    recipients_view.add_css_class("content-view");
+    let recipients_scroll = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .min_content_height(80)
+        .child(&recipients_view)
+        .build();
+
+    let armor_check = gtk::CheckButton::with_label("ASCII armor (for pasting into email or chat)");
+
+    recipients_box.append(&recipients_label);
+    recipients_box.append(&recipients_scroll);
+    recipients_box.append(&armor_check);
+
+    let passphrase_box_clone = passphrase_box.clone();
+    let recipients_box_clone = recipients_box.clone();
+    method_dropdown.connect_selected_notify(move |dropdown| {
+        let use_recipients = dropdown.selected() == 1;
+        passphrase_box_clone.set_visible(!use_recipients);
+        recipients_box_clone.set_visible(use_recipients);
+    });
+
+    let export_status = Rc::new(Label::new(None));
+    export_status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
     
-    let storage_title = Label::new(Some("STORAGE"));
-    storage_title.add_css_class("preferences-title");
-    storage_title.set_halign(gtk::Align::Start);
+    let export_button = gtk::Button::with_label("Export...");
+    export_button.add_css_class("action-button");
+
+    button_box.append(&cancel_button);
+    button_box.append(&export_button);
+
+    vbox.append(&scope_row);
+    vbox.append(&method_row);
+    vbox.append(&passphrase_box);
+    vbox.append(&recipients_box);
+    vbox.append(export_status.as_ref());
+    vbox.append(&button_box);
+
+    dialog.set_child(Some(&vbox));
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| { dialog_clone.close(); });
+
+    let dialog_clone = dialog.clone();
+    let parent = parent.clone();
+    let passphrase_clone = passphrase_entry.clone();
+    export_button.connect_clicked(move |_| {
+        let use_recipients = method_dropdown.selected() == 1;
+        let passphrase = passphrase_clone.text().to_string();
+        let buffer = recipients_view.buffer();
+        let recipient_keys = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false).to_string();
+        let armored = armor_check.is_active();
+
+        if use_recipients {
+            if let Err(e) = core::recipients::parse_recipients(&recipient_keys) {
+                export_status.set_markup(&format!("<span foreground='#a06060'>{}</span>", e));
+                return;
+            }
+        } else {
+            let confirm = confirm_entry.text().to_string();
+            if passphrase.is_empty() {
+                export_status.set_markup("<span foreground='#a06060'>Passphrase required</span>");
+                return;
+            }
+            if passphrase != confirm {
+                export_status.set_markup("<span foreground='#a06060'>Passphrases don't match</span>");
+                return;
+            }
+        }
+        let scope = scopes.get(scope_dropdown.selected() as usize)
+            .map(|(_, scope)| scope.clone())
+            .unwrap_or(ExportScope::All);
+        dialog_clone.close();
+
+        let file_chooser = gtk::FileChooserDialog::new(
+            Some("Export Notes"), 
+            Some(&parent), 
+            gtk::FileChooserAction::Save,
+            &[("Cancel", gtk::ResponseType::Cancel), ("Export", gtk::ResponseType::Accept)],
+        );
+        file_chooser.set_current_name(if use_recipients { "notes_export.age" } else { "notes_export.dat" });
+        
+        let manager_clone = manager_rc.clone();
+        let status_clone = status_label.clone();
+        
+        file_chooser.connect_response(move |dialog, response| {
+            if response == gtk::ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                    let manager_for_task = manager_clone.clone();
+                    let status_for_ui = status_clone.clone();
+                    let path_clone = path.clone();
+                    let passphrase = core::data::MasterPassword::from(passphrase.as_str());
+                    let recipient_keys = recipient_keys.clone();
+                    let scope = scope.clone();
+                    
+                    status_clone.set_text("Exporting...");
+                    
+                    let (sender, receiver) = async_channel::unbounded();
+                    let runtime = TOKIO_RUNTIME.get().unwrap();
+                    
+                    glib::spawn_future_local(async move {
+                        let _guard = runtime.enter();
+                        let result = tokio::task::spawn_blocking(move || {
+                            let manager = manager_for_task.lock().unwrap();
+                            if use_recipients {
+                                manager.export_to_recipients(&path_clone, &recipient_keys, &scope, armored)
+                            } else {
+                                manager.export_encrypted(&path_clone, passphrase, &scope)
+                            }
+                        }).await;
+                        let _ = sender.send((result, path)).await;
+                    });
+                    
+                    glib::spawn_future_local(async move {
+                        if let Ok((result, path)) = receiver.recv().await {
+                            match result {
+                                Ok(Ok(metadata)) => status_for_ui.set_text(&format!("Exported {} notes: {}", metadata.note_count, path.display())),
+                                Ok(Err(e)) => status_for_ui.set_text(&format!("Error: {}", e)),
+                                Err(e) => status_for_ui.set_text(&format!("Error: {}", e)),
+                            }
+                        }
+                    });
+                }
+            }
+            dialog.close();
+        });
+        file_chooser.show();
+    });
+
+    dialog.present();
+    passphrase_entry.grab_focus();
+}
+
+// Lets the user pick what kind of export they are importing before choosing the file
+fn show_import_source_dialog<F>(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    refresh_list: F,
+) where F: Fn() + 'static + Clone {
+    let dialog = gtk::Window::builder()
+        .title("Import")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(320)
+        .build();
     
-    let path_label = Label::new(Some("Database location:"));
-    path_label.set_halign(gtk::Align::Start);
+    // Custom header
+    let header = gtk::HeaderBar::new();
+    header.set_show_title_buttons(false);
+    header.add_css_class("custom-headerbar");
     
-    let path_entry = gtk::Entry::new();
-    path_entry.set_text(&db_path);
-    path_entry.set_hexpand(true);
+    let dialog_for_header = dialog.clone();
+    let close_btn = gtk::Button::new();
+    close_btn.add_css_class("traffic-btn");
+    close_btn.add_css_class("traffic-close");
+    close_btn.connect_clicked(move |_| dialog_for_header.close());
     
-    storage_group.append(&storage_title);
-    storage_group.append(&path_label);
-    storage_group.append(&path_entry);
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    btn_box.set_margin_start(4);
+    btn_box.append(&close_btn);
+    header.pack_start(&btn_box);
+    
+    let header_title = Label::new(Some("Import"));
+    header_title.add_css_class("headerbar-title");
+    header.set_title_widget(Some(&header_title));
+    
+    dialog.set_titlebar(Some(&header));
 
-    // Password group
-    let password_group = gtk::Box::new(gtk::Orientation::Vertical, 8);
-    password_group.add_css_class("preferences-group");
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 8);
+    vbox.set_margin_top(18);
+    vbox.set_margin_bottom(18);
+    vbox.set_margin_start(18);
+    vbox.set_margin_end(18);
+
+    let label = Label::new(Some("Import from:"));
+    label.set_halign(gtk::Align::Start);
+    vbox.append(&label);
+
+    // (label, is folder, constructor) - None is the native encrypted backup
+    type SourceCtor = fn(std::path::PathBuf) -> ImportSource;
+    let sources: [(&str, bool, Option<SourceCtor>); 6] = [
+        ("Notas backup (.dat)", false, None),
+        ("Markdown / text folder", true, Some(ImportSource::MarkdownFolder)),
+        ("Standard Notes backup", false, Some(ImportSource::StandardNotes)),
+        ("Simplenote export (notes.json)", false, Some(ImportSource::Simplenote)),
+        ("Google Keep Takeout folder", true, Some(ImportSource::GoogleKeep)),
+        ("Joplin raw export folder", true, Some(ImportSource::Joplin)),
+    ];
+
+    for (name, is_folder, ctor) in sources {
+        let button = gtk::Button::with_label(name);
+        button.add_css_class("secondary-button");
+
+        let parent = parent.clone();
+        let dialog_clone = dialog.clone();
+        let manager_rc = manager_rc.clone();
+        let status_label = status_label.clone();
+        let refresh_list = refresh_list.clone();
+        button.connect_clicked(move |_| {
+            dialog_clone.close();
+
+            let action = if is_folder {
+                gtk::FileChooserAction::SelectFolder
+            } else {
+                gtk::FileChooserAction::Open
+            };
+            let file_chooser = gtk::FileChooserDialog::new(
+                Some("Import Notes"), 
+                Some(&parent), 
+                action,
+                &[("Cancel", gtk::ResponseType::Cancel), ("Import", gtk::ResponseType::Accept)],
+            );
+
+            let parent = parent.clone();
+            let manager_clone = manager_rc.clone();
+            let status_clone = status_label.clone();
+            let refresh = refresh_list.clone();
+            file_chooser.connect_response(move |dialog, response| {
+                if response == gtk::ResponseType::Accept {
+                    if let Some(path) = dialog.file().and_then(|f| f.path()) {
+                        match ctor {
+                            // Archives encrypted to our public key open with the vault identity
+                            None if CoreManager::is_recipient_file(&path) => run_import(
+                                &parent,
+                                manager_clone.clone(),
+                                status_clone.clone(),
+                                refresh.clone(),
+                                "archive",
+                                move |manager| manager.preview_recipient_import(&path),
+                            ),
+                            None => show_import_password_dialog(
+                                &parent, 
+                                manager_clone.clone(), 
+                                status_clone.clone(), 
+                                refresh.clone(), 
+                                path
+                            ),
+                            Some(ctor) => {
+                                let source = ctor(path);
+                                run_import(
+                                    &parent,
+                                    manager_clone.clone(),
+                                    status_clone.clone(),
+                                    refresh.clone(),
+                                    source.display_name(),
+                                    move |manager| manager.preview_source_import(&source),
+                                )
+                            },
+                        }
+                    }
+                }
+                dialog.close();
+            });
+            file_chooser.show();
+        });
+        vbox.append(&button);
+    }
+
+    let shared_button = gtk::Button::with_label("Shared note (file or text)");
+    shared_button.add_css_class("secondary-button");
+    let parent_clone = parent.clone();
+    let dialog_clone = dialog.clone();
+    shared_button.connect_clicked(move |_| {
+        dialog_clone.close();
+        show_open_shared_note_dialog(&parent_clone, manager_rc.clone(), status_label.clone(), refresh_list.clone());
+    });
+    vbox.append(&shared_button);
+
+    dialog.set_child(Some(&vbox));
+    dialog.present();
+}
+
+// Builds an import preview off the main thread, then hands it to the preview dialog
+fn run_import<F, P>(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    refresh_list: F,
+    description: &str,
+    preview_fn: P,
+) where
+    F: Fn() + 'static + Clone,
+    P: FnOnce(&CoreManager) -> anyhow::Result<ImportPreview> + Send + 'static,
+{
+    status_label.set_text(&format!("Reading {}...", description));
+
+    let (sender, receiver) = async_channel::unbounded();
+    let runtime = TOKIO_RUNTIME.get().unwrap();
     
-    let password_title = Label::new(Some("CHANGE PASSWORD"));
-    password_title.add_css_class("preferences-title");
-    password_title.set_halign(gtk::Align::Start);
+    let manager_for_task = manager_rc.clone();
+    glib::spawn_future_local(async move {
+        let _guard = runtime.enter();
+        let result = tokio::task::spawn_blocking(move || {
+            preview_fn(&manager_for_task.lock().unwrap())
+        }).await;
+        let _ = sender.send(result).await;
+    });
     
-    let current_password_entry = gtk::PasswordEntry::new();
-    current_password_entry.set_placeholder_text(Some("Current Password"));
-    current_password_entry.set_show_peek_icon(true);
+    let parent = parent.clone();
+    glib::spawn_future_local(async move {
+        if let Ok(result) = receiver.recv().await {
+            match result {
+                Ok(Ok(preview)) => {
+                    status_label.set_text("");
+                    show_import_preview_dialog(&parent, manager_rc, status_label, refresh_list, preview);
+                },
+                Ok(Err(e)) => status_label.set_text(&format!("Error: {}", e)),
+                Err(e) => status_label.set_text(&format!("Error: {}", e)),
+            }
+        }
+    });
+}
+
+// Lists incoming notes with their status and lets the user choose what to do with each
+fn show_import_preview_dialog<F>(
+    parent: &ApplicationWindow,
+    manager_rc: Arc<Mutex<CoreManager>>,
+    status_label: Arc<Label>,
+    refresh_list: F,
+    preview: ImportPreview,
+) where F: Fn() + 'static + Clone {
+    let dialog = gtk::Window::builder()
+        .title("Import Preview")
+        .modal(true)
+        .transient_for(parent)
+        .default_width(560)
+        .default_height(480)
+        .build();
     
-    let new_password_entry = gtk::PasswordEntry::new();
-    new_password_entry.set_placeholder_text(Some("New Password"));
-    new_password_entry.set_show_peek_icon(true);
+    // Custom header
+    let header = gtk::HeaderBar::new();
+    header.set_show_title_buttons(false);
+    header.add_css_class("custom-headerbar");
     
-    let confirm_password_entry = gtk::PasswordEntry::new();
-    confirm_password_entry.set_placeholder_text(Some("Confirm New Password"));
-    confirm_password_entry.set_show_peek_icon(true);
+    let dialog_for_header = dialog.clone();
+    let close_btn = gtk::Button::new();
+    close_btn.add_css_class("traffic-btn");
+    close_btn.add_css_class("traffic-close");
+    close_btn.connect_clicked(move |_| dialog_for_header.close());
     
-    let change_password_button = gtk::Button::with_label("Change Password");
-    change_password_button.add_css_class("secondary-button");
+    let btn_box = gtk::Box::new(gtk::Orientation::Horizontal, 2);
+    btn_box.set_margin_start(4);
+    btn_box.append(&close_btn);
+    header.pack_start(&btn_box);
     
-    let password_status = Rc::new(Label::new(None));
-    password_status.set_halign(gtk::Align::Start);
+    let header_title = Label::new(Some("Import Preview"));
+    header_title.add_css_class("headerbar-title");
+    header.set_title_widget(Some(&header_title));
     
-    password_group.append(&password_title);
-    password_group.append(&current_password_entry);
-    password_group.append(&new_password_entry);
-    password_group.append(&confirm_password_entry);
-    password_group.append(&change_password_button);
-    password_group.append(password_status.as_ref());
+    dialog.set_titlebar(Some(&header));
 
-    let manager_clone = manager_rc.clone();
-    let current_clone = current_password_entry.clone();
-    let new_clone = new_password_entry.clone();
-    let confirm_clone = confirm_password_entry.clone();
-    let password_status_clone = password_status.clone();
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 10);
+    vbox.set_margin_top(18);
+    vbox.set_margin_bottom(18);
+    vbox.set_margin_start(18);
+    vbox.set_margin_end(18);
+
+    // Archive metadata is only readable now that the file has been decrypted
+    if let Some(ref metadata) = preview.metadata {
+        let info = Label::new(Some(&format!(
+            "Exported {} · {} notes · format v{} · Notas {}",
+            metadata.exported_at.format("%b %d, %Y %H:%M"),
+            metadata.note_count,
+            metadata.format_version,
+            metadata.app_version,
+        )));
+        info.set_halign(gtk::Align::Start);
+        info.set_wrap(true);
+        info.add_css_class("status-text");
+        vbox.append(&info);
+    }
+
+    let count = |status: ImportStatus| preview.entries.iter().filter(|e| e.status == status).count();
+    let summary = Label::new(Some(&format!(
+        "{} notes: {} new, {} identical, {} newer, {} older, {} conflicting{}",
+        preview.entries.len(),
+        count(ImportStatus::New),
+        count(ImportStatus::Identical),
+        count(ImportStatus::Newer),
+        count(ImportStatus::Older),
+        count(ImportStatus::Conflicting),
+        if preview.skipped > 0 { format!(", {} unreadable", preview.skipped) } else { String::new() },
+    )));
+    summary.set_halign(gtk::Align::Start);
+    summary.set_wrap(true);
+    summary.add_css_class("status-text");
+
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::None);
+    list_box.add_css_class("note-list");
+
+    let action_names: Vec<&str> = ImportAction::all().iter().map(|a| a.display_name()).collect();
+    let mut dropdowns = Vec::with_capacity(preview.entries.len());
+
+    for entry in &preview.entries {
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+
+        let status = Label::new(Some(entry.status.display_name()));
+        status.add_css_class("note-date");
+        status.set_width_chars(9);
+        status.set_xalign(0.0);
+
+        let text_box = gtk::Box::new(gtk::Orientation::Vertical, 1);
+        text_box.set_hexpand(true);
+        let title = Label::new(Some(&entry.note.title));
+        title.add_css_class("note-title");
+        title.set_halign(gtk::Align::Start);
+        title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        let date = Label::new(Some(&entry.note.updated_at.format("%b %d, %Y %H:%M").to_string()));
+        date.add_css_class("note-date");
+        date.set_halign(gtk::Align::Start);
+        text_box.append(&title);
+        text_box.append(&date);
+
+        let dropdown = gtk::DropDown::from_strings(&action_names);
+        let default_action = entry.status.default_action();
+        let selected = ImportAction::all().iter().position(|a| *a == default_action).unwrap_or(0);
+        dropdown.set_selected(selected as u32);
+        dropdown.set_valign(gtk::Align::Center);
+
+        row_box.append(&status);
+        row_box.append(&text_box);
+        row_box.append(&dropdown);
+
+        let row = ListBoxRow::new();
+        row.set_child(Some(&row_box));
+        list_box.append(&row);
+        dropdowns.push(dropdown);
+    }
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .child(&list_box)
+        .vexpand(true)
+        .build();
+
+    let import_status = Rc::new(Label::new(None));
+    import_status.set_halign(gtk::Align::Start);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+    button_box.set_halign(gtk::Align::End);
+
+    let cancel_button = gtk::Button::with_label("Cancel");
+    cancel_button.add_css_class("secondary-button");
     
-    change_password_button.connect_clicked(move |btn| {
-        let current = current_clone.text().to_string();
-        let new_pass = new_clone.text().to_string();
-        let confirm = confirm_clone.text().to_string();
-        
-        if current.is_empty() || new_pass.is_empty() || confirm.is_empty() {
-            password_status_clone.set_markup("<span foreground='#a06060'>All fields required</span>");
-            return;
-        }
-        if new_pass != confirm {
-            password_status_clone.set_markup("<span foreground='#a06060'>Passwords don't match</span>");
-            return;
-        }
-        if new_pass.len() < 8 {
-            password_status_clone.set_markup("<span foreground='#a06060'>Min 8 characters</span>");
+    let import_button = gtk::Button::with_label("Import");
+    import_button.add_css_class("action-button");
+
+    button_box.append(&cancel_button);
+    button_box.append(&import_button);
+
+    vbox.append(&summary);
+    vbox.append(&scrolled);
+    vbox.append(import_status.as_ref());
+    vbox.append(&button_box);
+
+    dialog.set_child(Some(&vbox));
+
+    let dialog_clone = dialog.clone();
+    cancel_button.connect_clicked(move |_| { dialog_clone.close(); });
+
+    // The preview holds decrypted notes; it is consumed by the merge or dropped
+    // (and zeroized) with the dialog.
+    let preview = Rc::new(RefCell::new(Some(preview)));
+    let dialog_clone = dialog.clone();
+    import_button.connect_clicked(move |btn| {
+        let Some(preview) = preview.borrow_mut().take() else {
             return;
-        }
-        
+        };
+        let actions: Vec<ImportAction> = dropdowns.iter()
+            .map(|d| ImportAction::all().get(d.selected() as usize).copied().unwrap_or(ImportAction::Skip))
+            .collect();
+
         btn.set_sensitive(false);
-        password_status_clone.set_text("Changing...");
-        
-        let manager_for_task = manager_clone.clone();
-        let password_status_for_ui = password_status_clone.clone();
-        let current_entry = current_clone.clone();
-        let new_entry = new_clone.clone();
-        let confirm_entry = confirm_clone.clone();
-        let btn_ui = btn.clone();
-        
+        import_status.set_text("Importing...");
+
+        let manager_clone = manager_rc.clone();
+        let status_clone = status_label.clone();
+        let dialog_close = dialog_clone.clone();
+        let import_status2 = import_status.clone();
+        let refresh = refresh_list.clone();
+
         let (sender, receiver) = async_channel::unbounded();
         let runtime = TOKIO_RUNTIME.get().unwrap();
         
         glib::spawn_future_local(async move {
             let _guard = runtime.enter();
             let result = tokio::task::spawn_blocking(move || {
-                let old = core::data::MasterPassword::from(current.as_str());
-                let new = core::data::MasterPassword::from(new_pass.as_str());
-                manager_for_task.lock().unwrap().change_password(old, new)
+                manager_clone.lock().unwrap().apply_import(preview, &actions)
             }).await;
             let _ = sender.send(result).await;
         });
@@ -1933,97 +5423,18 @@ fn show_preferences_dialog(
         glib::spawn_future_local(async move {
             if let Ok(result) = receiver.recv().await {
                 match result {
-                    Ok(Ok(_)) => {
-                        password_status_for_ui.set_markup("<span foreground='#60a060'>Password changed</span>");
-                        current_entry.set_text("");
-                        new_entry.set_text("");
-                        confirm_entry.set_text("");
+                    Ok(Ok(summary)) => { 
+                        status_clone.set_text(&format!("Import: {}", summary)); 
+                        refresh(); 
+                        dialog_close.close(); 
                     },
-                    Ok(Err(e)) => password_status_for_ui.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
-                    Err(e) => password_status_for_ui.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    Ok(Err(e)) => import_status2.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
+                    Err(e) => import_status2.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
                 }
-                btn_ui.set_sensitive(true);
             }
         });
     });
 
-    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 8);
-    button_box.set_halign(gtk::Align::End);
-    button_box.set_margin_top(8);
-    
-    let cancel_button = gtk::Button::with_label("Cancel");
-    cancel_button.add_css_class("secondary-button");
-    
-    let save_button = gtk::Button::with_label("Save");
-    save_button.add_css_class("action-button");
-    
-    button_box.append(&cancel_button);
-    button_box.append(&save_button);
-
-    main_box.append(&editor_group);
-    main_box.append(&security_group);
-    main_box.append(&storage_group);
-    main_box.append(&password_group);
-    main_box.append(&button_box);
-
-    scrolled.set_child(Some(&main_box));
-    dialog.set_child(Some(&scrolled));
-
-    let dialog_clone = dialog.clone();
-    cancel_button.connect_clicked(move |_| { dialog_clone.close(); });
-
-    let dialog_clone = dialog.clone();
-    let manager_clone = manager_rc.clone();
-    let path_entry_clone = path_entry.clone();
-    let font_dropdown_clone = font_dropdown.clone();
-    let size_spin_clone = size_spin.clone();
-    let title_switch_clone = title_switch.clone();
-    let title_entry_clone = title_entry_widget.clone();
-    let title_toggle_clone = title_toggle_widget.clone();
-    
-    save_button.connect_clicked(move |_| {
-        let current = manager_clone.lock().unwrap().get_settings().clone();
-        let default_path = manager_clone.lock().unwrap().get_data_path().display().to_string();
-        let path_str = path_entry_clone.text().to_string();
-        
-        let selected_font = EditorFont::from_index(font_dropdown_clone.selected());
-        let font_size = size_spin_clone.value() as u32;
-        let show_title = title_switch_clone.is_active();
-        
-        // Update thread-local settings
-        EDITOR_FONT.with(|f| *f.borrow_mut() = selected_font.clone());
-        EDITOR_FONT_SIZE.with(|s| *s.borrow_mut() = font_size);
-        SHOW_NOTE_TITLE.with(|s| *s.borrow_mut() = show_title);
-        
-        // Update title visibility immediately and keep status bar toggle in sync
-        title_entry_clone.set_visible(show_title);
-        title_toggle_clone.set_active(show_title);
-        
-        let new_settings = AppSettings {
-            auto_lock_timeout: auto_lock_spin.value() as u64,
-            clipboard_timeout: clipboard_spin.value() as u64,
-            custom_db_path: if path_str != default_path { 
-                Some(std::path::PathBuf::from(path_str)) 
-            } else { 
-                None 
-            },
-            argon2_params: current.argon2_params,
-            theme: CURRENT_THEME.with(|t| t.borrow().clone()),
-            editor_font: selected_font,
-            editor_font_size: font_size,
-            show_note_title: show_title,
-        };
-        
-        match manager_clone.lock().unwrap().update_settings(new_settings) {
-            Ok(_) => { 
-                status_label.set_text("Settings saved");
-                reload_css();
-                dialog_clone.close(); 
-            },
-            Err(e) => status_label.set_text(&format!("Error: {}", e)),
-        }
-    });
-
     dialog.present();
 }
 
@@ -2105,6 +5516,7 @@ fn show_import_password_dialog<F>(
     let dialog_clone = dialog.clone();
     let import_status_clone = import_status.clone();
     let password_entry_clone = password_entry.clone();
+    let parent_clone = parent.clone();
     
     import_button.connect_clicked(move |_| {
         let password = password_entry_clone.text().to_string();
@@ -2114,13 +5526,15 @@ fn show_import_password_dialog<F>(
         }
 
         let manager_clone = manager_rc.clone();
+        let manager_for_preview = manager_rc.clone();
         let status_clone = status_label.clone();
         let path_clone = import_path.clone();
         let dialog_close = dialog_clone.clone();
         let import_status2 = import_status_clone.clone();
         let refresh = refresh_list.clone();
+        let parent = parent_clone.clone();
         
-        import_status_clone.set_text("Importing...");
+        import_status_clone.set_text("Reading...");
         
         let (sender, receiver) = async_channel::unbounded();
         let runtime = TOKIO_RUNTIME.get().unwrap();
@@ -2129,7 +5543,7 @@ fn show_import_password_dialog<F>(
             let _guard = runtime.enter();
             let result = tokio::task::spawn_blocking(move || {
                 let pw = core::data::MasterPassword::from(password.as_str());
-                manager_clone.lock().unwrap().import_encrypted(&path_clone, pw)
+                manager_clone.lock().unwrap().preview_encrypted_import(&path_clone, pw)
             }).await;
             let _ = sender.send(result).await;
         });
@@ -2137,10 +5551,9 @@ fn show_import_password_dialog<F>(
         glib::spawn_future_local(async move {
             if let Ok(result) = receiver.recv().await {
                 match result {
-                    Ok(Ok(_)) => { 
-                        status_clone.set_text("Imported"); 
-                        refresh(); 
+                    Ok(Ok(preview)) => { 
                         dialog_close.close(); 
+                        show_import_preview_dialog(&parent, manager_for_preview, status_clone, refresh, preview);
                     },
                     Ok(Err(e)) => import_status2.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
                     Err(e) => import_status2.set_markup(&format!("<span foreground='#a06060'>{}</span>", e)),
@@ -2152,3 +5565,263 @@ fn show_import_password_dialog<F>(
     dialog.present();
     password_entry.grab_focus();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data::MasterPassword;
+
+    // Text still shown under `widget`, for checking that `clear_widget_text` missed nothing
+    fn remaining_widget_text(widget: &gtk::Widget) -> Vec<String> {
+        let mut remaining = Vec::new();
+        if let Some(editable) = widget.downcast_ref::<gtk::Editable>() {
+            remaining.push(editable.text().to_string());
+        } else if let Some(text_view) = widget.downcast_ref::<gtk::TextView>() {
+            let buffer = text_view.buffer();
+            remaining.push(buffer.text(&buffer.start_iter(), &buffer.end_iter(), true).to_string());
+            if buffer.can_undo() {
+                remaining.push(format!("{}: undo history", widget.type_().name()));
+            }
+        } else if let Some(label) = widget.downcast_ref::<Label>() {
+            remaining.push(label.text().to_string());
+        } else if let Some(list_box) = widget.downcast_ref::<gtk::ListBox>() {
+            if list_box.row_at_index(0).is_some() {
+                remaining.push(format!("{}: rows", widget.type_().name()));
+            }
+        }
+        remaining.retain(|text| !text.is_empty());
+
+        let mut child = widget.first_child();
+        while let Some(current) = child {
+            remaining.extend(remaining_widget_text(&current));
+            child = current.next_sibling();
+        }
+        remaining
+    }
+
+    // A private dbus-daemon standing in for the system bus
+    struct StandInBus {
+        daemon: std::process::Child,
+        address: String,
+    }
+
+    impl StandInBus {
+        // None where dbus-daemon isn't installed
+        fn start() -> Option<Self> {
+            use std::io::BufRead;
+            let mut daemon = std::process::Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            std::io::BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            Some(Self { daemon, address: address.trim().to_string() })
+        }
+
+        fn connect(&self) -> gio::DBusConnection {
+            gio::DBusConnection::for_address_sync(
+                &self.address,
+                gio::DBusConnectionFlags::AUTHENTICATION_CLIENT | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+                gio::Cancellable::NONE,
+            ).unwrap()
+        }
+    }
+
+    impl Drop for StandInBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    const LOGIND_XML: &str = r#"<node>
+        <interface name="org.freedesktop.login1.Manager">
+            <method name="GetSession">
+                <arg name="session_id" type="s" direction="in"/>
+                <arg name="object_path" type="o" direction="out"/>
+            </method>
+        </interface>
+    </node>"#;
+
+    // Takes logind's name on the bus and answers GetSession with `session`, or with an
+    // error when it's None. Returns how many times GetSession was called.
+    fn stand_in_logind(bus: &gio::DBusConnection, session: Option<&'static str>) -> Arc<std::sync::atomic::AtomicUsize> {
+        bus.call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+            Some(&("org.freedesktop.login1", 0u32).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        ).unwrap();
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let node = gio::DBusNodeInfo::for_xml(LOGIND_XML).unwrap();
+        bus.register_object(
+            "/org/freedesktop/login1",
+            &node.lookup_interface("org.freedesktop.login1.Manager").unwrap(),
+            glib::clone!(@strong calls => move |_, _, _, _, _, _, invocation| {
+                calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                match session {
+                    Some(path) => {
+                        let path = glib::variant::ObjectPath::try_from(path).unwrap();
+                        invocation.return_value(Some(&(path,).to_variant()));
+                    },
+                    None => invocation.return_dbus_error("org.freedesktop.login1.NoSessionForPID", "No session"),
+                }
+            }),
+            |_, _, _, _, _| unreachable!(),
+            |_, _, _, _, _, _| false,
+        ).unwrap();
+        calls
+    }
+
+    fn emit(bus: &gio::DBusConnection, path: &str, interface: &str, member: &str, parameters: Option<glib::Variant>) {
+        bus.emit_signal(None, path, interface, member, parameters.as_ref()).unwrap();
+        bus.flush_sync(gio::Cancellable::NONE).unwrap();
+    }
+
+    // Waits for the bus to have handled everything `connection` sent, match rules included
+    fn round_trip(connection: &gio::DBusConnection) {
+        connection.call_sync(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "GetId",
+            None,
+            None,
+            gio::DBusCallFlags::NONE,
+            1000,
+            gio::Cancellable::NONE,
+        ).unwrap();
+    }
+
+    fn run_until(context: &glib::MainContext, done: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() && Instant::now() < deadline {
+            while context.iteration(false) {}
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Subscribes through a stand-in logind and returns the signals that reach the
+    // handler, once logind has had `emit_signals` sent. None (and the test is skipped)
+    // without dbus-daemon.
+    fn login_signals_received(session: Option<&'static str>, emit_signals: impl Fn(&gio::DBusConnection, &gio::DBusConnection)) -> Option<(Vec<String>, usize)> {
+        let context = glib::MainContext::new();
+        context.with_thread_default(|| {
+            let Some(bus) = StandInBus::start() else {
+                eprintln!("Skipped: dbus-daemon is not installed");
+                return None;
+            };
+            let logind = bus.connect();
+            let calls = stand_in_logind(&logind, session);
+            let watcher = bus.connect();
+
+            let received = Rc::new(RefCell::new(Vec::new()));
+            let subscriptions: SignalSubscriptions = Rc::new(RefCell::new(Vec::new()));
+            let on_signal: SessionSignalHandler = Rc::new(glib::clone!(@strong received => move |_, member, _| {
+                received.borrow_mut().push(member.to_string());
+            }));
+            watch_login_signals(&watcher, on_signal, &subscriptions);
+            run_until(&context, || calls.load(std::sync::atomic::Ordering::SeqCst) == 1);
+            // Let the reply to GetSession arrive, then make sure the bus has the match
+            // rule for any Lock subscription it led to
+            let settled = Instant::now() + Duration::from_millis(200);
+            run_until(&context, || Instant::now() >= settled);
+            round_trip(&watcher);
+
+            emit_signals(&logind, &bus.connect());
+            // Signals from one sender arrive in order, and each test ends on PrepareForSleep
+            run_until(&context, || received.borrow().last().is_some_and(|member| member == "PrepareForSleep"));
+            let count = subscriptions.borrow().len();
+            let received = received.take();
+            Some((received, count))
+        }).unwrap()
+    }
+
+    #[test]
+    fn lock_signal_is_followed_for_this_session_only() {
+        let Some((received, subscriptions)) = login_signals_received(Some("/org/freedesktop/login1/session/_31"), |logind, impostor| {
+            emit(impostor, "/org/freedesktop/login1/session/_31", "org.freedesktop.login1.Session", "Lock", None);
+            emit(logind, "/org/freedesktop/login1/session/_32", "org.freedesktop.login1.Session", "Lock", None);
+            emit(logind, "/org/freedesktop/login1/session/_31", "org.freedesktop.login1.Session", "Lock", None);
+            emit(logind, "/org/freedesktop/login1", "org.freedesktop.login1.Manager", "PrepareForSleep", Some((true,).to_variant()));
+        }) else { return };
+        assert_eq!(subscriptions, 2);
+        assert_eq!(received, ["Lock", "PrepareForSleep"]);
+    }
+
+    #[test]
+    fn lock_signal_is_ignored_when_the_session_is_unknown() {
+        let Some((received, subscriptions)) = login_signals_received(None, |logind, _| {
+            emit(logind, "/org/freedesktop/login1/session/_31", "org.freedesktop.login1.Session", "Lock", None);
+            emit(logind, "/org/freedesktop/login1", "org.freedesktop.login1.Manager", "PrepareForSleep", Some((true,).to_variant()));
+        }) else { return };
+        assert_eq!(subscriptions, 1);
+        assert_eq!(received, ["PrepareForSleep"]);
+    }
+
+    fn fill_widget_text(widget: &gtk::Widget) {
+        if let Some(editable) = widget.downcast_ref::<gtk::Editable>() {
+            editable.set_text("typed into an entry");
+        } else if let Some(text_view) = widget.downcast_ref::<gtk::TextView>() {
+            let buffer = text_view.buffer();
+            buffer.set_text("first draft");
+            buffer.set_text("typed into the editor");
+        }
+        let mut child = widget.first_child();
+        while let Some(current) = child {
+            fill_widget_text(&current);
+            child = current.next_sibling();
+        }
+    }
+
+    // Builds the real main window, and sets the XDG directories and CORE_MANAGER for the
+    // whole process, so it runs on its own and needs a display:
+    // `xvfb-run cargo test lock_app_leaves_no_text_in_widgets -- --ignored`
+    #[test]
+    #[ignore = "needs a display and the process to itself"]
+    fn lock_app_leaves_no_text_in_widgets() {
+        let home = std::env::temp_dir().join(format!("notas-lock-test-{}", std::process::id()));
+        std::env::set_var("XDG_DATA_HOME", home.join("data"));
+        std::env::set_var("XDG_CONFIG_HOME", home.join("config"));
+        let mut manager = CoreManager::new().unwrap();
+        manager.unlock(MasterPassword::from("correct horse battery staple 42"), UnlockFactors::default()).unwrap();
+        manager.create_note("Bank".to_string(), "PIN is 4521".to_string()).unwrap();
+        assert!(CORE_MANAGER.set(Arc::new(Mutex::new(manager))).is_ok());
+
+        let leftover = Rc::new(RefCell::new(Vec::new()));
+        let application = Application::builder()
+            .application_id("com.jegly.Notas.Tests")
+            .flags(gio::ApplicationFlags::NON_UNIQUE)
+            .build();
+        application.connect_startup(|_| {
+            adw::init().expect("Failed to initialize libadwaita");
+        });
+        // Panics can't unwind out of a GTK callback, so results are checked after run
+        application.connect_activate(glib::clone!(@strong leftover => move |app| {
+            show_main_window(app);
+            let windows = app.windows();
+            for window in &windows {
+                fill_widget_text(window.upcast_ref());
+            }
+            let filled = windows.iter().flat_map(|window| remaining_widget_text(window.upcast_ref())).count();
+            leftover.borrow_mut().push(format!("{} texts before locking", filled));
+
+            lock_app(app);
+            leftover.borrow_mut().extend(windows.iter().flat_map(|window| remaining_widget_text(window.upcast_ref())));
+            app.quit();
+        }));
+        application.run_with_args::<&str>(&[]);
+        let _ = std::fs::remove_dir_all(&home);
+
+        let leftover = leftover.take();
+        assert!(leftover.len() == 1 && leftover[0] != "0 texts before locking", "{:?}", leftover);
+        assert!(!CORE_MANAGER.get().unwrap().lock().unwrap().is_unlocked());
+    }
+}